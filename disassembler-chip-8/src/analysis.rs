@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::opcodes::{self, Dialect, Instruction};
+
+/// How control leaves an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Falls through to the next instruction.
+    Next,
+    /// Either falls through or skips the next instruction.
+    Skip,
+    /// Unconditional jump to a fixed address.
+    Jump(usize),
+    /// Subroutine call, execution resumes after it on return.
+    Call(usize),
+    /// Return from a subroutine.
+    Return,
+    /// Jump whose target depends on a register (JP V0, addr).
+    Indirect,
+    /// Execution cannot continue (unknown opcode or EXIT).
+    Halt,
+}
+
+pub fn flow(instr: &Instruction) -> Flow {
+    match *instr {
+        Instruction::JP(nnn) => Flow::Jump(nnn as usize),
+        Instruction::CALL(nnn) => Flow::Call(nnn as usize),
+        Instruction::RET => Flow::Return,
+        Instruction::JPV0(_) => Flow::Indirect,
+        Instruction::Unknown(_) | Instruction::EXIT => Flow::Halt,
+        _ if instr.is_skip() => Flow::Skip,
+        _ => Flow::Next,
+    }
+}
+
+/// A subroutine discovered by following CALL targets.
+pub struct Function {
+    /// Addresses of every instruction reachable from the entry without entering a callee.
+    pub body: BTreeSet<usize>,
+    /// Call sites inside the body as (site address, target address).
+    pub calls: Vec<(usize, usize)>,
+}
+
+/// Machine state known at an instruction, before it executes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    /// Value of I, if it is the same on every path reaching the instruction.
+    pub i: Option<u16>,
+    /// Values of V0 through VF, if they are the same on every path.
+    pub v: [Option<u8>; 16],
+}
+
+fn same<T: PartialEq + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+    if a == b { a } else { None }
+}
+
+impl State {
+    const UNKNOWN: State = State { i: None, v: [None; 16] };
+
+    fn merge(&self, other: &State) -> State {
+        State {
+            i: same(self.i, other.i),
+            v: std::array::from_fn(|r| same(self.v[r], other.v[r])),
+        }
+    }
+
+    /// Returns the state after `instr` executes. Values that depend on a quirk become unknown.
+    pub fn step(&self, instr: &Instruction) -> State {
+        let mut next = *self;
+        let v = |r: u8| self.v[r as usize];
+        // applies an 8xyN operation returning (result, flag) when both operands are known
+        let mut alu = |x: u8, y: u8, op: fn(u8, u8) -> (u8, Option<u8>)| {
+            let result = v(x).zip(v(y)).map(|(a, b)| op(a, b));
+            next.v[x as usize] = result.map(|(value, _)| value);
+            next.v[0xF] = result.and_then(|(_, flag)| flag);
+        };
+        match *instr {
+            Instruction::LDVxImm { x, imm } => next.v[x as usize] = Some(imm),
+            Instruction::ADDVxImm { x, imm } => next.v[x as usize] = v(x).map(|a| a.wrapping_add(imm)),
+            Instruction::LDVxVy { x, y } => next.v[x as usize] = v(y),
+            // VF is reset by the logic quirk on the COSMAC VIP only
+            Instruction::ORVxVy { x, y } => alu(x, y, |a, b| (a | b, None)),
+            Instruction::ANDVxVy { x, y } => alu(x, y, |a, b| (a & b, None)),
+            Instruction::XORVxVy { x, y } => alu(x, y, |a, b| (a ^ b, None)),
+            Instruction::ADDVxVy { x, y } => alu(x, y, |a, b| {
+                let (value, carry) = a.overflowing_add(b);
+                (value, Some(carry as u8))
+            }),
+            Instruction::SUBVxVy { x, y } => alu(x, y, |a, b| (a.wrapping_sub(b), Some((a >= b) as u8))),
+            Instruction::SUBNVxVy { x, y } => alu(x, y, |a, b| (b.wrapping_sub(a), Some((b >= a) as u8))),
+            // the shift quirk decides whether Vx or Vy is shifted, so only x == y is certain
+            Instruction::SHRVxVy { x, y } if x == y => alu(x, y, |a, _| (a >> 1, Some(a & 1))),
+            Instruction::SHLVxVy { x, y } if x == y => alu(x, y, |a, _| (a << 1, Some(a >> 7))),
+            Instruction::SHRVxVy { x, .. } | Instruction::SHLVxVy { x, .. } => {
+                next.v[x as usize] = None;
+                next.v[0xF] = None;
+            }
+            Instruction::RNDVxImm { x, .. }
+            | Instruction::LDVxDT(x)
+            | Instruction::LDVxK(x) => next.v[x as usize] = None,
+            Instruction::DRWVxVyn { .. } | Instruction::DRWVxVy0 { .. } => next.v[0xF] = None,
+            Instruction::LDVxI(x) | Instruction::LDVxR(x) => {
+                next.v[..=x as usize].fill(None);
+                if let Instruction::LDVxI(_) = instr {
+                    next.i = None;
+                }
+            }
+            Instruction::LDI(nnn) => next.i = Some(nnn),
+            Instruction::ADDIVx(x) => next.i = self.i.zip(v(x)).map(|(i, a)| (i + a as u16) & 0xFFF),
+            // I depends on the interpreter's font location, or is incremented by the load/store quirk
+            Instruction::LDFVx(_) | Instruction::LDHFVx(_) | Instruction::LDIVx(_) => next.i = None,
+            // the callee may change anything
+            Instruction::CALL(_) => next = State::UNKNOWN,
+            _ => {}
+        }
+        next
+    }
+}
+
+/// Result of the control-flow analysis of a ROM.
+pub struct Analysis {
+    /// Address of the first ROM byte.
+    pub start_addr: usize,
+    /// First address past the end of the ROM.
+    pub end_addr: usize,
+    /// Addresses execution starts at. The first one is the main program.
+    pub entries: Vec<usize>,
+    /// Every reachable instruction, by address.
+    pub code: BTreeMap<usize, Instruction>,
+    /// Every discovered function, by entry address. Entry points are functions too.
+    pub functions: BTreeMap<usize, Function>,
+    /// Machine state known before each reachable instruction.
+    pub states: BTreeMap<usize, State>,
+}
+
+impl Analysis {
+    pub fn in_rom(&self, addr: usize) -> bool {
+        addr >= self.start_addr && addr < self.end_addr
+    }
+
+    /// Returns true if `addr` holds a byte of a reachable instruction.
+    pub fn is_code_byte(&self, addr: usize) -> bool {
+        self.code.contains_key(&addr) || (addr > 0 && self.code.contains_key(&(addr - 1)))
+    }
+}
+
+/// Reads the big-endian opcode at `addr`, if both of its bytes are inside the ROM.
+pub fn fetch(rom: &[u8], start_addr: usize, addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(start_addr)?;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(((bytes[0] as u16) << 8) | (bytes[1] as u16))
+}
+
+/// Returns the successors of the instruction at `addr` inside its function.
+fn successors(addr: usize, instr: &Instruction) -> Vec<usize> {
+    match flow(instr) {
+        Flow::Next | Flow::Call(_) => vec![addr + 2],
+        Flow::Skip => vec![addr + 2, addr + 4],
+        Flow::Jump(target) => vec![target],
+        Flow::Return | Flow::Indirect | Flow::Halt => vec![],
+    }
+}
+
+/// Follows control flow from the entry points, discovering reachable code and functions.
+pub fn analyze(rom: &[u8], start_addr: usize, entries: &[usize], dialect: Dialect) -> Analysis {
+    let mut analysis = Analysis {
+        start_addr,
+        end_addr: start_addr + rom.len(),
+        entries: entries.to_vec(),
+        code: BTreeMap::new(),
+        functions: BTreeMap::new(),
+        states: BTreeMap::new(),
+    };
+
+    let mut pending: Vec<usize> = entries.iter().rev().copied().collect();
+    while let Some(entry) = pending.pop() {
+        if analysis.functions.contains_key(&entry) || fetch(rom, start_addr, entry).is_none() {
+            continue;
+        }
+
+        let mut function = Function { body: BTreeSet::new(), calls: Vec::new() };
+        let mut in_states: BTreeMap<usize, State> = BTreeMap::new();
+        let mut worklist = vec![(entry, State::UNKNOWN)];
+
+        while let Some((addr, state)) = worklist.pop() {
+            let Some(opcode) = fetch(rom, start_addr, addr) else {
+                continue;
+            };
+            let state = match in_states.get(&addr) {
+                Some(old) => {
+                    let merged = old.merge(&state);
+                    if merged == *old {
+                        continue;
+                    }
+                    merged
+                }
+                None => state,
+            };
+            in_states.insert(addr, state);
+
+            let instr = opcodes::decode(opcode, dialect);
+            if function.body.insert(addr) {
+                analysis.code.insert(addr, instr);
+                if let Flow::Call(target) = flow(&instr) {
+                    function.calls.push((addr, target));
+                    pending.push(target);
+                }
+            }
+
+            let next = state.step(&instr);
+            for succ in successors(addr, &instr) {
+                worklist.push((succ, next));
+            }
+        }
+
+        for (addr, state) in in_states {
+            analysis
+                .states
+                .entry(addr)
+                .and_modify(|old| *old = old.merge(&state))
+                .or_insert(state);
+        }
+        function.calls.sort();
+        analysis.functions.insert(entry, function);
+    }
+
+    analysis
+}
@@ -0,0 +1,199 @@
+use crate::analysis::{Analysis, State};
+use crate::opcodes::Instruction;
+
+/// Returns a symbolic name for an address: a subroutine, code or data label.
+pub fn label(analysis: &Analysis, addr: usize) -> String {
+    if analysis.entries.first() == Some(&addr) {
+        "main".to_string()
+    } else if analysis.functions.contains_key(&addr) {
+        format!("sub_{:03X}", addr)
+    } else if analysis.code.contains_key(&addr) {
+        format!("code_{:03X}", addr)
+    } else if analysis.in_rom(addr) {
+        format!("data_{:03X}", addr)
+    } else {
+        format!("${:03X}", addr)
+    }
+}
+
+/// Appends the known value of a register, e.g. " (V3 = 0x0A)".
+fn value(state: Option<&State>, x: u8) -> String {
+    match state.and_then(|s| s.v[x as usize]) {
+        Some(value) => format!(" (V{:01X} = 0x{:02X})", x, value),
+        None => String::new(),
+    }
+}
+
+/// Appends the known value of I as a label, e.g. " (I = data_3C0)".
+fn i_value(analysis: &Analysis, state: Option<&State>) -> String {
+    match state.and_then(|s| s.i) {
+        Some(i) => format!(" (I = {})", label(analysis, i as usize)),
+        None => String::new(),
+    }
+}
+
+/// Appends whether a skip is always or never taken, given its outcome when known.
+fn outcome(skips: Option<bool>) -> &'static str {
+    match skips {
+        Some(true) => " (always)",
+        Some(false) => " (never)",
+        None => "",
+    }
+}
+
+/// Describes what an instruction does. `state` is the machine state before the
+/// instruction, when the address was reached by the control-flow analysis.
+pub fn comment(analysis: &Analysis, instr: &Instruction, state: Option<&State>) -> String {
+    let v = |r: u8| state.and_then(|s| s.v[r as usize]);
+    let after = state.map(|s| s.step(instr));
+    let result = |x: u8| value(after.as_ref(), x);
+
+    match *instr {
+        Instruction::CLS => "clear the screen".to_string(),
+        Instruction::RET => "return from subroutine".to_string(),
+        Instruction::SYS(nnn) => format!("call machine code routine at ${:03X}", nnn),
+        Instruction::JP(nnn) => format!("jump to {}", label(analysis, nnn as usize)),
+        Instruction::CALL(nnn) => format!("call {}", label(analysis, nnn as usize)),
+        Instruction::SEVxImm { x, imm } => format!(
+            "skip next if V{:01X} == 0x{:02X}{}",
+            x,
+            imm,
+            outcome(v(x).map(|a| a == imm))
+        ),
+        Instruction::SNEVxImm { x, imm } => format!(
+            "skip next if V{:01X} != 0x{:02X}{}",
+            x,
+            imm,
+            outcome(v(x).map(|a| a != imm))
+        ),
+        Instruction::SEVxVy { x, y } => format!(
+            "skip next if V{:01X} == V{:01X}{}",
+            x,
+            y,
+            outcome(v(x).zip(v(y)).map(|(a, b)| a == b))
+        ),
+        Instruction::SNEVxVy { x, y } => format!(
+            "skip next if V{:01X} != V{:01X}{}",
+            x,
+            y,
+            outcome(v(x).zip(v(y)).map(|(a, b)| a != b))
+        ),
+        Instruction::LDVxImm { x, imm } => format!("V{:01X} = 0x{:02X}", x, imm),
+        Instruction::ADDVxImm { x, imm } => format!("V{:01X} += 0x{:02X}{}", x, imm, result(x)),
+        Instruction::LDVxVy { x, y } => format!("V{:01X} = V{:01X}{}", x, y, result(x)),
+        Instruction::ORVxVy { x, y } => format!("V{:01X} |= V{:01X}{}", x, y, result(x)),
+        Instruction::ANDVxVy { x, y } => format!("V{:01X} &= V{:01X}{}", x, y, result(x)),
+        Instruction::XORVxVy { x, y } => format!("V{:01X} ^= V{:01X}{}", x, y, result(x)),
+        Instruction::ADDVxVy { x, y } => format!("V{:01X} += V{:01X}, VF = carry{}", x, y, result(x)),
+        Instruction::SUBVxVy { x, y } => format!("V{:01X} -= V{:01X}, VF = NOT borrow{}", x, y, result(x)),
+        Instruction::SHRVxVy { x, .. } => format!("V{:01X} >>= 1, VF = bit shifted out{}", x, result(x)),
+        Instruction::SUBNVxVy { x, y } => format!(
+            "V{:01X} = V{:01X} - V{:01X}, VF = NOT borrow{}",
+            x,
+            y,
+            x,
+            result(x)
+        ),
+        Instruction::SHLVxVy { x, .. } => format!("V{:01X} <<= 1, VF = bit shifted out{}", x, result(x)),
+        Instruction::LDI(nnn) => format!("I = {}", label(analysis, nnn as usize)),
+        Instruction::JPV0(nnn) => match v(0) {
+            Some(v0) => format!("jump to ${:03X} + V0 (= {})", nnn, label(analysis, nnn as usize + v0 as usize)),
+            None => format!("jump to ${:03X} + V0", nnn),
+        },
+        Instruction::RNDVxImm { x, imm } => format!("V{:01X} = random byte & 0x{:02X}", x, imm),
+        Instruction::DRWVxVyn { x, y, n } => format!(
+            "draw 8x{} sprite at (V{:01X}, V{:01X}), VF = collision{}",
+            n,
+            x,
+            y,
+            i_value(analysis, state)
+        ),
+        Instruction::DRWVxVy0 { x, y } => format!(
+            "draw 16x16 sprite at (V{:01X}, V{:01X}), VF = collision{}",
+            x,
+            y,
+            i_value(analysis, state)
+        ),
+        Instruction::SKPVx(x) => format!("skip next if key V{:01X} is pressed{}", x, value(state, x)),
+        Instruction::SKNPVx(x) => format!("skip next if key V{:01X} is not pressed{}", x, value(state, x)),
+        Instruction::LDVxDT(x) => format!("V{:01X} = delay timer", x),
+        Instruction::LDVxK(x) => format!("wait for a key press, store it in V{:01X}", x),
+        Instruction::LDDTVx(x) => format!("delay timer = V{:01X}{}", x, value(state, x)),
+        Instruction::LDSTVx(x) => format!("sound timer = V{:01X}{}", x, value(state, x)),
+        Instruction::ADDIVx(x) => format!("I += V{:01X}{}", x, i_value(analysis, after.as_ref())),
+        Instruction::LDFVx(x) => format!("I = font sprite for digit V{:01X}{}", x, value(state, x)),
+        Instruction::LDHFVx(x) => format!("I = large font sprite for digit V{:01X}{}", x, value(state, x)),
+        Instruction::LDBVx(x) => format!("store BCD of V{:01X} at I..I+2{}", x, i_value(analysis, state)),
+        Instruction::LDIVx(x) => format!("store V0..V{:01X} at I..I+{}{}", x, x, i_value(analysis, state)),
+        Instruction::LDVxI(x) => format!("load V0..V{:01X} from I..I+{}{}", x, x, i_value(analysis, state)),
+        Instruction::LDRV(x) => format!("store V0..V{:01X} in the RPL flags", x),
+        Instruction::LDVxR(x) => format!("load V0..V{:01X} from the RPL flags", x),
+        Instruction::SCD(n) => format!("scroll the screen down {} lines", n),
+        Instruction::SCR => "scroll the screen right 4 pixels".to_string(),
+        Instruction::SCL => "scroll the screen left 4 pixels".to_string(),
+        Instruction::EXIT => "exit the interpreter".to_string(),
+        Instruction::LOW => "switch to 64x32 low resolution".to_string(),
+        Instruction::HIGH => "switch to 128x64 high resolution".to_string(),
+        Instruction::Unknown(_) => "unknown opcode".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+    use crate::opcodes::Dialect;
+
+    /// Comments every reachable instruction of a ROM loaded at 0x200, by address.
+    fn comments(rom: &[u8]) -> Vec<(usize, String)> {
+        let analysis = analysis::analyze(rom, 0x200, &[0x200], Dialect::Chip8);
+        analysis.code.iter().map(|(&addr, instr)| (addr, comment(&analysis, instr, analysis.states.get(&addr)))).collect()
+    }
+
+    #[test]
+    fn known_register_and_i_values() {
+        let rom = [
+            0x6A, 0x0A, // VA = 0x0A
+            0x7A, 0x05, // VA += 0x05
+            0xA2, 0x10, // I = 0x210
+            0xDA, 0xB5, // DRW VA, VB, 5
+            0x3A, 0x0F, // SE VA, 0x0F
+            0xFB, 0x15, // LD DT, VB
+            0x12, 0x0C, // JP 0x20C
+            0x00, 0x00, //
+            0xF0, 0x90, 0xF0, 0x90, 0xF0,
+        ];
+        assert_eq!(
+            comments(&rom),
+            [
+                (0x200, "VA = 0x0A".to_string()),
+                (0x202, "VA += 0x05 (VA = 0x0F)".to_string()),
+                (0x204, "I = data_210".to_string()),
+                (0x206, "draw 8x5 sprite at (VA, VB), VF = collision (I = data_210)".to_string()),
+                (0x208, "skip next if VA == 0x0F (always)".to_string()),
+                (0x20A, "delay timer = VB".to_string()),
+                (0x20C, "jump to code_20C".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_are_dropped_where_paths_disagree() {
+        let rom = [
+            0x6A, 0x01, // VA = 0x01
+            0xA2, 0x10, // I = 0x210
+            0xCB, 0x01, // VB = random byte & 0x01
+            0x3B, 0x00, // SE VB, 0x00
+            0x6A, 0x02, // VA = 0x02
+            0xFA, 0x1E, // ADD I, VA
+            0x12, 0x0C, // JP 0x20C
+        ];
+        let lines = comments(&rom);
+        assert_eq!(lines[3], (0x206, "skip next if VB == 0x00".to_string()));
+        assert_eq!(lines[5], (0x20A, "I += VA".to_string()));
+
+        // with one path VA stays known, and so does I after the addition
+        let rom = [0x6A, 0x01, 0xA2, 0x10, 0xFA, 0x1E, 0x12, 0x06];
+        assert_eq!(comments(&rom)[2], (0x204, "I += VA (I = $211)".to_string()));
+    }
+}
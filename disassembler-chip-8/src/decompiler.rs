@@ -0,0 +1,393 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::analysis::Analysis;
+use crate::opcodes::Instruction;
+use crate::options::Options;
+
+/// Decompiles the ROM into structured pseudo-code and prints it.
+pub fn run(rom: &[u8], options: &Options, out: &mut dyn Write) -> io::Result<()> {
+    let analysis = options.analyze(rom);
+
+    for (idx, (&entry, function)) in analysis.functions.iter().enumerate() {
+        if idx > 0 {
+            writeln!(out)?;
+        }
+        let mut decompiler = Decompiler::new(&analysis, &function.body);
+        for line in decompiler.function(entry) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// A line of pseudo-code, tagged with the address of the instruction it came from.
+struct Line {
+    addr: Option<usize>,
+    depth: usize,
+    text: String,
+}
+
+/// An enclosing `loop` or `while`, used to turn jumps into `break`/`continue`.
+struct Loop {
+    head: usize,
+    exit: usize,
+}
+
+struct Decompiler<'a> {
+    analysis: &'a Analysis,
+    body: &'a BTreeSet<usize>,
+    lines: Vec<Line>,
+    labels: BTreeSet<usize>,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(analysis: &'a Analysis, body: &'a BTreeSet<usize>) -> Self {
+        Decompiler { analysis, body, lines: Vec::new(), labels: BTreeSet::new(), loops: Vec::new() }
+    }
+
+    /// Returns the pseudo-code of the function starting at `entry`.
+    fn function(&mut self, entry: usize) -> Vec<String> {
+        let (Some(&first), Some(&last)) = (self.body.first(), self.body.last()) else {
+            return Vec::new();
+        };
+        if first != entry {
+            self.labels.insert(entry);
+            self.push(None, 0, format!("goto {};", label_name(entry)));
+        }
+        self.range(first, last + 2, 0);
+
+        let mut out = vec![format!("fn {}() {{", self.function_name(entry))];
+        let mut labelled = BTreeSet::new();
+        for line in &self.lines {
+            if let Some(addr) = line.addr
+                && self.labels.contains(&addr)
+                && labelled.insert(addr)
+            {
+                out.push(format!("{}:", label_name(addr)));
+            }
+            out.push(format!("{}{}", "    ".repeat(line.depth + 1), line.text));
+        }
+        out.push("}".to_string());
+        out
+    }
+
+    fn function_name(&self, addr: usize) -> String {
+        if self.analysis.entries.first() == Some(&addr) {
+            "main".to_string()
+        } else {
+            format!("sub_{:03X}", addr)
+        }
+    }
+
+    fn push(&mut self, addr: Option<usize>, depth: usize, text: String) {
+        self.lines.push(Line { addr, depth, text });
+    }
+
+    fn instr(&self, addr: usize) -> Option<Instruction> {
+        if self.body.contains(&addr) {
+            self.analysis.code.get(&addr).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Emits every instruction of the function in [lo, hi).
+    fn range(&mut self, lo: usize, hi: usize, depth: usize) {
+        let mut addr = lo;
+        while addr < hi
+            && let Some(&next) = self.body.range(addr..hi).next()
+        {
+            addr = self.statement(next, hi, depth);
+        }
+    }
+
+    /// Emits the statement starting at `addr` and returns the address after it.
+    fn statement(&mut self, addr: usize, hi: usize, depth: usize) -> usize {
+        // the furthest backward jump to `addr` inside the range closes a loop
+        let back_edge = self
+            .body
+            .range(addr..hi.max(addr))
+            .rev()
+            .find(|&&a| self.instr(a) == Some(Instruction::JP(addr as u16)))
+            .copied();
+        if let Some(end) = back_edge
+            && !self.loops.iter().any(|l| l.head == addr)
+        {
+            self.emit_loop(addr, end, depth);
+            return end + 2;
+        }
+
+        let Some(instr) = self.instr(addr) else {
+            return addr + 2;
+        };
+
+        if instr.is_skip() {
+            return self.emit_skip(addr, &instr, hi, depth);
+        }
+
+        let text = match instr {
+            Instruction::JP(target) => self.jump(target as usize),
+            Instruction::CALL(target) => format!("{}();", self.function_name(target as usize)),
+            Instruction::RET => "return;".to_string(),
+            _ => expression(&instr),
+        };
+        self.push(Some(addr), depth, text);
+        addr + 2
+    }
+
+    /// Emits `loop`, `while` or `do`/`while` for the region [head, end] closed by the jump at `end`.
+    fn emit_loop(&mut self, head: usize, end: usize, depth: usize) {
+        let exit = end + 2;
+        self.loops.push(Loop { head, exit });
+
+        // SE/SNE followed by a jump out of the loop at its head is a `while` condition
+        let guard = self.instr(head).filter(|i| i.is_skip());
+        if let Some(skip) = guard
+            && self.instr(head + 2) == Some(Instruction::JP(exit as u16))
+        {
+            self.push(Some(head), depth, format!("while {} {{", condition(&skip, false)));
+            self.range(head + 4, end, depth + 1);
+        } else if end > head + 2
+            && let Some(skip) = self.instr(end - 2).filter(|i| i.is_skip())
+        {
+            // a skip over the backward jump makes it conditional: a `do`/`while` loop
+            self.push(Some(head), depth, "do {".to_string());
+            self.range(head, end - 2, depth + 1);
+            self.push(Some(end - 2), depth, format!("}} while {};", condition(&skip, true)));
+            self.loops.pop();
+            return;
+        } else {
+            self.push(Some(head), depth, "loop {".to_string());
+            self.range(head, end, depth + 1);
+        }
+        self.push(None, depth, "}".to_string());
+
+        self.loops.pop();
+    }
+
+    /// Emits an `if`/`else` for a skip instruction and what it skips over.
+    fn emit_skip(&mut self, addr: usize, skip: &Instruction, hi: usize, depth: usize) -> usize {
+        let next = addr + 2;
+
+        // skip over a forward jump: the fall-through region runs when the skip is taken
+        if let Some(Instruction::JP(target)) = self.instr(next) {
+            let target = target as usize;
+            let in_loop = self.loops.last().is_some_and(|l| target == l.exit || target == l.head);
+            if target > next + 2 && target <= hi && !in_loop {
+                self.push(Some(addr), depth, format!("if {} {{", condition(skip, false)));
+
+                let else_jump = target - 2;
+                if let Some(Instruction::JP(end)) = self.instr(else_jump)
+                    && else_jump >= next + 2
+                    && end as usize > target
+                    && end as usize <= hi
+                {
+                    let end = end as usize;
+                    self.range(next + 2, else_jump, depth + 1);
+                    self.push(None, depth, "} else {".to_string());
+                    self.range(target, end, depth + 1);
+                    self.push(None, depth, "}".to_string());
+                    return end;
+                }
+
+                self.range(next + 2, target, depth + 1);
+                self.push(None, depth, "}".to_string());
+                return target;
+            }
+        }
+
+        // otherwise the single next statement runs when the skip is not taken
+        self.push(Some(addr), depth, format!("if {} {{", condition(skip, true)));
+        let after = if self.instr(next).is_some() {
+            self.statement(next, hi, depth + 1)
+        } else {
+            self.push(None, depth + 1, format!("goto {};", label_name(next)));
+            next + 2
+        };
+        self.push(None, depth, "}".to_string());
+        after
+    }
+
+    fn jump(&mut self, target: usize) -> String {
+        for l in self.loops.iter().rev() {
+            if target == l.exit {
+                return "break;".to_string();
+            }
+            if target == l.head {
+                return "continue;".to_string();
+            }
+        }
+        if self.analysis.functions.contains_key(&target) && !self.body.contains(&target) {
+            return format!("return {}();", self.function_name(target));
+        }
+        self.labels.insert(target);
+        format!("goto {};", label_name(target))
+    }
+}
+
+fn label_name(addr: usize) -> String {
+    format!("label_{:03X}", addr)
+}
+
+fn reg(x: u8) -> String {
+    format!("v{:x}", x)
+}
+
+/// Returns the condition under which a skip instruction skips, or its negation.
+fn condition(skip: &Instruction, negate: bool) -> String {
+    let (eq, ne) = if negate { ("!=", "==") } else { ("==", "!=") };
+    let key = if negate { "!" } else { "" };
+    let not_key = if negate { "" } else { "!" };
+    match *skip {
+        Instruction::SEVxImm { x, imm } => format!("{} {} 0x{:02X}", reg(x), eq, imm),
+        Instruction::SNEVxImm { x, imm } => format!("{} {} 0x{:02X}", reg(x), ne, imm),
+        Instruction::SEVxVy { x, y } => format!("{} {} {}", reg(x), eq, reg(y)),
+        Instruction::SNEVxVy { x, y } => format!("{} {} {}", reg(x), ne, reg(y)),
+        Instruction::SKPVx(x) => format!("{}key_pressed({})", key, reg(x)),
+        Instruction::SKNPVx(x) => format!("{}key_pressed({})", not_key, reg(x)),
+        _ => "true".to_string(),
+    }
+}
+
+/// Returns the pseudo-code statement for an instruction that does not change control flow.
+fn expression(instr: &Instruction) -> String {
+    match *instr {
+        Instruction::CLS => "clear_screen();".to_string(),
+        Instruction::SYS(nnn) => format!("sys(0x{:03X});", nnn),
+        Instruction::LDVxImm { x, imm } => format!("{} = 0x{:02X};", reg(x), imm),
+        Instruction::ADDVxImm { x, imm } => format!("{} += 0x{:02X};", reg(x), imm),
+        Instruction::LDVxVy { x, y } => format!("{} = {};", reg(x), reg(y)),
+        Instruction::ORVxVy { x, y } => format!("{} |= {};", reg(x), reg(y)),
+        Instruction::ANDVxVy { x, y } => format!("{} &= {};", reg(x), reg(y)),
+        Instruction::XORVxVy { x, y } => format!("{} ^= {};", reg(x), reg(y)),
+        Instruction::ADDVxVy { x, y } => format!("{} += {}; vf = carry;", reg(x), reg(y)),
+        Instruction::SUBVxVy { x, y } => format!("{} -= {}; vf = !borrow;", reg(x), reg(y)),
+        Instruction::SHRVxVy { x, .. } => format!("{} >>= 1; vf = lsb;", reg(x)),
+        Instruction::SUBNVxVy { x, y } => format!("{} = {} - {}; vf = !borrow;", reg(x), reg(y), reg(x)),
+        Instruction::SHLVxVy { x, .. } => format!("{} <<= 1; vf = msb;", reg(x)),
+        Instruction::LDI(nnn) => format!("i = 0x{:03X};", nnn),
+        Instruction::JPV0(nnn) => format!("goto *(0x{:03X} + v0);", nnn),
+        Instruction::RNDVxImm { x, imm } => format!("{} = rand() & 0x{:02X};", reg(x), imm),
+        Instruction::DRWVxVyn { x, y, n } => format!("vf = draw({}, {}, {});", reg(x), reg(y), n),
+        Instruction::LDVxDT(x) => format!("{} = delay_timer;", reg(x)),
+        Instruction::LDVxK(x) => format!("{} = wait_key();", reg(x)),
+        Instruction::LDDTVx(x) => format!("delay_timer = {};", reg(x)),
+        Instruction::LDSTVx(x) => format!("sound_timer = {};", reg(x)),
+        Instruction::ADDIVx(x) => format!("i += {};", reg(x)),
+        Instruction::LDFVx(x) => format!("i = font({});", reg(x)),
+        Instruction::LDBVx(x) => format!("memory[i..i + 3] = bcd({});", reg(x)),
+        Instruction::LDIVx(x) => format!("memory[i..=i + {}] = v0..={};", x, reg(x)),
+        Instruction::LDVxI(x) => format!("v0..={} = memory[i..=i + {}];", reg(x), x),
+        Instruction::DRWVxVy0 { x, y } => format!("vf = draw16({}, {});", reg(x), reg(y)),
+        Instruction::LDHFVx(x) => format!("i = big_font({});", reg(x)),
+        Instruction::LDRV(x) => format!("flags[0..={}] = v0..={};", x, reg(x)),
+        Instruction::LDVxR(x) => format!("v0..={} = flags[0..={}];", reg(x), x),
+        Instruction::SCD(n) => format!("scroll_down({});", n),
+        Instruction::SCR => "scroll_right();".to_string(),
+        Instruction::SCL => "scroll_left();".to_string(),
+        Instruction::EXIT => "exit();".to_string(),
+        Instruction::LOW => "lores();".to_string(),
+        Instruction::HIGH => "hires();".to_string(),
+        Instruction::Unknown(opcode) => format!("unknown(0x{:04X});", opcode),
+        // control flow is handled by the structuring pass
+        _ => format!("/* {} */", instr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+    use crate::opcodes::Dialect;
+
+    /// Decompiles the function at `start`, the entry point of a ROM loaded there.
+    fn decompile(rom: &[u8], start: usize) -> Vec<String> {
+        let analysis = analysis::analyze(rom, start, &[start], Dialect::Chip8);
+        Decompiler::new(&analysis, &analysis.functions[&start].body).function(start)
+    }
+
+    #[test]
+    fn self_loop_at_address_zero() {
+        // 0x000: JP 0x000
+        assert_eq!(decompile(&[0x10, 0x00], 0), ["fn main() {", "    loop {", "    }", "}"]);
+    }
+
+    #[test]
+    fn if_else() {
+        // SE v0, 5; JP 0x20A; v1 = 1; v2 = 2; JP 0x20C; v3 = 3; JP 0x20C
+        let rom = [0x30, 0x05, 0x12, 0x0A, 0x61, 0x01, 0x62, 0x02, 0x12, 0x0C, 0x63, 0x03, 0x12, 0x0C];
+        assert_eq!(
+            decompile(&rom, 0x200),
+            [
+                "fn main() {",
+                "    if v0 == 0x05 {",
+                "        v1 = 0x01;",
+                "        v2 = 0x02;",
+                "    } else {",
+                "        v3 = 0x03;",
+                "    }",
+                "    loop {",
+                "    }",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn while_loop() {
+        // SE v0, 5; JP 0x208; v0 += 1; JP 0x200; JP 0x208
+        let rom = [0x30, 0x05, 0x12, 0x08, 0x70, 0x01, 0x12, 0x00, 0x12, 0x08];
+        assert_eq!(
+            decompile(&rom, 0x200),
+            [
+                "fn main() {",
+                "    while v0 == 0x05 {",
+                "        v0 += 0x01;",
+                "    }",
+                "    loop {",
+                "    }",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn do_while_loop() {
+        // v0 += 1; SE v0, 5; JP 0x200; JP 0x206
+        let rom = [0x70, 0x01, 0x30, 0x05, 0x12, 0x00, 0x12, 0x06];
+        assert_eq!(
+            decompile(&rom, 0x200),
+            [
+                "fn main() {",
+                "    do {",
+                "        v0 += 0x01;",
+                "    } while v0 != 0x05;",
+                "    loop {",
+                "    }",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn goto_label() {
+        // a jump over data to a loop head can only be a goto: JP 0x204; (data); CLS; SE v0, 1; JP 0x204; JP 0x20A
+        let rom = [0x12, 0x04, 0xFF, 0xFF, 0x00, 0xE0, 0x30, 0x01, 0x12, 0x04, 0x12, 0x0A];
+        assert_eq!(
+            decompile(&rom, 0x200),
+            [
+                "fn main() {",
+                "    goto label_204;",
+                "label_204:",
+                "    do {",
+                "        clear_screen();",
+                "    } while v0 != 0x01;",
+                "    loop {",
+                "    }",
+                "}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::analysis::{self, Analysis};
+use crate::opcodes::{Instruction, Syntax};
+use crate::options::Options;
+
+/// Longest run of data bytes printed on one line.
+const DATA_RUN: usize = 8;
+
+/// Shortest removed/added run recognised as a moved block.
+const MIN_MOVED: usize = 2;
+
+/// Edits searched for in each direction before a part of the ROMs is given
+/// up on and printed as removed and added, so that unrelated ROMs compare quickly.
+const MAX_COST: isize = 1024;
+
+/// A unit of comparison: a reachable instruction or data bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Code { addr: usize, opcode: u16, instr: Instruction },
+    Data { addr: usize, bytes: Vec<u8> },
+}
+
+impl Item {
+    fn addr(&self) -> usize {
+        match self {
+            Item::Code { addr, .. } | Item::Data { addr, .. } => *addr,
+        }
+    }
+
+    /// Comparison key used for alignment, with absolute addresses masked out
+    /// so that code which only moved still lines up.
+    fn key(&self) -> String {
+        match self {
+            Item::Code { instr, .. } => match address_operand(instr) {
+                Some(_) => format!("{}", instr).split('$').next().unwrap_or_default().to_string(),
+                None => format!("{}", instr),
+            },
+            Item::Data { bytes, .. } => format!("DB {:02X?}", bytes),
+        }
+    }
+
+    fn render(&self, syntax: Syntax) -> String {
+        match self {
+            Item::Code { addr, opcode, instr } => format!("0x{:04X}: 0x{:04X} {}", addr, opcode, instr.render(syntax)),
+            Item::Data { addr, bytes } => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("0x{:04X}: DB {}", addr, bytes.join(" "))
+            }
+        }
+    }
+}
+
+/// Returns the absolute address an instruction refers to, if any.
+fn address_operand(instr: &Instruction) -> Option<usize> {
+    match *instr {
+        Instruction::SYS(nnn)
+        | Instruction::JP(nnn)
+        | Instruction::CALL(nnn)
+        | Instruction::LDI(nnn)
+        | Instruction::JPV0(nnn) => Some(nnn as usize),
+        _ => None,
+    }
+}
+
+/// Splits the ROM into instructions and single data bytes using the control-flow analysis.
+fn items(rom: &[u8], analysis: &Analysis) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut addr = analysis.start_addr;
+    while addr < analysis.end_addr {
+        if let Some(instr) = analysis.code.get(&addr) {
+            let opcode = analysis::fetch(rom, analysis.start_addr, addr).unwrap_or_default();
+            items.push(Item::Code { addr, opcode, instr: *instr });
+            addr += 2;
+        } else {
+            items.push(Item::Data { addr, bytes: vec![rom[addr - analysis.start_addr]] });
+            addr += 1;
+        }
+    }
+    items
+}
+
+/// Returns true if `items[i]` is a data byte directly following `items[prev]`.
+fn continues_data(items: &[Item], prev: usize, i: usize) -> bool {
+    i == prev + 1 && matches!((&items[prev], &items[i]), (Item::Data { .. }, Item::Data { .. }))
+}
+
+/// Concatenates consecutive data items into one for printing.
+fn join(items: &[Item], indices: &[usize]) -> Item {
+    match &items[indices[0]] {
+        Item::Data { addr, .. } => Item::Data {
+            addr: *addr,
+            bytes: indices
+                .iter()
+                .flat_map(|&i| match &items[i] {
+                    Item::Data { bytes, .. } => bytes.clone(),
+                    Item::Code { .. } => Vec::new(),
+                })
+                .collect(),
+        },
+        item => item.clone(),
+    }
+}
+
+/// Groups sorted indices so that runs of data bytes print as one line.
+fn group(items: &[Item], indices: &[usize]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for &i in indices {
+        match groups.last_mut() {
+            Some(g) if g.len() < DATA_RUN && continues_data(items, g[g.len() - 1], i) => g.push(i),
+            _ => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Computes a shortest edit script between `a` and `b` with the linear-space
+/// variant of Myers' algorithm.
+fn edit_script(a: &[String], b: &[String]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_range(a, b, (0, a.len()), (0, b.len()), &mut edits);
+    compact(a, b, &mut edits);
+    edits
+}
+
+/// Appends the edits turning `a[a_range]` into `b[b_range]`, splitting the
+/// problem at a middle snake so that memory stays linear in the input.
+fn diff_range(a: &[String], b: &[String], (mut a_lo, mut a_hi): (usize, usize), (mut b_lo, mut b_hi): (usize, usize), edits: &mut Vec<Edit>) {
+    while a_lo < a_hi && b_lo < b_hi && a[a_lo] == b[b_lo] {
+        edits.push(Edit::Equal(a_lo, b_lo));
+        a_lo += 1;
+        b_lo += 1;
+    }
+    let mut suffix = Vec::new();
+    while a_lo < a_hi && b_lo < b_hi && a[a_hi - 1] == b[b_hi - 1] {
+        a_hi -= 1;
+        b_hi -= 1;
+        suffix.push(Edit::Equal(a_hi, b_hi));
+    }
+
+    if a_lo == a_hi {
+        edits.extend((b_lo..b_hi).map(Edit::Insert));
+    } else if b_lo == b_hi {
+        edits.extend((a_lo..a_hi).map(Edit::Delete));
+    } else if let Some((x, y, u, v)) = middle_snake(&a[a_lo..a_hi], &b[b_lo..b_hi]) {
+        diff_range(a, b, (a_lo, a_lo + x), (b_lo, b_lo + y), edits);
+        edits.extend((0..u - x).map(|k| Edit::Equal(a_lo + x + k, b_lo + y + k)));
+        diff_range(a, b, (a_lo + u, a_hi), (b_lo + v, b_hi), edits);
+    } else {
+        edits.extend((a_lo..a_hi).map(Edit::Delete));
+        edits.extend((b_lo..b_hi).map(Edit::Insert));
+    }
+    edits.extend(suffix.into_iter().rev());
+}
+
+/// Finds the middle snake of a shortest edit script between two non-empty
+/// sequences that differ in their first and last items, as `(x, y, u, v)`:
+/// `a[x..u]` equals `b[y..v]`. Returns `None` past `MAX_COST` edits.
+fn middle_snake(a: &[String], b: &[String]) -> Option<(usize, usize, usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    // furthest x reached on each diagonal, forwards and counted from the end backwards
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+    for d in 0..=max.min(MAX_COST) {
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && forward[idx - 1] < forward[idx + 1]) { forward[idx + 1] } else { forward[idx - 1] + 1 };
+            let (x0, y0) = (x, x - k);
+            let mut y = y0;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[idx] = x;
+            let c = delta - k;
+            if delta % 2 != 0 && (-(d - 1)..=d - 1).contains(&c) && x + backward[(c + offset) as usize] >= n {
+                return Some((x0 as usize, y0 as usize, x as usize, y as usize));
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let idx = (c + offset) as usize;
+            let mut x = if c == -d || (c != d && backward[idx - 1] < backward[idx + 1]) { backward[idx + 1] } else { backward[idx - 1] + 1 };
+            let (x0, y0) = (x, x - c);
+            let mut y = y0;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[idx] = x;
+            let k = delta - c;
+            if delta % 2 == 0 && (-d..=d).contains(&k) && x + forward[(k + offset) as usize] >= n {
+                return Some(((n - x) as usize, (m - y) as usize, (n - x0) as usize, (m - y0) as usize));
+            }
+        }
+    }
+    None
+}
+
+/// Number of equal items a removal or insertion at `p` can slide across,
+/// downwards or upwards, because each of them matches the edited item.
+fn reach(a: &[String], b: &[String], edits: &[Edit], p: usize, down: bool) -> usize {
+    let mut steps = 0;
+    loop {
+        let q = if down { p + steps + 1 } else if p > steps { p - steps - 1 } else { return steps };
+        match (edits[p], edits.get(q)) {
+            (Edit::Delete(d), Some(&Edit::Equal(i, _))) if a[i] == a[d] => steps += 1,
+            (Edit::Insert(e), Some(&Edit::Equal(_, j))) if b[j] == b[e] => steps += 1,
+            _ => return steps,
+        }
+    }
+}
+
+/// Slides each removal and insertion across matching equal items when that
+/// puts it beside an edit of the other kind, so that an item changed within
+/// a run of repeated ones pairs up with its replacement.
+fn compact(a: &[String], b: &[String], edits: &mut [Edit]) {
+    for p in 0..edits.len() {
+        let opposite = |edit: Option<&Edit>| {
+            matches!((edits[p], edit), (Edit::Delete(_), Some(Edit::Insert(_))) | (Edit::Insert(_), Some(Edit::Delete(_))))
+        };
+        let (up, down) = (reach(a, b, edits, p, false), reach(a, b, edits, p, true));
+        let range = if up > 0 && p > up && opposite(edits.get(p - up - 1)) {
+            p - up..p + 1
+        } else if down > 0 && opposite(edits.get(p + down + 1)) {
+            p..p + down + 1
+        } else {
+            continue;
+        };
+        // the edited item and the equal ones swap places, keeping the indices in order
+        let moving_up = range.end == p + 1;
+        let edit = edits[p];
+        let slice = &mut edits[range];
+        let shift = |e: Edit, by: isize| match (edit, e) {
+            (Edit::Delete(_), Edit::Equal(i, j)) => Edit::Equal(i.wrapping_add_signed(by), j),
+            (_, Edit::Equal(i, j)) => Edit::Equal(i, j.wrapping_add_signed(by)),
+            (_, e) => e,
+        };
+        let moved = match (edit, slice[if moving_up { 0 } else { slice.len() - 1 }]) {
+            (Edit::Delete(_), Edit::Equal(i, _)) => Edit::Delete(i),
+            (_, Edit::Equal(_, j)) => Edit::Insert(j),
+            (_, e) => e,
+        };
+        if moving_up {
+            slice.rotate_right(1);
+            slice[0] = moved;
+            slice[1..].iter_mut().for_each(|e| *e = shift(*e, 1));
+        } else {
+            slice.rotate_left(1);
+            let last = slice.len() - 1;
+            slice[last] = moved;
+            slice[..last].iter_mut().for_each(|e| *e = shift(*e, -1));
+        }
+    }
+}
+
+/// Compares two ROMs instruction by instruction and prints the differences.
+/// Returns the number of differences.
+pub fn run(old_rom: &[u8], new_rom: &[u8], options: &Options, out: &mut dyn Write) -> io::Result<usize> {
+    let old_items = items(old_rom, &options.analyze(old_rom));
+    let new_items = items(new_rom, &options.analyze(new_rom));
+    let syntax = options.syntax;
+
+    let old_keys: Vec<String> = old_items.iter().map(Item::key).collect();
+    let new_keys: Vec<String> = new_items.iter().map(Item::key).collect();
+    let edits = edit_script(&old_keys, &new_keys);
+
+    // addresses of matched items, used to check that moved references still agree
+    let mut changed = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let mut matched = Vec::new();
+    let mut hunk = (Vec::new(), Vec::new());
+    // number of old items before each insertion, to print it in place
+    let mut insert_pos: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut cursor = 0;
+    for edit in edits.iter().chain(std::iter::once(&Edit::Equal(usize::MAX, usize::MAX))) {
+        match *edit {
+            Edit::Delete(i) => {
+                hunk.0.push(i);
+                cursor = i + 1;
+            }
+            Edit::Insert(j) => {
+                hunk.1.push(j);
+                insert_pos.insert(j, cursor);
+            }
+            Edit::Equal(i, j) => {
+                cursor = i.wrapping_add(1);
+                // pair up a removal followed by an insertion as a change
+                let (dels, ins) = std::mem::take(&mut hunk);
+                let paired = dels.len().min(ins.len());
+                for (&i, &j) in dels.iter().zip(&ins).take(paired) {
+                    changed.push((i, j));
+                }
+                removed.extend_from_slice(&dels[paired..]);
+                added.extend_from_slice(&ins[paired..]);
+                if i != usize::MAX {
+                    matched.push((i, j));
+                }
+            }
+        }
+    }
+
+    let moved = find_moved(&old_keys, &new_keys, &mut removed, &mut added);
+
+    // where every unchanged byte went, so that references to moved code still agree
+    let mut relocation: BTreeMap<usize, usize> = BTreeMap::new();
+    let moved_pairs = moved.iter().flat_map(|&(from, to, len)| (0..len).map(move |k| (from + k, to + k)));
+    for (i, j) in matched.iter().copied().chain(moved_pairs) {
+        let (a, b) = (old_items[i].addr(), new_items[j].addr());
+        relocation.insert(a, b);
+        if let Item::Code { .. } = old_items[i] {
+            relocation.insert(a + 1, b + 1);
+        }
+    }
+    changed.extend(matched.into_iter().filter(|&(i, j)| !same(&old_items[i], &new_items[j], &relocation)));
+    changed.sort();
+
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut pairs: Vec<Vec<(usize, usize)>> = Vec::new();
+    for &(i, j) in &changed {
+        match pairs.last_mut() {
+            Some(g) if g.len() < DATA_RUN && {
+                let (pi, pj) = g[g.len() - 1];
+                continues_data(&old_items, pi, i) && continues_data(&new_items, pj, j)
+            } => g.push((i, j)),
+            _ => pairs.push(vec![(i, j)]),
+        }
+    }
+    for g in &pairs {
+        let (is, js): (Vec<usize>, Vec<usize>) = g.iter().copied().unzip();
+        lines.push((is[0], format!("~ {}\n  {}", join(&old_items, &is).render(syntax), join(&new_items, &js).render(syntax))));
+    }
+    for g in group(&old_items, &removed) {
+        lines.push((g[0], format!("- {}", join(&old_items, &g).render(syntax))));
+    }
+    for g in group(&new_items, &added) {
+        lines.push((insert_pos[&g[0]], format!("+ {}", join(&new_items, &g).render(syntax))));
+    }
+    for &(from, to, len) in &moved {
+        let (first, last) = (&old_items[from], &old_items[from + len - 1]);
+        lines.push((
+            from,
+            format!(
+                "> moved 0x{:04X}..0x{:04X} -> 0x{:04X}..0x{:04X} ({} items)",
+                first.addr(),
+                last.addr(),
+                new_items[to].addr(),
+                new_items[to + len - 1].addr(),
+                len
+            ),
+        ));
+    }
+    lines.sort_by_key(|(pos, _)| *pos);
+    for (_, line) in &lines {
+        writeln!(out, "{}", line)?;
+    }
+
+    writeln!(
+        out,
+        "{} changed, {} removed, {} added, {} moved",
+        changed.len(),
+        removed.len(),
+        added.len(),
+        moved.len()
+    )?;
+    Ok(lines.len())
+}
+
+/// Returns true if two aligned items are identical once moved addresses are accounted for.
+fn same(old: &Item, new: &Item, relocation: &BTreeMap<usize, usize>) -> bool {
+    match (old, new) {
+        (Item::Code { opcode: a, instr: old_instr, .. }, Item::Code { opcode: b, instr: new_instr, .. }) => {
+            a == b
+                || address_operand(old_instr)
+                    .zip(address_operand(new_instr))
+                    .is_some_and(|(from, to)| relocation.get(&from).copied().unwrap_or(from) == to)
+        }
+        (Item::Data { bytes: a, .. }, Item::Data { bytes: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+/// Finds removed runs that reappear unchanged as added runs, removing them from both lists.
+/// Returns (old index, new index, length) for each moved block.
+fn find_moved(
+    old_keys: &[String],
+    new_keys: &[String],
+    removed: &mut Vec<usize>,
+    added: &mut Vec<usize>,
+) -> Vec<(usize, usize, usize)> {
+    let mut moved = Vec::new();
+    for (start, len) in runs(removed) {
+        if len < MIN_MOVED {
+            continue;
+        }
+        let matches = |t: usize| (0..len).all(|k| old_keys[start + k] == new_keys[t + k]);
+        let Some(target) = runs(added)
+            .into_iter()
+            .filter(|&(_, l)| l >= len)
+            .find_map(|(s, l)| (s..=s + l - len).find(|&t| matches(t)))
+        else {
+            continue;
+        };
+        removed.retain(|&i| i < start || i >= start + len);
+        added.retain(|&j| j < target || j >= target + len);
+        moved.push((start, target, len));
+    }
+    moved
+}
+
+/// Groups sorted indices into (start, length) runs of consecutive values.
+fn runs(indices: &[usize]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &i in indices {
+        match runs.last_mut() {
+            Some((start, len)) if *start + *len == i => *len += 1,
+            _ => runs.push((i, 1)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options;
+
+    fn keys(items: &str) -> Vec<String> {
+        items.chars().map(String::from).collect()
+    }
+
+    /// Rebuilds both sequences from an edit script, checking that it is in order.
+    fn apply(a: &[String], b: &[String], edits: &[Edit]) -> (Vec<String>, Vec<String>) {
+        let (mut old, mut new) = (Vec::new(), Vec::new());
+        for edit in edits {
+            match *edit {
+                Edit::Equal(i, j) => {
+                    assert_eq!(a[i], b[j]);
+                    old.push(a[i].clone());
+                    new.push(b[j].clone());
+                }
+                Edit::Delete(i) => old.push(a[i].clone()),
+                Edit::Insert(j) => new.push(b[j].clone()),
+            }
+        }
+        (old, new)
+    }
+
+    /// Diffs two ROMs loaded at 0x200, returning the difference count and the output.
+    fn diff(old: &[u8], new: &[u8]) -> (usize, String) {
+        let args: Vec<String> = ["diff", "old.ch8", "new.ch8"].iter().map(|s| s.to_string()).collect();
+        let options = options::parse(&args).unwrap();
+        let mut out = Vec::new();
+        let count = run(old, new, &options, &mut out).unwrap();
+        (count, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn edit_script_is_shortest() {
+        // the example of Myers' paper, 5 edits apart
+        let (a, b) = (keys("ABCABBA"), keys("CBABAC"));
+        let edits = edit_script(&a, &b);
+        assert_eq!(apply(&a, &b, &edits), (a.clone(), b.clone()));
+        assert_eq!(edits.iter().filter(|e| !matches!(e, Edit::Equal(..))).count(), 5);
+    }
+
+    #[test]
+    fn change_inside_repeated_items_pairs_up() {
+        let (a, b) = (keys("xAAAAy"), keys("xAABAy"));
+        let edits = edit_script(&a, &b);
+        assert_eq!(apply(&a, &b, &edits), (a.clone(), b.clone()));
+        let at = edits.iter().position(|e| matches!(e, Edit::Delete(_) | Edit::Insert(_))).unwrap();
+        assert!(matches!(edits[at..at + 2], [Edit::Delete(3), Edit::Insert(3)] | [Edit::Insert(3), Edit::Delete(3)]));
+    }
+
+    #[test]
+    fn identical_roms_have_no_differences() {
+        // no differences is what makes `diff` exit with 0
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x00, 0xEE];
+        assert_eq!(diff(&rom, &rom), (0, "0 changed, 0 removed, 0 added, 0 moved\n".to_string()));
+    }
+
+    #[test]
+    fn references_follow_code_moved_by_an_insertion() {
+        // CALL 0x204; JP 0x202; RET, then the same behind a CLS
+        let old = [0x22, 0x04, 0x12, 0x02, 0x00, 0xEE];
+        let new = [0x00, 0xE0, 0x22, 0x06, 0x12, 0x04, 0x00, 0xEE];
+        let (count, out) = diff(&old, &new);
+        assert_eq!(count, 1);
+        assert_eq!(out, "+ 0x0200: 0x00E0 CLS\n0 changed, 0 removed, 1 added, 0 moved\n");
+    }
+
+    #[test]
+    fn finds_moved_runs_of_at_least_min_moved_items() {
+        let (old, new) = (keys("abcd"), keys("cdab"));
+        let (mut removed, mut added) = (vec![0, 1], vec![2, 3]);
+        assert_eq!(find_moved(&old, &new, &mut removed, &mut added), [(0, 2, MIN_MOVED)]);
+        assert!(removed.is_empty() && added.is_empty());
+
+        let (mut removed, mut added) = (vec![0], vec![1]);
+        assert!(find_moved(&keys("ab"), &keys("ba"), &mut removed, &mut added).is_empty());
+        assert_eq!((removed, added), (vec![0], vec![1]));
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::analysis::{self, Analysis, Flow};
+use crate::opcodes::Instruction;
+use crate::options::Options;
+
+/// Maximum subroutine nesting supported by the original interpreter.
+const STACK_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// A single problem found in the ROM.
+pub struct Finding {
+    pub addr: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Lints the ROM and prints every finding. Returns the number of errors.
+pub fn run(rom: &[u8], options: &Options, out: &mut dyn Write) -> io::Result<usize> {
+    let findings = lint(&options.analyze(rom));
+
+    for finding in &findings {
+        writeln!(out, "0x{:04X}: {}: {}", finding.addr, finding.severity, finding.message)?;
+    }
+
+    let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+    let errors = count(Severity::Error);
+    writeln!(
+        out,
+        "{} errors, {} warnings, {} infos",
+        errors,
+        count(Severity::Warning),
+        count(Severity::Info)
+    )?;
+    Ok(errors)
+}
+
+pub fn lint(analysis: &Analysis) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (&addr, instr) in &analysis.code {
+        let mut report = |severity, message: String| {
+            findings.push(Finding { addr, severity, message });
+        };
+
+        match *instr {
+            Instruction::Unknown(opcode) => {
+                report(Severity::Error, format!("unknown opcode 0x{:04X} is reachable", opcode));
+            }
+            Instruction::SYS(nnn) => report(
+                Severity::Warning,
+                format!("SYS ${:03X} calls a machine code routine, modern interpreters ignore it", nnn),
+            ),
+            Instruction::LDIVx(x) => {
+                if let Some(message) = code_overwrite(analysis, addr, x as usize + 1) {
+                    report(Severity::Warning, message);
+                }
+                if reads_i_after(analysis, addr) {
+                    report(
+                        Severity::Info,
+                        "I is advanced past the stored registers on the COSMAC VIP but not on CHIP-48/SUPER-CHIP (load/store quirk)".to_string(),
+                    );
+                }
+            }
+            Instruction::LDVxI(_) if reads_i_after(analysis, addr) => report(
+                Severity::Info,
+                "I is advanced past the loaded registers on the COSMAC VIP but not on CHIP-48/SUPER-CHIP (load/store quirk)".to_string(),
+            ),
+            Instruction::LDBVx(_) => {
+                if let Some(message) = code_overwrite(analysis, addr, 3) {
+                    report(Severity::Warning, message);
+                }
+            }
+            Instruction::JPV0(nnn) if nnn >> 8 != 0 => report(
+                Severity::Info,
+                format!("jumps to ${:03X} + V0 on CHIP-8 but ${:03X} + V{:01X} on SUPER-CHIP (jump quirk)", nnn, nnn, nnn >> 8),
+            ),
+            _ => {}
+        }
+
+        match analysis::flow(instr) {
+            Flow::Jump(target) => check_target(analysis, "jump", target, &mut report),
+            Flow::Call(target) => check_target(analysis, "call", target, &mut report),
+            _ => {}
+        }
+
+        check_vf(instr, &mut report);
+    }
+
+    check_stack_depth(analysis, &mut findings);
+
+    findings.sort_by_key(|f| (f.addr, f.severity));
+    findings
+}
+
+/// Reports jump and call targets that are odd or outside the ROM.
+fn check_target(analysis: &Analysis, kind: &str, target: usize, report: &mut impl FnMut(Severity, String)) {
+    if !analysis.in_rom(target) {
+        report(
+            Severity::Error,
+            format!(
+                "{} target ${:03X} is outside the ROM (${:03X}..${:03X})",
+                kind, target, analysis.start_addr, analysis.end_addr
+            ),
+        );
+    } else if !target.is_multiple_of(2) {
+        report(
+            Severity::Warning,
+            format!("{} target ${:03X} is odd, instructions are normally word-aligned", kind, target),
+        );
+    }
+}
+
+/// Reports 8xyN operations that use VF as an operand.
+fn check_vf(instr: &Instruction, report: &mut impl FnMut(Severity, String)) {
+    let (x, y, sets_flag) = match *instr {
+        Instruction::ORVxVy { x, y } | Instruction::ANDVxVy { x, y } | Instruction::XORVxVy { x, y } => (x, y, false),
+        Instruction::ADDVxVy { x, y }
+        | Instruction::SUBVxVy { x, y }
+        | Instruction::SHRVxVy { x, y }
+        | Instruction::SUBNVxVy { x, y }
+        | Instruction::SHLVxVy { x, y } => (x, y, true),
+        _ => return,
+    };
+
+    if x == 0xF && sets_flag {
+        report(
+            Severity::Warning,
+            "VF is the destination of an operation that sets VF, the result is overwritten by the flag".to_string(),
+        );
+    } else if x == 0xF {
+        report(
+            Severity::Info,
+            "VF is the destination of a logic operation, the COSMAC VIP resets it to 0 afterwards (logic quirk)".to_string(),
+        );
+    } else if y == 0xF {
+        report(
+            Severity::Info,
+            "VF is used as an operand, its value is a flag left by an earlier instruction".to_string(),
+        );
+    }
+
+    if let Instruction::SHRVxVy { x, y } | Instruction::SHLVxVy { x, y } = *instr
+        && x != y
+    {
+        report(
+            Severity::Info,
+            format!("shifts V{:01X} on the COSMAC VIP but V{:01X} on CHIP-48/SUPER-CHIP (shift quirk)", y, x),
+        );
+    }
+}
+
+/// Returns a message if writing `len` bytes at the known value of I overwrites reachable code.
+fn code_overwrite(analysis: &Analysis, addr: usize, len: usize) -> Option<String> {
+    let i = analysis.states.get(&addr)?.i? as usize;
+    let hit = (i..i + len).find(|&a| analysis.is_code_byte(a))?;
+    Some(format!(
+        "writes ${:03X}..${:03X} through I, overwriting code at ${:03X} (self-modifying code)",
+        i,
+        i + len - 1,
+        hit
+    ))
+}
+
+/// Returns true if I is read on the straight-line path after `addr` before it is reloaded.
+fn reads_i_after(analysis: &Analysis, addr: usize) -> bool {
+    let mut next = addr + 2;
+    while let Some(instr) = analysis.code.get(&next) {
+        match instr {
+            Instruction::DRWVxVyn { .. }
+            | Instruction::DRWVxVy0 { .. }
+            | Instruction::LDBVx(_)
+            | Instruction::LDIVx(_)
+            | Instruction::LDVxI(_)
+            | Instruction::ADDIVx(_) => return true,
+            Instruction::LDI(_) | Instruction::LDFVx(_) | Instruction::LDHFVx(_) => return false,
+            _ => {}
+        }
+        match analysis::flow(instr) {
+            Flow::Next | Flow::Skip => next += 2,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Reports recursive calls and call chains deeper than the interpreter stack.
+fn check_stack_depth(analysis: &Analysis, findings: &mut Vec<Finding>) {
+    let mut heights: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut active = BTreeSet::new();
+    let mut recursive = BTreeSet::new();
+    for &entry in &analysis.entries {
+        let height = stack_height(analysis, entry, &mut heights, &mut active, &mut recursive);
+        if height > STACK_SIZE {
+            findings.push(stack_overflow(analysis, entry, height, &heights));
+        }
+    }
+
+    for (site, target) in recursive {
+        findings.push(Finding {
+            addr: site,
+            severity: Severity::Warning,
+            message: format!("recursive call to ${:03X}, stack depth is unbounded", target),
+        });
+    }
+}
+
+/// Follows the deepest call chain from `entry` and reports the call that overflows the stack.
+fn stack_overflow(analysis: &Analysis, entry: usize, height: usize, heights: &BTreeMap<usize, usize>) -> Finding {
+    let mut function = entry;
+    let mut site = entry;
+    for _ in 0..=STACK_SIZE {
+        let Some(&(call, target)) = analysis
+            .functions
+            .get(&function)
+            .and_then(|f| f.calls.iter().max_by_key(|(_, t)| heights.get(t).copied().unwrap_or(0)))
+        else {
+            break;
+        };
+        site = call;
+        function = target;
+    }
+    Finding {
+        addr: site,
+        severity: Severity::Error,
+        message: format!("call stack reaches {} levels, exceeding the {}-entry stack", height, STACK_SIZE),
+    }
+}
+
+/// Returns the deepest nesting of calls starting in `function`, recording recursive call sites.
+fn stack_height(
+    analysis: &Analysis,
+    function: usize,
+    heights: &mut BTreeMap<usize, usize>,
+    active: &mut BTreeSet<usize>,
+    recursive: &mut BTreeSet<(usize, usize)>,
+) -> usize {
+    if let Some(&height) = heights.get(&function) {
+        return height;
+    }
+    let Some(f) = analysis.functions.get(&function) else {
+        return 0;
+    };
+
+    active.insert(function);
+    let mut height = 0;
+    for &(site, target) in &f.calls {
+        if active.contains(&target) {
+            recursive.insert((site, target));
+            continue;
+        }
+        height = height.max(1 + stack_height(analysis, target, heights, active, recursive));
+    }
+    active.remove(&function);
+
+    heights.insert(function, height);
+    height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::Dialect;
+
+    /// Lints a ROM loaded and entered at 0x200, as (address, severity, message).
+    fn lint_rom(rom: &[u8]) -> Vec<(usize, Severity, String)> {
+        let analysis = analysis::analyze(rom, 0x200, &[0x200], Dialect::Chip8);
+        lint(&analysis).into_iter().map(|f| (f.addr, f.severity, f.message)).collect()
+    }
+
+    /// A chain of `depth` functions 4 bytes apart, each calling the next and returning.
+    fn call_chain(depth: usize) -> Vec<u8> {
+        let mut rom = Vec::new();
+        for level in 0..depth {
+            let next = 0x200 + 4 * (level + 1);
+            rom.extend_from_slice(&[0x20 | (next >> 8) as u8, next as u8, 0x00, 0xEE]);
+        }
+        rom.extend_from_slice(&[0x00, 0xEE]);
+        rom
+    }
+
+    #[test]
+    fn reports_call_chains_deeper_than_the_stack() {
+        assert!(lint_rom(&call_chain(16)).is_empty());
+
+        let findings = lint_rom(&call_chain(17));
+        assert_eq!(findings.len(), 1);
+        let (addr, severity, message) = &findings[0];
+        // the deepest call, the 17th, is made by the function at 0x240
+        assert_eq!((*addr, *severity), (0x240, Severity::Error));
+        assert_eq!(message, "call stack reaches 17 levels, exceeding the 16-entry stack");
+    }
+
+    #[test]
+    fn reports_recursive_calls() {
+        // 0x200: CALL 0x204; 0x202: JP 0x202; 0x204: CALL 0x204; 0x206: RET
+        let findings = lint_rom(&[0x22, 0x04, 0x12, 0x02, 0x22, 0x04, 0x00, 0xEE]);
+        assert_eq!(findings, [(0x204, Severity::Warning, "recursive call to $204, stack depth is unbounded".to_string())]);
+    }
+
+    #[test]
+    fn reports_odd_and_out_of_rom_targets() {
+        // 0x200: JP 0x203, with a JP 0x203 at 0x203 to stop there
+        let findings = lint_rom(&[0x12, 0x03, 0x00, 0x12, 0x03]);
+        assert_eq!(findings[0], (0x200, Severity::Warning, "jump target $203 is odd, instructions are normally word-aligned".to_string()));
+
+        let findings = lint_rom(&[0x23, 0x00]);
+        assert_eq!(findings, [(0x200, Severity::Error, "call target $300 is outside the ROM ($200..$202)".to_string())]);
+    }
+
+    #[test]
+    fn reports_writes_through_known_i_into_code() {
+        // 0x200: LD I, 0x206; 0x202: LD [I], V1; 0x204: JP 0x204; 0x206: data
+        let findings = lint_rom(&[0xA2, 0x06, 0xF1, 0x55, 0x12, 0x04, 0x00, 0x00]);
+        assert!(findings.is_empty());
+
+        // 0x200: LD I, 0x204; 0x202: LD [I], V1; 0x204: JP 0x204
+        let findings = lint_rom(&[0xA2, 0x04, 0xF1, 0x55, 0x12, 0x04]);
+        assert_eq!(findings, [(0x202, Severity::Warning, "writes $204..$205 through I, overwriting code at $204 (self-modifying code)".to_string())]);
+    }
+}
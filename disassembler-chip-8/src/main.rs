@@ -1,25 +1,86 @@
+mod analysis;
+mod annotate;
+mod decompiler;
+mod diff;
 mod disassembler;
+mod lint;
+mod opcodes;
+mod options;
 mod utils;
 use std::env;
 use std::error::Error;
 use std::fs;
-// use std::io;
+use std::io::{self, Read, Write};
+use std::process;
+
+use options::{Command, ParseError};
+
+/// Reads a ROM from a file, or from stdin when the path is `-`.
+fn read_rom(filepath: &str) -> Result<Vec<u8>, String> {
+    let rom = if filepath == "-" {
+        let mut rom = Vec::new();
+        io::stdin().read_to_end(&mut rom).map(|_| rom)
+    } else {
+        fs::read(filepath)
+    };
+    rom.map_err(|e| format!("Error at handling file {}: {}", filepath, e))
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Use: cargo run -- <file.ch8>");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match options::parse(&args) {
+        Ok(options) => options,
+        Err(ParseError::Help) => {
+            println!("{}", options::USAGE);
+            return Ok(());
+        }
+        Err(ParseError::Invalid(e)) => {
+            eprintln!("{}\n", e);
+            eprintln!("{}", options::USAGE);
 
-        return Ok(());
-    }
+            process::exit(2);
+        }
+    };
 
-    let filepath = &args[1];
-    let rom =
-        fs::read(filepath)
-            .map_err(|e| format!("Error at handling file {}: {}", filepath, e))?;
-    println!("Disassembling {} ({} bytes)", filepath, rom.len());
+    let filepath = &options.inputs[0];
+    let rom = read_rom(filepath)?;
+    let code = options.slice(&rom);
+
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(io::BufWriter::new(
+            fs::File::create(path).map_err(|e| format!("Error at creating file {}: {}", path, e))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
 
-    disassembler::run(&rom);
+    let failed = match options.command {
+        Command::Lint => {
+            writeln!(out, "Linting {} ({} bytes)", filepath, rom.len())?;
+            lint::run(code, &options, &mut out)? > 0
+        }
+        Command::Decompile => {
+            writeln!(out, "// Decompiled from {} ({} bytes)", filepath, rom.len())?;
+            decompiler::run(code, &options, &mut out)?;
+            false
+        }
+        Command::Diff => {
+            let new_filepath = &options.inputs[1];
+            let new_rom = read_rom(new_filepath)?;
+            writeln!(out, "--- {} ({} bytes)", filepath, rom.len())?;
+            writeln!(out, "+++ {} ({} bytes)", new_filepath, new_rom.len())?;
+            diff::run(code, options.slice(&new_rom), &options, &mut out)? > 0
+        }
+        Command::Disassemble => {
+            writeln!(out, "Disassembling {} ({} bytes)", filepath, rom.len())?;
+            disassembler::run(code, &options, &mut out)?;
+            false
+        }
+    };
+
+    out.flush()?;
+    if failed {
+        process::exit(1);
+    }
 
     Ok(())
 }
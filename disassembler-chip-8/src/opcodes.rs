@@ -0,0 +1,326 @@
+use std::fmt;
+
+use crate::utils;
+
+/// Instruction set the ROM was written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// The original COSMAC VIP instruction set.
+    Chip8,
+    /// SUPER-CHIP 1.1, adding scrolling, high resolution and RPL flags.
+    Schip,
+}
+
+/// Assembly syntax used to print instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Mnemonics from Cowgod's Chip-8 technical reference.
+    Cowgod,
+    /// The Octo assembler language.
+    Octo,
+}
+
+/// A decoded Chip-8 instruction.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Clear the display.
+    CLS,
+    /// Return from a subroutine.
+    RET,
+    /// Jump to a machine code routine at nnn. (Ignored by modern interpreters.)
+    SYS(u16),
+    /// Jump to location nnn.
+    JP(u16),
+    /// Call subroutine at nnn.
+    CALL(u16),
+    /// Skip next instruction if Vx == kk.
+    SEVxImm { x: u8, imm: u8 },
+    /// Skip next instruction if Vx != kk.
+    SNEVxImm { x: u8, imm: u8 },
+    /// Skip next instruction if Vx == Vy.
+    SEVxVy { x: u8, y: u8 },
+    /// Set Vx = kk.
+    LDVxImm { x: u8, imm: u8 },
+    /// Set Vx = Vx + kk.
+    ADDVxImm { x: u8, imm: u8 },
+    /// Set Vx = Vy.
+    LDVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx | Vy (bitwise OR).
+    ORVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx & Vy (bitwise AND).
+    ANDVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx ^ Vy (bitwise XOR).
+    XORVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx + Vy, set VF = carry.
+    ADDVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx - Vy, set VF = NOT borrow.
+    SUBVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx >> 1 (or Vy >> 1 on the COSMAC VIP), set VF = LSB.
+    SHRVxVy { x: u8, y: u8 },
+    /// Set Vx = Vy - Vx, set VF = NOT borrow.
+    SUBNVxVy { x: u8, y: u8 },
+    /// Set Vx = Vx << 1 (or Vy << 1 on the COSMAC VIP), set VF = MSB.
+    SHLVxVy { x: u8, y: u8 },
+    /// Skip next instruction if Vx != Vy.
+    SNEVxVy { x: u8, y: u8 },
+    /// Set I = nnn.
+    LDI(u16),
+    /// Jump to location nnn + V0.
+    JPV0(u16),
+    /// Set Vx = random byte & kk.
+    RNDVxImm { x: u8, imm: u8 },
+    /// Display n-byte sprite at (Vx, Vy), set VF = collision.
+    DRWVxVyn { x: u8, y: u8, n: u8 },
+    /// Display 16x16 sprite at (Vx, Vy), set VF = collision. (Super Chip-48)
+    DRWVxVy0 { x: u8, y: u8 },
+    /// Skip next instruction if key Vx is pressed.
+    SKPVx(u8),
+    /// Skip next instruction if key Vx is not pressed.
+    SKNPVx(u8),
+    /// Set Vx = delay timer value.
+    LDVxDT(u8),
+    /// Wait for key press, store in Vx.
+    LDVxK(u8),
+    /// Set delay timer = Vx.
+    LDDTVx(u8),
+    /// Set sound timer = Vx.
+    LDSTVx(u8),
+    /// Set I = I + Vx.
+    ADDIVx(u8),
+    /// Set I = location of sprite for digit Vx.
+    LDFVx(u8),
+    /// Store BCD of Vx in memory at I, I+1, I+2.
+    LDBVx(u8),
+    /// Store registers V0 through Vx in memory starting at I.
+    LDIVx(u8),
+    /// Read registers V0 through Vx from memory starting at I.
+    LDVxI(u8),
+    /// Set I = location of high-res sprite for digit Vx. (Super Chip-48)
+    LDHFVx(u8),
+    /// Store V0 through Vx in RPL user flags. (Super Chip-48)
+    LDRV(u8),
+    /// Read V0 through Vx from RPL user flags. (Super Chip-48)
+    LDVxR(u8),
+    /// Scroll display down by n lines. (Super Chip-48)
+    SCD(u8),
+    /// Scroll display right by 4 pixels. (Super Chip-48)
+    SCR,
+    /// Scroll display left by 4 pixels. (Super Chip-48)
+    SCL,
+    /// Exit the interpreter. (Super Chip-48)
+    EXIT,
+    /// Set display to low resolution (64x32). (Super Chip-48)
+    LOW,
+    /// Set display to high resolution (128x64). (Super Chip-48)
+    HIGH,
+    /// Unknown opcode.
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// Returns true for the conditional skip instructions (SE, SNE, SKP, SKNP).
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SEVxImm { .. }
+                | Instruction::SNEVxImm { .. }
+                | Instruction::SEVxVy { .. }
+                | Instruction::SNEVxVy { .. }
+                | Instruction::SKPVx(_)
+                | Instruction::SKNPVx(_)
+        )
+    }
+
+    /// Formats the instruction in the given assembly syntax.
+    pub fn render(&self, syntax: Syntax) -> String {
+        match syntax {
+            Syntax::Cowgod => self.to_string(),
+            Syntax::Octo => self.octo(),
+        }
+    }
+
+    /// Formats the instruction as Octo source. Skips become `if ... then`,
+    /// which runs the next instruction only when the condition holds.
+    fn octo(&self) -> String {
+        match *self {
+            Instruction::CLS => "clear".to_string(),
+            Instruction::RET => "return".to_string(),
+            Instruction::SYS(nnn) => format!("native 0x{:03X}", nnn),
+            Instruction::JP(nnn) => format!("jump 0x{:03X}", nnn),
+            Instruction::CALL(nnn) => format!(":call 0x{:03X}", nnn),
+            Instruction::SEVxImm { x, imm } => format!("if v{:x} != 0x{:02X} then", x, imm),
+            Instruction::SNEVxImm { x, imm } => format!("if v{:x} == 0x{:02X} then", x, imm),
+            Instruction::SEVxVy { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::LDVxImm { x, imm } => format!("v{:x} := 0x{:02X}", x, imm),
+            Instruction::ADDVxImm { x, imm } => format!("v{:x} += 0x{:02X}", x, imm),
+            Instruction::LDVxVy { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::ORVxVy { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::ANDVxVy { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::XORVxVy { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::ADDVxVy { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::SUBVxVy { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::SHRVxVy { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SUBNVxVy { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::SHLVxVy { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::SNEVxVy { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::LDI(nnn) => format!("i := 0x{:03X}", nnn),
+            Instruction::JPV0(nnn) => format!("jump0 0x{:03X}", nnn),
+            Instruction::RNDVxImm { x, imm } => format!("v{:x} := random 0x{:02X}", x, imm),
+            Instruction::DRWVxVyn { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::DRWVxVy0 { x, y } => format!("sprite v{:x} v{:x} 0", x, y),
+            Instruction::SKPVx(x) => format!("if v{:x} -key then", x),
+            Instruction::SKNPVx(x) => format!("if v{:x} key then", x),
+            Instruction::LDVxDT(x) => format!("v{:x} := delay", x),
+            Instruction::LDVxK(x) => format!("v{:x} := key", x),
+            Instruction::LDDTVx(x) => format!("delay := v{:x}", x),
+            Instruction::LDSTVx(x) => format!("buzzer := v{:x}", x),
+            Instruction::ADDIVx(x) => format!("i += v{:x}", x),
+            Instruction::LDFVx(x) => format!("i := hex v{:x}", x),
+            Instruction::LDBVx(x) => format!("bcd v{:x}", x),
+            Instruction::LDIVx(x) => format!("save v{:x}", x),
+            Instruction::LDVxI(x) => format!("load v{:x}", x),
+            Instruction::LDHFVx(x) => format!("i := bighex v{:x}", x),
+            Instruction::LDRV(x) => format!("saveflags v{:x}", x),
+            Instruction::LDVxR(x) => format!("loadflags v{:x}", x),
+            Instruction::SCD(n) => format!("scroll-down {}", n),
+            Instruction::SCR => "scroll-right".to_string(),
+            Instruction::SCL => "scroll-left".to_string(),
+            Instruction::EXIT => "exit".to_string(),
+            Instruction::LOW => "lores".to_string(),
+            Instruction::HIGH => "hires".to_string(),
+            Instruction::Unknown(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+        }
+    }
+}
+
+pub fn decode(opcode: u16, dialect: Dialect) -> Instruction {
+    let x = utils::x(opcode);
+    let y = utils::y(opcode);
+    let schip = dialect == Dialect::Schip;
+    match (opcode & 0xF000) >> 12 {
+        0x0 => match opcode {
+            0x00E0 => Instruction::CLS,
+            0x00EE => Instruction::RET,
+            0x00FB if schip => Instruction::SCR,
+            0x00FC if schip => Instruction::SCL,
+            0x00FD if schip => Instruction::EXIT,
+            0x00FE if schip => Instruction::LOW,
+            0x00FF if schip => Instruction::HIGH,
+            0x00C0..=0x00CF if schip => Instruction::SCD(utils::k(opcode)),
+            _ => Instruction::SYS(utils::nnn(opcode)),
+        },
+        0x1 => Instruction::JP(utils::nnn(opcode)),
+        0x2 => Instruction::CALL(utils::nnn(opcode)),
+        0x3 => Instruction::SEVxImm { x, imm: utils::kk(opcode) },
+        0x4 => Instruction::SNEVxImm { x, imm: utils::kk(opcode) },
+        0x5 => match utils::k(opcode) {
+            0x0 => Instruction::SEVxVy { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x6 => Instruction::LDVxImm { x, imm: utils::kk(opcode) },
+        0x7 => Instruction::ADDVxImm { x, imm: utils::kk(opcode) },
+        0x8 => match utils::k(opcode) {
+            0x0 => Instruction::LDVxVy { x, y },
+            0x1 => Instruction::ORVxVy { x, y },
+            0x2 => Instruction::ANDVxVy { x, y },
+            0x3 => Instruction::XORVxVy { x, y },
+            0x4 => Instruction::ADDVxVy { x, y },
+            0x5 => Instruction::SUBVxVy { x, y },
+            0x6 => Instruction::SHRVxVy { x, y },
+            0x7 => Instruction::SUBNVxVy { x, y },
+            0xE => Instruction::SHLVxVy { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0x9 => match utils::k(opcode) {
+            0x0 => Instruction::SNEVxVy { x, y },
+            _ => Instruction::Unknown(opcode),
+        },
+        0xA => Instruction::LDI(utils::nnn(opcode)),
+        0xB => Instruction::JPV0(utils::nnn(opcode)),
+        0xC => Instruction::RNDVxImm { x, imm: utils::kk(opcode) },
+        0xD => match utils::k(opcode) {
+            0 if schip => Instruction::DRWVxVy0 { x, y },
+            n => Instruction::DRWVxVyn { x, y, n },
+        },
+        0xE => match utils::kk(opcode) {
+            0x9E => Instruction::SKPVx(x),
+            0xA1 => Instruction::SKNPVx(x),
+            _ => Instruction::Unknown(opcode),
+        },
+        0xF => match utils::kk(opcode) {
+            0x07 => Instruction::LDVxDT(x),
+            0x0A => Instruction::LDVxK(x),
+            0x15 => Instruction::LDDTVx(x),
+            0x18 => Instruction::LDSTVx(x),
+            0x1E => Instruction::ADDIVx(x),
+            0x29 => Instruction::LDFVx(x),
+            0x30 if schip => Instruction::LDHFVx(x),
+            0x33 => Instruction::LDBVx(x),
+            0x55 => Instruction::LDIVx(x),
+            0x65 => Instruction::LDVxI(x),
+            0x75 if schip => Instruction::LDRV(x),
+            0x85 if schip => Instruction::LDVxR(x),
+            _ => Instruction::Unknown(opcode),
+        },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::CLS => write!(f, "CLS"),
+            Instruction::RET => write!(f, "RET"),
+            Instruction::SYS(nnn) => write!(f, "SYS ${:03X}", nnn),
+            Instruction::JP(nnn) => write!(f, "JMP ${:03X}", nnn),
+            Instruction::CALL(nnn) => write!(f, "CALL ${:03X}", nnn),
+            Instruction::SEVxImm { x, imm } => write!(f, "SE V{:01X}, {:02X}", x, imm),
+            Instruction::SNEVxImm { x, imm } => write!(f, "SNE V{:01X}, {:02X}", x, imm),
+            Instruction::SEVxVy { x, y } => write!(f, "SE V{:01X}, V{:01X}", x, y),
+            Instruction::LDVxImm { x, imm } => write!(f, "LD V{:01X}, {:02X}", x, imm),
+            Instruction::ADDVxImm { x, imm } => write!(f, "ADD V{:01X}, {:02X}", x, imm),
+            Instruction::LDVxVy { x, y } => write!(f, "LD V{:01X}, V{:01X}", x, y),
+            Instruction::ORVxVy { x, y } => write!(f, "OR V{:01X}, V{:01X}", x, y),
+            Instruction::ANDVxVy { x, y } => write!(f, "AND V{:01X}, V{:01X}", x, y),
+            Instruction::XORVxVy { x, y } => write!(f, "XOR V{:01X}, V{:01X}", x, y),
+            Instruction::ADDVxVy { x, y } => write!(f, "ADD V{:01X}, V{:01X}", x, y),
+            Instruction::SUBVxVy { x, y } => write!(f, "SUB V{:01X}, V{:01X}", x, y),
+            Instruction::SHRVxVy { x, y } => write!(f, "SHR V{:01X} {{ ,V{:01X}}}", x, y),
+            Instruction::SUBNVxVy { x, y } => write!(f, "SUBN V{:01X}, V{:01X}", x, y),
+            Instruction::SHLVxVy { x, y } => write!(f, "SHL V{:01X} {{ ,V{:01X}}}", x, y),
+            Instruction::SNEVxVy { x, y } => write!(f, "SNE V{:01X}, V{:01X}", x, y),
+            Instruction::LDI(nnn) => write!(f, "LD I, ${:03X}", nnn),
+            Instruction::JPV0(nnn) => write!(f, "JMP V0, ${:03X}", nnn),
+            Instruction::RNDVxImm { x, imm } => write!(f, "RND V{:01X}, {:02X}", x, imm),
+            Instruction::DRWVxVyn { x, y, n } => write!(f, "DRW V{:01X}, V{:01X}, {:01X}", x, y, n),
+            Instruction::SKPVx(x) => write!(f, "SKP V{:01X}", x),
+            Instruction::SKNPVx(x) => write!(f, "SKNP V{:01X}", x),
+            Instruction::LDVxDT(x) => write!(f, "LD V{:01X}, DT", x),
+            Instruction::LDVxK(x) => write!(f, "LD V{:01X}, K", x),
+            Instruction::LDDTVx(x) => write!(f, "LD DT, V{:01X}", x),
+            Instruction::LDSTVx(x) => write!(f, "LD ST, V{:01X}", x),
+            Instruction::ADDIVx(x) => write!(f, "ADD I, V{:01X}", x),
+            Instruction::LDFVx(x) => write!(f, "LD F, V{:01X}", x),
+            Instruction::LDBVx(x) => write!(f, "LD B, V{:01X}", x),
+            Instruction::LDIVx(x) => write!(f, "LD [I], V{:01X}", x),
+            Instruction::LDVxI(x) => write!(f, "LD V{:01X}, [I]", x),
+            Instruction::DRWVxVy0 { x, y } => write!(f, "DRW V{:01X}, V{:01X}, 0", x, y),
+            Instruction::LDHFVx(x) => write!(f, "LD HF, V{:01X}", x),
+            Instruction::LDRV(x) => write!(f, "LD R, V{:01X}", x),
+            Instruction::LDVxR(x) => write!(f, "LD V{:01X}, R", x),
+            Instruction::SCD(n) => write!(f, "SCD {:01X}", n),
+            Instruction::SCR => write!(f, "SCR"),
+            Instruction::SCL => write!(f, "SCL"),
+            Instruction::EXIT => write!(f, "EXIT"),
+            Instruction::LOW => write!(f, "LOW"),
+            Instruction::HIGH => write!(f, "HIGH"),
+            Instruction::Unknown(opcode) => match opcode & 0xF000 {
+                0x8000 => write!(f, "0x8???"),
+                0xE000 => write!(f, "0xEX??"),
+                0xF000 => write!(f, "0xF???"),
+                _ => write!(f, "???"),
+            },
+        }
+    }
+}
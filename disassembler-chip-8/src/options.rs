@@ -0,0 +1,247 @@
+use crate::analysis::{self, Analysis};
+use crate::opcodes::{Dialect, Syntax};
+
+pub const USAGE: &str = "\
+Use: cargo run -- [disassemble | lint | decompile] [options] <file.ch8 | ->
+     cargo run -- diff [options] <old.ch8> <new.ch8>
+
+Options:
+  -l, --load <addr>      address the ROM is loaded at (default 0x200, 0x600 for ETI-660)
+  -s, --start <offset>   first ROM byte to process
+  -e, --end <offset>     ROM byte to stop at (exclusive)
+  -E, --entry <addr>     entry point for code analysis, may be repeated
+  -d, --dialect <name>   chip8 | schip (default chip8)
+  -S, --syntax <name>    cowgod | octo (default cowgod)
+  -x, --hexdump          show raw bytes next to each instruction
+  -a, --annotate         explain each instruction, with known register and I values
+  -o, --output <file>    write to a file instead of stdout
+
+Numbers are decimal or hexadecimal with a 0x or $ prefix, up to 0xFFFF. A file name of - reads stdin.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Disassemble,
+    Lint,
+    Decompile,
+    Diff,
+}
+
+/// Command-line options shared by every command.
+pub struct Options {
+    pub command: Command,
+    /// Input files, `-` for stdin.
+    pub inputs: Vec<String>,
+    /// Address the first byte of the ROM is loaded at.
+    pub load_addr: usize,
+    /// Offset of the first ROM byte to process.
+    pub start: usize,
+    /// Offset of the ROM byte to stop at, or the end of the ROM.
+    pub end: Option<usize>,
+    /// Entry points for code analysis. Defaults to the load address.
+    pub entries: Vec<usize>,
+    pub dialect: Dialect,
+    pub syntax: Syntax,
+    pub hexdump: bool,
+    pub annotate: bool,
+    /// Output file, or stdout.
+    pub output: Option<String>,
+}
+
+impl Options {
+    /// Address of the first processed byte.
+    pub fn base_addr(&self) -> usize {
+        self.load_addr + self.start
+    }
+
+    /// Returns the processed part of the ROM.
+    pub fn slice<'a>(&self, rom: &'a [u8]) -> &'a [u8] {
+        let end = self.end.unwrap_or(rom.len()).min(rom.len());
+        &rom[self.start.min(end)..end]
+    }
+
+    /// Entry points for code analysis of a slice of `len` bytes. Without explicit
+    /// entries, execution starts at the load address, or at the slice if that is outside it.
+    pub fn entry_points(&self, len: usize) -> Vec<usize> {
+        if !self.entries.is_empty() {
+            return self.entries.clone();
+        }
+        let base = self.base_addr();
+        if self.load_addr >= base && self.load_addr < base + len {
+            vec![self.load_addr]
+        } else {
+            vec![base]
+        }
+    }
+
+    /// Runs the control-flow analysis on a slice returned by `slice`.
+    pub fn analyze(&self, rom: &[u8]) -> Analysis {
+        analysis::analyze(rom, self.base_addr(), &self.entry_points(rom.len()), self.dialect)
+    }
+}
+
+/// Why the command line was not run.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Help was asked for.
+    Help,
+    /// The arguments are invalid, with the reason.
+    Invalid(String),
+}
+
+impl From<String> for ParseError {
+    fn from(reason: String) -> Self {
+        ParseError::Invalid(reason)
+    }
+}
+
+/// Largest address or offset accepted, so that sums of them cannot overflow.
+const MAX_NUMBER: usize = 0xFFFF;
+
+/// Parses a decimal number or a hexadecimal one prefixed with `0x` or `$`,
+/// up to `MAX_NUMBER`.
+fn parse_number(value: &str) -> Result<usize, String> {
+    let parsed = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16)
+    } else {
+        value.parse()
+    };
+    match parsed {
+        Ok(number) if number <= MAX_NUMBER => Ok(number),
+        Ok(_) => Err(format!("Number out of range (0 to 0x{:04X}): {}", MAX_NUMBER, value)),
+        Err(_) => Err(format!("Invalid number: {}", value)),
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Options, ParseError> {
+    let mut options = Options {
+        command: Command::Disassemble,
+        inputs: Vec::new(),
+        load_addr: 0x200,
+        start: 0,
+        end: None,
+        entries: Vec::new(),
+        dialect: Dialect::Chip8,
+        syntax: Syntax::Cowgod,
+        hexdump: false,
+        annotate: false,
+        output: None,
+    };
+
+    let mut args = args.iter();
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "disassemble" if first => options.command = Command::Disassemble,
+            "lint" if first => options.command = Command::Lint,
+            "decompile" if first => options.command = Command::Decompile,
+            "diff" if first => options.command = Command::Diff,
+            "-l" | "--load" => options.load_addr = parse_number(value()?)?,
+            "-s" | "--start" => options.start = parse_number(value()?)?,
+            "-e" | "--end" => options.end = Some(parse_number(value()?)?),
+            "-E" | "--entry" => options.entries.push(parse_number(value()?)?),
+            "-d" | "--dialect" => {
+                options.dialect = match value()?.as_str() {
+                    "chip8" => Dialect::Chip8,
+                    "schip" => Dialect::Schip,
+                    other => return Err(format!("Unknown dialect: {}", other).into()),
+                }
+            }
+            "-S" | "--syntax" => {
+                options.syntax = match value()?.as_str() {
+                    "cowgod" => Syntax::Cowgod,
+                    "octo" => Syntax::Octo,
+                    other => return Err(format!("Unknown syntax: {}", other).into()),
+                }
+            }
+            "-x" | "--hexdump" => options.hexdump = true,
+            "-a" | "--annotate" => options.annotate = true,
+            "-o" | "--output" => options.output = Some(value()?.clone()),
+            "-h" | "--help" => return Err(ParseError::Help),
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("Unknown option: {}", flag).into()),
+            input => options.inputs.push(input.to_string()),
+        }
+        first = false;
+    }
+
+    let expected = if options.command == Command::Diff { 2 } else { 1 };
+    if options.inputs.len() != expected {
+        return Err(format!("Expected {} input file(s), got {}", expected, options.inputs.len()).into());
+    }
+    if options.inputs.iter().filter(|input| *input == "-").count() > 1 {
+        return Err("Only one input can be read from stdin".to_string().into());
+    }
+    if options.end.is_some_and(|end| end < options.start) {
+        return Err("End offset is before the start offset".to_string().into());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Options, ParseError> {
+        parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn invalid(args: &str) -> String {
+        match parse_args(args) {
+            Err(ParseError::Invalid(reason)) => reason,
+            Err(ParseError::Help) => panic!("{} asked for help", args),
+            Ok(_) => panic!("{} parsed", args),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse_args("rom.ch8").unwrap();
+        assert_eq!(options.command, Command::Disassemble);
+        assert_eq!(options.inputs, ["rom.ch8"]);
+        assert_eq!((options.load_addr, options.start, options.end), (0x200, 0, None));
+        assert_eq!(options.entry_points(0x100), [0x200]);
+    }
+
+    #[test]
+    fn commands_and_options() {
+        let options = parse_args("lint -l 0x600 -s $10 --end 256 -E 0x610 -E 1552 -d schip -S octo -x -a -o out.txt -").unwrap();
+        assert_eq!(options.command, Command::Lint);
+        assert_eq!(options.inputs, ["-"]);
+        assert_eq!((options.load_addr, options.start, options.end), (0x600, 0x10, Some(256)));
+        assert_eq!(options.base_addr(), 0x610);
+        assert_eq!(options.entries, [0x610, 0x610]);
+        assert_eq!((options.dialect, options.syntax), (Dialect::Schip, Syntax::Octo));
+        assert!(options.hexdump && options.annotate);
+        assert_eq!(options.output.as_deref(), Some("out.txt"));
+
+        let options = parse_args("diff old.ch8 new.ch8").unwrap();
+        assert_eq!((options.command, options.inputs), (Command::Diff, vec!["old.ch8".to_string(), "new.ch8".to_string()]));
+        // a command name after the first argument is a file name
+        assert_eq!(parse_args("lint lint").unwrap().inputs, ["lint"]);
+    }
+
+    #[test]
+    fn help() {
+        assert!(matches!(parse_args("--help"), Err(ParseError::Help)));
+        assert!(matches!(parse_args("diff -h"), Err(ParseError::Help)));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(invalid("-q rom.ch8"), "Unknown option: -q");
+        assert_eq!(invalid("rom.ch8 -l"), "Missing value for -l");
+        assert_eq!(invalid("-l 0xZZ rom.ch8"), "Invalid number: 0xZZ");
+        assert_eq!(invalid("-d chip48 rom.ch8"), "Unknown dialect: chip48");
+        assert_eq!(invalid("diff rom.ch8"), "Expected 2 input file(s), got 1");
+        assert_eq!(invalid("diff - -"), "Only one input can be read from stdin");
+        assert_eq!(invalid("-s 8 -e 4 rom.ch8"), "End offset is before the start offset");
+    }
+
+    #[test]
+    fn rejects_numbers_that_could_overflow_addresses() {
+        assert_eq!(parse_args("-l 0xFFFF -s 0xFFFF rom.ch8").unwrap().base_addr(), 0x1FFFE);
+        assert_eq!(invalid("-l 0x10000 rom.ch8"), "Number out of range (0 to 0xFFFF): 0x10000");
+        assert_eq!(invalid("-s 18446744073709551615 rom.ch8"), "Number out of range (0 to 0xFFFF): 18446744073709551615");
+        assert_eq!(invalid("-E $FFFFFFFF rom.ch8"), "Number out of range (0 to 0xFFFF): $FFFFFFFF");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::error::WavError;
+use crate::format::AudioFormat;
+use crate::utils::WAVHeader;
+
+const IMA_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107,
+    118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894,
+    6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+const IMA_INDEX_STEPS: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+const MS_ADAPTATION: [i32; 16] = [230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230];
+
+/// Predictor coefficient pairs used when the `fmt ` chunk does not list its own.
+const MS_COEFFICIENTS: [(i32, i32); 7] = [(256, 0), (512, -256), (0, 0), (192, 64), (240, 0), (460, -208), (392, -232)];
+
+fn le_i16(bytes: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Bytes of the per-channel block header.
+fn block_header_size(codec: AudioFormat) -> usize {
+    if codec == AudioFormat::MsAdpcm { 7 } else { 4 }
+}
+
+/// Frames decoded from `len` bytes of a block, which is shorter than
+/// `block_align` only for the last block of a file.
+fn block_frames(codec: AudioFormat, channels: usize, len: usize) -> usize {
+    let body = len.saturating_sub(block_header_size(codec) * channels);
+    match codec {
+        AudioFormat::MsAdpcm if len >= 7 * channels => 2 + body * 2 / channels,
+        AudioFormat::ImaAdpcm if len >= 4 * channels => 1 + body / (4 * channels) * 8,
+        _ => 0,
+    }
+}
+
+/// Frames per full block: `wSamplesPerBlock` from the `fmt ` extension,
+/// which may not exceed what `block_align` holds, or that maximum when absent.
+pub fn samples_per_block(header: &WAVHeader) -> Result<usize, WavError> {
+    let codec = header.codec();
+    let capacity = block_frames(codec, header.num_channels as usize, header.block_align as usize);
+    if capacity == 0 {
+        return Err(WavError::InconsistentSizes);
+    }
+    // The block header alone holds two MS ADPCM samples and one IMA sample.
+    let minimum = if codec == AudioFormat::MsAdpcm { 2 } else { 1 };
+    match header.extension.get(0..2) {
+        Some(field) => match u16::from_le_bytes([field[0], field[1]]) as usize {
+            0 => Ok(capacity),
+            frames if (minimum..=capacity).contains(&frames) => Ok(frames),
+            _ => Err(WavError::InconsistentSizes),
+        },
+        None => Ok(capacity),
+    }
+}
+
+/// Number of frames `data_len` bytes of ADPCM data decode to.
+pub fn frame_count(header: &WAVHeader, data_len: u64) -> Result<u64, WavError> {
+    let per_block = samples_per_block(header)? as u64;
+    let block_align = header.block_align as u64;
+    let partial = block_frames(header.codec(), header.num_channels as usize, (data_len % block_align) as usize) as u64;
+    Ok(data_len / block_align * per_block + partial.min(per_block))
+}
+
+/// Coefficient pairs listed in an MS ADPCM `fmt ` extension, after
+/// `wSamplesPerBlock` and `wNumCoef`, or the standard seven.
+fn ms_coefficients(extension: &[u8]) -> Vec<(i32, i32)> {
+    if extension.len() < 4 {
+        return MS_COEFFICIENTS.to_vec();
+    }
+    let count = u16::from_le_bytes([extension[2], extension[3]]) as usize;
+    let pairs: Vec<(i32, i32)> = extension[4..]
+        .chunks_exact(4)
+        .take(count)
+        .map(|pair| (le_i16(pair, 0) as i32, le_i16(pair, 2) as i32))
+        .collect();
+    if pairs.is_empty() { MS_COEFFICIENTS.to_vec() } else { pairs }
+}
+
+fn decode_ima_block(block: &[u8], channels: usize, frames: usize, out: &mut [i16]) {
+    for channel in 0..channels {
+        let mut predictor = le_i16(block, channel * 4) as i32;
+        let mut index = (block[channel * 4 + 2] as i32).clamp(0, 88);
+        out[channel] = predictor as i16;
+        // Each channel's nibbles come in 4-byte groups of eight samples,
+        // the groups of all channels interleaved, low nibble first.
+        for frame in 1..frames {
+            let n = frame - 1;
+            let byte = block[4 * channels + (n / 8 * channels + channel) * 4 + n % 8 / 2];
+            let nibble = if n % 2 == 0 { byte & 0x0F } else { byte >> 4 } as i32;
+            let step = IMA_STEPS[index as usize];
+            let mut diff = step >> 3;
+            if nibble & 1 != 0 {
+                diff += step >> 2;
+            }
+            if nibble & 2 != 0 {
+                diff += step >> 1;
+            }
+            if nibble & 4 != 0 {
+                diff += step;
+            }
+            predictor = if nibble & 8 != 0 { predictor - diff } else { predictor + diff }.clamp(-32768, 32767);
+            index = (index + IMA_INDEX_STEPS[nibble as usize & 7]).clamp(0, 88);
+            out[frame * channels + channel] = predictor as i16;
+        }
+    }
+}
+
+fn decode_ms_block(block: &[u8], channels: usize, frames: usize, coefficients: &[(i32, i32)], out: &mut [i16]) -> Result<(), WavError> {
+    let mut states = Vec::with_capacity(channels);
+    for channel in 0..channels {
+        let &(coef1, coef2) = coefficients.get(block[channel] as usize).ok_or(WavError::InconsistentSizes)?;
+        let delta = le_i16(block, channels + 2 * channel) as i32;
+        let sample1 = le_i16(block, 3 * channels + 2 * channel) as i32;
+        let sample2 = le_i16(block, 5 * channels + 2 * channel) as i32;
+        out[channel] = sample2 as i16;
+        out[channels + channel] = sample1 as i16;
+        states.push((coef1, coef2, delta, sample1, sample2));
+    }
+    // Nibbles follow high nibble first, alternating between channels.
+    for n in 0..(frames - 2) * channels {
+        let byte = block[7 * channels + n / 2];
+        let nibble = if n % 2 == 0 { byte >> 4 } else { byte & 0x0F } as i32;
+        let (coef1, coef2, delta, sample1, sample2) = &mut states[n % channels];
+        let signed = if nibble >= 8 { nibble - 16 } else { nibble };
+        let predicted = ((*sample1 * *coef1 + *sample2 * *coef2) / 256 + signed * *delta).clamp(-32768, 32767);
+        *delta = (MS_ADAPTATION[nibble as usize] * *delta / 256).max(16);
+        *sample2 = *sample1;
+        *sample1 = predicted;
+        out[n + 2 * channels] = predicted as i16;
+    }
+    Ok(())
+}
+
+/// Decodes IMA or MS ADPCM blocks of `block_align` bytes to interleaved
+/// 16-bit samples. A short last block yields the samples it holds.
+pub fn decode(header: &WAVHeader, data: &[u8]) -> Result<Vec<i16>, WavError> {
+    let codec = header.codec();
+    let channels = header.num_channels as usize;
+    let per_block = samples_per_block(header)?;
+    let coefficients = ms_coefficients(&header.extension);
+    let mut out = Vec::new();
+    for block in data.chunks(header.block_align as usize) {
+        let frames = block_frames(codec, channels, block.len()).min(per_block);
+        if frames == 0 {
+            break;
+        }
+        let start = out.len();
+        out.resize(start + frames * channels, 0);
+        match codec {
+            AudioFormat::MsAdpcm => decode_ms_block(block, channels, frames, &coefficients, &mut out[start..])?,
+            _ => decode_ima_block(block, channels, frames, &mut out[start..]),
+        }
+    }
+    Ok(out)
+}
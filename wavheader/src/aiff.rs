@@ -0,0 +1,275 @@
+use crate::error::WavError;
+use crate::format::AudioFormat;
+use crate::riff::{self, Chunk};
+use crate::utils::WAVHeader;
+
+/// Timestamp of the AIFF-C draft that every `FVER` chunk holds.
+pub const AIFC_VERSION: u32 = 0xA280_5140;
+
+fn be_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Returns true if the file starts with a `FORM` header of type `AIFF` or `AIFC`.
+pub fn is_aiff(prefix: &[u8]) -> bool {
+    prefix.len() >= 12 && prefix[0..4] == *b"FORM" && (prefix[8..12] == *b"AIFF" || prefix[8..12] == *b"AIFC")
+}
+
+/// Walks the chunks of a `FORM` file of `len` bytes like `riff::walk`,
+/// except that sizes are big-endian and there is no `ds64` chunk.
+pub fn walk(len: u64, mut read_header: impl FnMut(usize) -> Option<[u8; 8]>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset as u64 + 8 <= len {
+        let Some(header) = read_header(offset) else {
+            break;
+        };
+        let chunk = Chunk { id: be_u32(&header, 0), offset, size: be_u32(&header, 4) as u64 };
+        offset = chunk.data_offset().saturating_add(chunk.size as usize).saturating_add(chunk.size as usize & 1);
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Walks the chunks of a `FORM` file held in memory.
+pub fn walk_chunks(bytes: &[u8]) -> Vec<Chunk> {
+    walk(bytes.len() as u64, |offset| bytes.get(offset..offset + 8).map(|header| header.try_into().unwrap()))
+}
+
+/// Reads an 80-bit IEEE 754 extended-precision number, as the `COMM` sample rate is stored.
+pub fn read_extended(bytes: &[u8]) -> f64 {
+    let exponent = (be_u16(bytes, 0) & 0x7FFF) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if bytes[0] & 0x80 != 0 { -value } else { value }
+}
+
+/// Writes a whole number as an 80-bit extended-precision number.
+pub fn write_extended(value: u32) -> [u8; 10] {
+    let mut bytes = [0; 10];
+    if value > 0 {
+        let shift = (value as u64).leading_zeros();
+        bytes[0..2].copy_from_slice(&((16383 + 63 - shift) as u16).to_be_bytes());
+        bytes[2..10].copy_from_slice(&((value as u64) << shift).to_be_bytes());
+    }
+    bytes
+}
+
+/// The codec of an AIFF-C compression type, and the bits per sample it
+/// stores when that is not what the `COMM` chunk says.
+fn compression_codec(id: u32, bits: u16) -> Result<(AudioFormat, u16), WavError> {
+    match &id.to_be_bytes() {
+        b"NONE" | b"twos" | b"sowt" | b"raw " | b"in24" | b"in32" => Ok((AudioFormat::Pcm, bits)),
+        b"fl32" | b"FL32" => Ok((AudioFormat::IeeeFloat, 32)),
+        b"fl64" | b"FL64" => Ok((AudioFormat::IeeeFloat, 64)),
+        // G.711 files give the decoded size, usually 16, in `sampleSize`.
+        b"alaw" | b"ALAW" => Ok((AudioFormat::ALaw, 8)),
+        b"ulaw" | b"ULAW" => Ok((AudioFormat::MuLaw, 8)),
+        _ => Err(WavError::UnsupportedCompression(id)),
+    }
+}
+
+/// The AIFF-C compression type and name for a codec, or `None` for PCM,
+/// which plain AIFF holds.
+pub fn compression_type(format: AudioFormat, bits_per_sample: u16) -> Option<(&'static [u8; 4], &'static str)> {
+    match format {
+        AudioFormat::IeeeFloat if bits_per_sample > 32 => Some((b"fl64", "64-bit floating point")),
+        AudioFormat::IeeeFloat => Some((b"fl32", "32-bit floating point")),
+        AudioFormat::ALaw => Some((b"alaw", "ALaw 2:1")),
+        AudioFormat::MuLaw => Some((b"ulaw", "uLaw 2:1")),
+        _ => None,
+    }
+}
+
+/// Builds a header from an AIFF or AIFF-C file: the `COMM` chunk gives the
+/// format, and the samples start after the offset and block size fields of
+/// the `SSND` chunk. `prefix` holds at least the 12-byte `FORM` header and
+/// `ssnd_bytes` the first 8 bytes of the `SSND` payload.
+pub fn header(prefix: &[u8], chunks: &[Chunk], comm_bytes: &[u8], ssnd_bytes: &[u8], file_len: u64) -> Result<WAVHeader, WavError> {
+    if prefix.len() < 12 {
+        return Err(WavError::Truncated { expected: 12, got: prefix.len() });
+    }
+    let format = be_u32(prefix, 8);
+    let comm = riff::find_chunk(chunks, b"COMM").ok_or(WavError::MissingChunk(riff::fourcc(b"COMM")))?;
+    let ssnd = riff::find_chunk(chunks, b"SSND").ok_or(WavError::MissingChunk(riff::fourcc(b"SSND")))?;
+    if comm_bytes.len() < 18 {
+        return Err(WavError::Truncated { expected: comm.data_offset() + 18, got: file_len as usize });
+    }
+    if ssnd_bytes.len() < 8 {
+        return Err(WavError::Truncated { expected: ssnd.data_offset() + 8, got: file_len as usize });
+    }
+
+    let num_channels = be_u16(comm_bytes, 0);
+    let sample_size = be_u16(comm_bytes, 6);
+    let sample_rate = read_extended(&comm_bytes[8..18]);
+    let compression = if format == riff::fourcc(b"AIFC") {
+        if comm_bytes.len() < 22 {
+            return Err(WavError::Truncated { expected: comm.data_offset() + 22, got: file_len as usize });
+        }
+        Some(be_u32(comm_bytes, 18))
+    } else {
+        None
+    };
+    let (codec, bits_per_sample) = compression_codec(compression.unwrap_or(riff::fourcc(b"NONE")), sample_size)?;
+    if num_channels == 0 || bits_per_sample == 0 || !(1.0..=u32::MAX as f64).contains(&sample_rate) {
+        return Err(WavError::InconsistentSizes);
+    }
+
+    let block_align = (num_channels as u32 * (bits_per_sample as u32).div_ceil(8)).min(u16::MAX as u32) as u16;
+    let sample_rate = sample_rate.round() as u32;
+    let offset = be_u32(ssnd_bytes, 0) as u64;
+    Ok(WAVHeader {
+        chunk_id: be_u32(prefix, 0),
+        chunk_size: be_u32(prefix, 4) as u64,
+        format,
+        sub_chunk_1_id: comm.id,
+        sub_chunk_1_size: comm.size as u32,
+        audio_format: codec.code(),
+        num_channels,
+        sample_rate,
+        byte_rate: sample_rate.saturating_mul(block_align as u32),
+        block_align,
+        bits_per_sample,
+        sub_chunk_2_id: ssnd.id,
+        sub_chunk_2_size: ssnd.size.saturating_sub(8 + offset),
+        ds64_offset: None,
+        sample_count: Some(be_u32(comm_bytes, 2) as u64),
+        fact_samples: None,
+        fmt_offset: comm.data_offset(),
+        data_offset: ssnd.data_offset() + 8 + offset as usize,
+        extension_size: 0,
+        valid_bits_per_sample: bits_per_sample,
+        channel_mask: 0,
+        sub_format: None,
+        extension: Vec::new(),
+        compression,
+    })
+}
+
+/// Converts between AIFF and WAV sample layouts: reverses the bytes of
+/// each sample and flips the sign bit of 8-bit PCM, which AIFF stores signed.
+/// Applying it twice gives back the input.
+pub fn swap_sample_order(data: &[u8], width: usize, codec: AudioFormat) -> Vec<u8> {
+    match (codec, width) {
+        (AudioFormat::Pcm, 1) => data.iter().map(|byte| byte ^ 0x80).collect(),
+        (_, 0 | 1) => data.to_vec(),
+        _ => data.chunks_exact(width).flat_map(|sample| sample.iter().rev().copied()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::format::Container;
+    use crate::writer::{WavSpec, WavWriter};
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = [&id[..], &(data.len() as u32).to_be_bytes()].concat();
+        bytes.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    /// A `FORM` file of type `kind` holding `chunks`.
+    fn form(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        [&b"FORM"[..], &(body.len() as u32 + 4).to_be_bytes(), kind, &body].concat()
+    }
+
+    /// A `COMM` payload; `compression` adds the AIFF-C type and an empty name.
+    fn comm(channels: u16, frames: u32, bits: u16, rate: u32, compression: Option<&[u8; 4]>) -> Vec<u8> {
+        let mut comm = [&channels.to_be_bytes()[..], &frames.to_be_bytes(), &bits.to_be_bytes(), &write_extended(rate)].concat();
+        if let Some(compression) = compression {
+            comm.extend_from_slice(compression);
+            comm.extend_from_slice(&[0, 0]);
+        }
+        comm
+    }
+
+    fn parse(bytes: &[u8]) -> Result<WAVHeader, WavError> {
+        let chunks = walk_chunks(bytes);
+        let payload = |id: &[u8; 4]| riff::find_chunk(&chunks, id).map_or(&[][..], |chunk| &bytes[chunk.data_offset()..(chunk.data_offset() + chunk.size as usize).min(bytes.len())]);
+        header(bytes, &chunks, payload(b"COMM"), payload(b"SSND"), bytes.len() as u64)
+    }
+
+    #[test]
+    fn extended_round_trips_sample_rates() {
+        assert_eq!(write_extended(44100), [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(write_extended(48000), [0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]);
+        for rate in [0, 1, 8000, 22050, 44100, 48000, 96000, 192000, u32::MAX] {
+            assert_eq!(read_extended(&write_extended(rate)), rate as f64);
+        }
+        // negative, and not a whole number
+        assert_eq!(read_extended(&[0xC0, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]), -44100.0);
+        assert_eq!(read_extended(&[0x3F, 0xFE, 0xC0, 0, 0, 0, 0, 0, 0, 0]), 0.75);
+    }
+
+    #[test]
+    fn reads_comm_and_ssnd() {
+        // an SSND offset of 4 puts the samples 4 bytes after the block size field
+        let ssnd = [&[0, 0, 0, 4, 0, 0, 0, 0][..], &[0xAA; 4], &[1, 2, 3, 4, 5, 6, 7, 8]].concat();
+        let bytes = form(b"AIFF", &[chunk(b"COMM", &comm(2, 2, 16, 44100, None)), chunk(b"SSND", &ssnd)]);
+        let header = parse(&bytes).unwrap();
+        assert_eq!(header.format, riff::fourcc(b"AIFF"));
+        assert_eq!(header.audio_format, AudioFormat::Pcm.code());
+        assert_eq!((header.num_channels, header.bits_per_sample, header.sample_rate), (2, 16, 44100));
+        assert_eq!((header.block_align, header.byte_rate), (4, 176400));
+        assert_eq!(header.sample_count, Some(2));
+        assert_eq!(header.fmt_offset, 20);
+        assert_eq!(&bytes[header.data_offset..][..header.sub_chunk_2_size as usize], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(header.compression, None);
+    }
+
+    #[test]
+    fn reads_aifc_compression_types() {
+        let ssnd = [&[0; 8][..], &[0; 8]].concat();
+        let aifc = |compression: &[u8; 4], bits: u16| {
+            let fver = chunk(b"FVER", &AIFC_VERSION.to_be_bytes());
+            parse(&form(b"AIFC", &[fver, chunk(b"COMM", &comm(1, 2, bits, 48000, Some(compression))), chunk(b"SSND", &ssnd)])).unwrap()
+        };
+        let header = aifc(b"fl32", 32);
+        assert_eq!(header.format, riff::fourcc(b"AIFC"));
+        assert_eq!(header.audio_format, AudioFormat::IeeeFloat.code());
+        assert_eq!((header.bits_per_sample, header.sample_rate, header.block_align), (32, 48000, 4));
+        assert_eq!(header.compression, Some(riff::fourcc(b"fl32")));
+        // G.711 gives the decoded size
+        let header = aifc(b"ulaw", 16);
+        assert_eq!((header.audio_format, header.bits_per_sample), (AudioFormat::MuLaw.code(), 8));
+        assert!(matches!(parse(&form(b"AIFC", &[chunk(b"COMM", &comm(1, 2, 16, 48000, Some(b"ima4"))), chunk(b"SSND", &ssnd)])), Err(WavError::UnsupportedCompression(_))));
+    }
+
+    #[test]
+    fn rejects_missing_and_short_chunks() {
+        let ssnd = chunk(b"SSND", &[0; 8]);
+        assert!(matches!(parse(&form(b"AIFF", std::slice::from_ref(&ssnd))), Err(WavError::MissingChunk(_))));
+        assert!(matches!(parse(&form(b"AIFF", &[chunk(b"COMM", &[0; 16]), ssnd.clone()])), Err(WavError::Truncated { .. })));
+        // AIFF-C needs the compression type
+        assert!(matches!(parse(&form(b"AIFC", &[chunk(b"COMM", &comm(1, 0, 16, 8000, None)), ssnd.clone()])), Err(WavError::Truncated { .. })));
+        assert!(matches!(parse(&form(b"AIFF", &[chunk(b"COMM", &comm(1, 0, 16, 0, None)), ssnd])), Err(WavError::InconsistentSizes)));
+    }
+
+    #[test]
+    fn written_float_files_read_back_as_aifc() {
+        let spec = WavSpec { format: AudioFormat::IeeeFloat, channels: 2, sample_rate: 96000, bits_per_sample: 32, container: Container::Aiff };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_bytes(&[0; 16]).unwrap();
+        let bytes = writer.finalize().unwrap().into_inner();
+        let header = WAVHeader::parse(&bytes).unwrap();
+        assert!(header.is_aiff());
+        assert_eq!(header.format, riff::fourcc(b"AIFC"));
+        assert_eq!(header.compression, Some(riff::fourcc(b"fl32")));
+        assert_eq!((header.audio_format, header.num_channels, header.sample_rate), (AudioFormat::IeeeFloat.code(), 2, 96000));
+        assert_eq!((header.sample_count, header.sub_chunk_2_size), (Some(2), 16));
+    }
+}
@@ -0,0 +1,206 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use crate::error::WavError;
+use crate::format::{AudioFormat, Container};
+use crate::reader::{WavReader, BLOCK_FRAMES};
+use crate::resample::{Quality, Resampler};
+use crate::samples::Buffer;
+use crate::utils::WAVHeader;
+use crate::writer::{WavSpec, WavWriter};
+
+/// How to rearrange the channels of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remix {
+    /// Averages every channel into one, e.g. a stereo downmix.
+    Downmix,
+    /// Takes each output channel from a source channel, by index. Repeating
+    /// an index duplicates a channel (`[0, 0]` turns mono into stereo), and a
+    /// subset or permutation extracts or reorders channels.
+    Select(Vec<usize>),
+}
+
+impl Remix {
+    /// Number of channels produced.
+    pub fn channels(&self) -> usize {
+        match self {
+            Remix::Downmix => 1,
+            Remix::Select(indexes) => indexes.len(),
+        }
+    }
+
+    /// Remixes interleaved samples. Indexes must be in range; see `Conversion::spec`.
+    pub fn apply(&self, buffer: &Buffer<f64>) -> Buffer<f64> {
+        let data = match self {
+            Remix::Downmix => buffer.frames().map(|frame| frame.iter().sum::<f64>() / frame.len() as f64).collect(),
+            Remix::Select(indexes) => buffer.frames().flat_map(|frame| indexes.iter().map(|&index| frame[index])).collect(),
+        };
+        Buffer::new(self.channels(), data)
+    }
+}
+
+/// Triangular-PDF dither: the sum of two independent uniform values of up to
+/// half a step each, added before quantizing so that the rounding error is
+/// noise rather than distortion correlated with the signal.
+pub struct Dither {
+    /// Quantization step in the [-1.0, 1.0] range.
+    step: f64,
+    /// xorshift64* state; any fixed non-zero seed will do.
+    state: u64,
+}
+
+impl Dither {
+    /// Dither for quantizing to `bits`-bit integers.
+    pub fn new(bits: u16) -> Self {
+        Dither { step: 1.0 / (1u64 << (bits - 1)) as f64, state: 0x2545_F491_4F6C_DD1D }
+    }
+
+    /// A uniform value in [-0.5, 0.5).
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let value = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        value as f64 / (1u64 << 53) as f64 - 0.5
+    }
+
+    pub fn apply(&mut self, data: &mut [f64]) {
+        for sample in data {
+            *sample += (self.uniform() + self.uniform()) * self.step;
+        }
+    }
+}
+
+/// Target of a conversion. Fields left as `None` keep the source's values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conversion {
+    /// `Pcm`, `IeeeFloat`, `ALaw` or `MuLaw`. When `None`, an ADPCM source,
+    /// or a float or G.711 one asked for an integer size it cannot hold,
+    /// becomes PCM.
+    pub format: Option<AudioFormat>,
+    /// When `None` and the codec changes: 32 for float, 8 for G.711, and 24
+    /// for PCM from float or 16 otherwise.
+    pub bits_per_sample: Option<u16>,
+    /// Adds TPDF dither before quantizing to integer PCM.
+    pub dither: bool,
+    pub remix: Option<Remix>,
+    /// Resamples to this rate.
+    pub sample_rate: Option<u32>,
+    pub quality: Quality,
+    /// WAV or AIFF. When `None`, the source's, or for `convert_file` the one
+    /// named by the output's extension.
+    pub container: Option<Container>,
+}
+
+impl Conversion {
+    /// The format of the converted file for a source with `header`.
+    pub fn spec(&self, header: &WAVHeader) -> Result<WavSpec, WavError> {
+        let source = header.codec();
+        let format = match (self.format, self.bits_per_sample) {
+            (Some(format), _) => format,
+            (None, Some(8 | 16 | 24)) if source == AudioFormat::IeeeFloat => AudioFormat::Pcm,
+            (None, Some(bits)) if matches!(source, AudioFormat::ALaw | AudioFormat::MuLaw) && bits != 8 => AudioFormat::Pcm,
+            (None, _) if matches!(source, AudioFormat::ImaAdpcm | AudioFormat::MsAdpcm) => AudioFormat::Pcm,
+            (None, _) => source,
+        };
+        let bits_per_sample = match self.bits_per_sample {
+            Some(bits) => bits,
+            None if format == source => header.bits_per_sample,
+            None => match format {
+                AudioFormat::IeeeFloat => 32,
+                AudioFormat::ALaw | AudioFormat::MuLaw => 8,
+                _ if source == AudioFormat::IeeeFloat => 24,
+                _ => 16,
+            },
+        };
+        if let Some(Remix::Select(indexes)) = &self.remix
+            && let Some(&index) = indexes.iter().find(|&&index| index >= header.num_channels as usize)
+        {
+            return Err(WavError::NoSuchChannel { index, channels: header.num_channels });
+        }
+        let channels = self.remix.as_ref().map_or(header.num_channels as usize, Remix::channels) as u16;
+        let sample_rate = self.sample_rate.unwrap_or(header.sample_rate);
+        if sample_rate == 0 {
+            return Err(WavError::InconsistentSizes);
+        }
+        let container = self.container.unwrap_or(if header.is_aiff() { Container::Aiff } else { Container::Wave });
+        Ok(WavSpec { format, channels, sample_rate, bits_per_sample, container })
+    }
+}
+
+/// Streams the samples of `reader` into a new file written to `inner`, and
+/// returns the writer once the file is finalized.
+pub fn convert<R: Read + Seek, W: Write + Seek>(reader: &mut WavReader<R>, inner: W, conversion: &Conversion) -> Result<W, WavError> {
+    let spec = conversion.spec(reader.header())?;
+    let bits = reader.header().container_bits();
+    let mut writer = WavWriter::new(inner, spec)?;
+    let mut dither = (conversion.dither && spec.format == AudioFormat::Pcm).then(|| Dither::new(spec.sample_width() as u16 * 8));
+    let source_rate = reader.header().sample_rate;
+    let mut resampler = (spec.sample_rate != source_rate)
+        .then(|| Resampler::new(spec.channels as usize, source_rate, spec.sample_rate, conversion.quality));
+    let mut write = |mut buffer: Buffer<f64>| {
+        if let Some(dither) = &mut dither {
+            dither.apply(&mut buffer.data);
+        }
+        writer.write_f64(&buffer.data)
+    };
+    while let Some(samples) = reader.read_block(BLOCK_FRAMES)? {
+        let mut buffer = samples.to_f64(bits);
+        if let Some(remix) = &conversion.remix {
+            buffer = remix.apply(&buffer);
+        }
+        match &mut resampler {
+            Some(resampler) => write(resampler.process(&buffer))?,
+            None => write(buffer)?,
+        }
+    }
+    if let Some(resampler) = &mut resampler {
+        write(resampler.finish())?;
+    }
+    writer.finalize()
+}
+
+/// Fails if `output` names the existing file `input`, which creating the
+/// output would truncate before it is read.
+pub(crate) fn check_output(input: &str, output: &str) -> Result<(), WavError> {
+    if Path::new(output).exists() && fs::canonicalize(input)? == fs::canonicalize(output)? {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "output file is the input file").into());
+    }
+    Ok(())
+}
+
+/// `conversion`, writing the container named by the extension of `output`
+/// when it does not name one itself.
+pub(crate) fn for_output(conversion: &Conversion, output: &str) -> Conversion {
+    Conversion { container: conversion.container.or_else(|| Container::from_path(output)), ..conversion.clone() }
+}
+
+/// Converts the file at `input` into a new file at `output`, which must not
+/// be the input itself. Returns the format written.
+pub fn convert_file(input: &str, output: &str, conversion: &Conversion) -> Result<WavSpec, WavError> {
+    check_output(input, output)?;
+    let conversion = for_output(conversion, output);
+    let mut reader = WavReader::open(input)?;
+    let spec = conversion.spec(reader.header())?;
+    convert(&mut reader, BufWriter::new(File::create(output)?), &conversion)?;
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dither_is_triangular_within_one_step() {
+        let step = 1.0 / 32768.0;
+        let mut data = vec![0.0; 100_000];
+        Dither::new(16).apply(&mut data);
+        assert!(data.iter().all(|s| s.abs() < step));
+        let mean = data.iter().sum::<f64>() / data.len() as f64;
+        let variance = data.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / data.len() as f64;
+        // The sum of two uniform values of a step's width has variance step² / 6.
+        assert!(mean.abs() < step / 100.0, "mean {}", mean / step);
+        assert!((variance / (step * step / 6.0) - 1.0).abs() < 0.02, "variance {}", variance / (step * step));
+    }
+}
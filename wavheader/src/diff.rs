@@ -0,0 +1,221 @@
+use std::io::{self, Read, Seek};
+
+use crate::error::WavError;
+use crate::reader::{WavReader, BLOCK_FRAMES, MAX_METADATA_CHUNK};
+use crate::riff;
+use crate::utils::WAVHeader;
+
+/// A header field whose value differs between two files.
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+/// A chunk that differs between two files, paired by ID and order of
+/// appearance. A size is `None` when the file lacks the chunk.
+pub struct ChunkDiff {
+    pub id: u32,
+    pub left: Option<u64>,
+    pub right: Option<u64>,
+}
+
+/// How the decoded samples of two files compare, over the frames both hold.
+pub struct SampleDiff {
+    pub left_frames: u64,
+    pub right_frames: u64,
+    /// First frame where any channel differs.
+    pub first_difference: Option<u64>,
+    /// Largest difference between two samples, relative to full scale.
+    pub max_difference: f64,
+    /// Energy of the left file over that of the difference, in dB; infinite
+    /// when the samples are equal.
+    pub snr: f64,
+    /// The frame counts, sample types and every sample value match.
+    pub identical: bool,
+}
+
+/// The header fields as shown in a report, by name.
+fn header_fields(header: &WAVHeader) -> Vec<(&'static str, String)> {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    vec![
+        ("ChunkID", riff::fourcc_str(header.chunk_id)),
+        ("ChunkSize", header.chunk_size.to_string()),
+        ("Format", riff::fourcc_str(header.format)),
+        ("SubChunk1ID", riff::fourcc_str(header.sub_chunk_1_id)),
+        ("SubChunk1Size", header.sub_chunk_1_size.to_string()),
+        ("AudioFormat", format!("{} ({})", header.audio_format, header.codec())),
+        ("NumChannels", header.num_channels.to_string()),
+        ("SampleRate", header.sample_rate.to_string()),
+        ("ByteRate", header.byte_rate.to_string()),
+        ("BlockAlign", header.block_align.to_string()),
+        ("BitsPerSample", header.bits_per_sample.to_string()),
+        ("ExtensionSize", header.extension_size.to_string()),
+        ("ValidBitsPerSample", header.valid_bits_per_sample.to_string()),
+        ("ChannelMask", format!("0x{:08X}", header.channel_mask)),
+        ("SubFormat", optional(header.sub_format.map(|guid| guid.to_string()))),
+        ("CompressionType", optional(header.compression.map(riff::fourcc_str))),
+        ("SubChunk2ID", riff::fourcc_str(header.sub_chunk_2_id)),
+        ("SubChunk2Size", header.sub_chunk_2_size.to_string()),
+        ("SampleCount", optional(header.sample_count.map(|count| count.to_string()))),
+        ("FactSamples", optional(header.fact_samples.map(|count| count.to_string()))),
+    ]
+}
+
+/// Header fields whose values differ, in report order.
+pub fn compare_headers(left: &WAVHeader, right: &WAVHeader) -> Vec<FieldDiff> {
+    header_fields(left)
+        .into_iter()
+        .zip(header_fields(right))
+        .filter(|((_, left), (_, right))| left != right)
+        .map(|((field, left), (_, right))| FieldDiff { field, left, right })
+        .collect()
+}
+
+/// A chunk ID, size and payload, or no payload for a chunk too large to read.
+type Payload = (u32, u64, Option<Vec<u8>>);
+
+/// Returns true for padding and for chunks whose contents follow from the
+/// header or the container, which a lossless rewrite may add or drop.
+fn is_structural(id: u32) -> bool {
+    matches!(&id.to_be_bytes(), b"JUNK" | b"junk" | b"PAD " | b"FLLR" | b"ds64" | b"fact" | b"FVER")
+}
+
+/// Payloads of the chunks other than the format and sample data chunks and
+/// structural ones, in file order.
+fn metadata_chunks<R: Read + Seek>(reader: &mut WavReader<R>) -> io::Result<Vec<Payload>> {
+    let header = reader.header();
+    let (format_id, data_id) = (header.sub_chunk_1_id, header.sub_chunk_2_id);
+    let chunks: Vec<_> = reader.chunks().iter().filter(|chunk| chunk.id != format_id && chunk.id != data_id && !is_structural(chunk.id)).copied().collect();
+    let mut payloads = Vec::new();
+    for chunk in &chunks {
+        let data = if chunk.size <= MAX_METADATA_CHUNK { Some(reader.read_chunk(chunk)?) } else { None };
+        payloads.push((chunk.id, chunk.size, data));
+    }
+    Ok(payloads)
+}
+
+/// Chunks, other than the format, sample data and structural chunks, that one file
+/// lacks or whose contents differ. The nth chunk of an ID in one file is
+/// paired with the nth of that ID in the other.
+pub fn compare_chunks<R: Read + Seek, S: Read + Seek>(left: &mut WavReader<R>, right: &mut WavReader<S>) -> io::Result<Vec<ChunkDiff>> {
+    let left = metadata_chunks(left)?;
+    let mut right: Vec<_> = metadata_chunks(right)?.into_iter().map(Some).collect();
+    let mut diffs = Vec::new();
+    for (id, size, data) in left {
+        let pair = right.iter_mut().find(|chunk| chunk.as_ref().is_some_and(|(right_id, _, _)| *right_id == id));
+        match pair.and_then(Option::take) {
+            Some((_, right_size, right_data)) => {
+                if size != right_size || data.is_none() || data != right_data {
+                    diffs.push(ChunkDiff { id, left: Some(size), right: Some(right_size) });
+                }
+            }
+            None => diffs.push(ChunkDiff { id, left: Some(size), right: None }),
+        }
+    }
+    diffs.extend(right.into_iter().flatten().map(|(id, size, _)| ChunkDiff { id, left: None, right: Some(size) }));
+    Ok(diffs)
+}
+
+/// Compares the decoded samples of two files with the same channel count,
+/// block by block, as values relative to full scale.
+pub fn compare_samples<R: Read + Seek, S: Read + Seek>(left: &mut WavReader<R>, right: &mut WavReader<S>) -> Result<SampleDiff, WavError> {
+    let channels = left.header().num_channels;
+    if right.header().num_channels != channels {
+        return Err(WavError::FormatMismatch);
+    }
+    let (left_bits, right_bits) = (left.header().container_bits(), right.header().container_bits());
+    let mut diff = SampleDiff {
+        left_frames: left.frame_count(),
+        right_frames: right.frame_count(),
+        first_difference: None,
+        max_difference: 0.0,
+        snr: f64::INFINITY,
+        identical: left.frame_count() == right.frame_count(),
+    };
+    let (mut signal, mut noise) = (0.0, 0.0);
+    let mut frame = 0;
+    left.seek_frame(0)?;
+    right.seek_frame(0)?;
+    while let (Some(left_block), Some(right_block)) = (left.read_block(BLOCK_FRAMES)?, right.read_block(BLOCK_FRAMES)?) {
+        if left_block != right_block {
+            diff.identical = false;
+        }
+        let (a, b) = (left_block.to_f64(left_bits), right_block.to_f64(right_bits));
+        for (left_frame, right_frame) in a.frames().zip(b.frames()) {
+            for (&x, &y) in left_frame.iter().zip(right_frame) {
+                let difference = (x - y).abs();
+                if difference > 0.0 && diff.first_difference.is_none() {
+                    diff.first_difference = Some(frame);
+                }
+                diff.max_difference = diff.max_difference.max(difference);
+                signal += x * x;
+                noise += difference * difference;
+            }
+            frame += 1;
+        }
+        // Blocks of both files hold the same frames until one file ends.
+        if a.frame_count() != b.frame_count() {
+            break;
+        }
+    }
+    if noise > 0.0 {
+        diff.snr = 10.0 * (signal / noise).log10();
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::convert::{self, Conversion};
+    use crate::format::{AudioFormat, Container};
+    use crate::writer::{WavSpec, WavWriter};
+
+    /// A mono 32-bit float WAVE file holding a ramp, followed by `chunks`.
+    fn file(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let spec = WavSpec { format: AudioFormat::IeeeFloat, channels: 1, sample_rate: 44100, bits_per_sample: 32, container: Container::Wave };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_f64(&(0..64).map(|i| i as f64 / 64.0).collect::<Vec<_>>()).unwrap();
+        for (id, data) in chunks {
+            writer.add_chunk(riff::fourcc(id), data.to_vec());
+        }
+        writer.finalize().unwrap().into_inner()
+    }
+
+    fn reader(bytes: &[u8]) -> WavReader<Cursor<&[u8]>> {
+        WavReader::new(Cursor::new(bytes)).unwrap()
+    }
+
+    fn chunk_diffs(left: &[u8], right: &[u8]) -> Vec<(String, Option<u64>, Option<u64>)> {
+        let diffs = compare_chunks(&mut reader(left), &mut reader(right)).unwrap();
+        diffs.iter().map(|diff| (riff::fourcc_str(diff.id), diff.left, diff.right)).collect()
+    }
+
+    #[test]
+    fn padding_and_structural_chunks_are_not_compared() {
+        let info: &[u8] = b"INFOINAM\x06\x00\x00\x00title\x00";
+        let left = file(&[(b"LIST", info)]);
+        let right = file(&[(b"fact", &64u32.to_le_bytes()), (b"LIST", info), (b"FLLR", &[0; 32])]);
+        assert!(chunk_diffs(&left, &right).is_empty());
+
+        let other = file(&[(b"LIST", b"INFOINAM\x06\x00\x00\x00other\x00"), (b"iXML", b"<BWFXML/>")]);
+        assert_eq!(chunk_diffs(&left, &other), [("LIST".to_string(), Some(18), Some(18)), ("iXML".to_string(), None, Some(9))]);
+    }
+
+    #[test]
+    fn lossless_conversion_to_aiff_compares_identical() {
+        let wave = file(&[]);
+        let aiff = convert::convert(&mut reader(&wave), Cursor::new(Vec::new()), &Conversion { container: Some(Container::Aiff), ..Conversion::default() })
+            .unwrap()
+            .into_inner();
+        // the WAVE file has a JUNK chunk and the AIFF-C file an FVER chunk
+        assert_eq!(reader(&aiff).header().compression, Some(riff::fourcc(b"fl32")));
+        assert!(chunk_diffs(&wave, &aiff).is_empty());
+        let samples = compare_samples(&mut reader(&wave), &mut reader(&aiff)).unwrap();
+        assert!(samples.identical && samples.first_difference.is_none());
+        assert_eq!((samples.left_frames, samples.right_frames), (64, 64));
+    }
+}
@@ -0,0 +1,420 @@
+use std::io::{self, Read, Seek, Write};
+use std::ops::Range;
+
+use crate::convert::{self, Conversion};
+use crate::error::WavError;
+use crate::format::Container;
+use crate::metadata::{self, CuePoint, Sampler};
+use crate::reader::{WavReader, BLOCK_FRAMES, MAX_METADATA_CHUNK};
+use crate::riff::{self, Chunk};
+use crate::stats::SilenceDetector;
+use crate::writer::WavWriter;
+
+/// A point in a file, as a frame index or a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Frame(u64),
+    Seconds(f64),
+}
+
+impl Position {
+    /// Parses `48000` as a frame index, and `1.5s` or `250ms` as a time.
+    pub fn parse(text: &str) -> Option<Self> {
+        let seconds = |value: &str, scale: f64| value.parse::<f64>().ok().filter(|s| *s >= 0.0).map(|s| Position::Seconds(s * scale));
+        if let Some(value) = text.strip_suffix("ms") {
+            seconds(value, 0.001)
+        } else if let Some(value) = text.strip_suffix('s') {
+            seconds(value, 1.0)
+        } else {
+            text.parse().ok().map(Position::Frame)
+        }
+    }
+
+    pub fn to_frame(self, sample_rate: u32) -> u64 {
+        match self {
+            Position::Frame(frame) => frame,
+            Position::Seconds(seconds) => (seconds * sample_rate as f64).round() as u64,
+        }
+    }
+}
+
+/// Returns true for chunks copied unchanged into an edited file: not the
+/// ones the writer builds, padding, or ones holding sample positions.
+fn is_copied(id: u32, data: &[u8]) -> bool {
+    match &id.to_be_bytes() {
+        b"fmt " | b"data" | b"fact" | b"ds64" | b"JUNK" | b"junk" | b"PAD " | b"FLLR" | b"cue " | b"smpl" => false,
+        b"LIST" => !data.starts_with(b"adtl"),
+        _ => true,
+    }
+}
+
+/// Reads the chunks to carry into a file that starts at frame `start` of
+/// the source, advancing the `bext` time reference by `start`. None are
+/// carried from an AIFF source or into an AIFF file, which has no place for
+/// RIFF metadata.
+fn copied_chunks<R: Read + Seek>(reader: &mut WavReader<R>, start: u64, container: Container) -> Result<Vec<(u32, Vec<u8>)>, WavError> {
+    if reader.header().is_aiff() || container == Container::Aiff {
+        return Ok(Vec::new());
+    }
+    let chunks: Vec<Chunk> = reader.chunks().to_vec();
+    let mut copied = Vec::new();
+    for chunk in chunks.iter().filter(|chunk| chunk.size <= MAX_METADATA_CHUNK && is_copied(chunk.id, b"")) {
+        let mut data = reader.read_chunk(chunk)?;
+        if !is_copied(chunk.id, &data) {
+            continue;
+        }
+        if chunk.id == riff::fourcc(b"bext") && data.len() >= 346 {
+            let time_reference = u64::from_le_bytes(data[338..346].try_into().unwrap()) + start;
+            data[338..346].copy_from_slice(&time_reference.to_le_bytes());
+        }
+        copied.push((chunk.id, data));
+    }
+    Ok(copied)
+}
+
+/// Cue points inside `range`, moved so that `range.start` lands on `offset`,
+/// with region lengths cut at the end of the range.
+fn shifted_cues(cues: &[CuePoint], range: &Range<u64>, offset: u64) -> Vec<CuePoint> {
+    cues.iter()
+        .filter(|cue| range.contains(&(cue.position as u64)))
+        .map(|cue| {
+            let shift = |value: u32| (value as u64).saturating_sub(range.start) + offset;
+            let mut cue = cue.clone();
+            cue.length = cue.length.map(|length| length.min((range.end - cue.position as u64) as u32));
+            cue.position = shift(cue.position) as u32;
+            cue.sample_offset = shift(cue.sample_offset) as u32;
+            cue.chunk_id = riff::fourcc(b"data");
+            cue.chunk_start = 0;
+            cue.block_start = 0;
+            cue
+        })
+        .collect()
+}
+
+/// The sampler with only its loops inside `range`, moved so that
+/// `range.start` lands on frame 0.
+fn shifted_sampler(sampler: &Sampler, range: &Range<u64>) -> Sampler {
+    let mut sampler = sampler.clone();
+    // Loop ends are inclusive.
+    sampler.loops.retain(|entry| entry.start as u64 >= range.start && (entry.end as u64) < range.end);
+    for entry in &mut sampler.loops {
+        entry.start -= range.start as u32;
+        entry.end -= range.start as u32;
+    }
+    sampler
+}
+
+/// Copies frames `range` of `reader` to `writer` without converting them.
+fn copy_frames<R: Read + Seek, W: Write + Seek>(reader: &mut WavReader<R>, writer: &mut WavWriter<W>, range: Range<u64>) -> Result<(), WavError> {
+    reader.seek_frame(range.start)?;
+    let mut frame = range.start;
+    while frame < range.end {
+        let Some(samples) = reader.read_block(((range.end - frame) as usize).min(BLOCK_FRAMES))? else {
+            break;
+        };
+        frame += samples.frame_count() as u64;
+        writer.write_samples(&samples)?;
+    }
+    Ok(())
+}
+
+/// Writes frames `range` of `reader` to a new file with the source's
+/// metadata, in the container named by the extension of `output`. Cue
+/// points and sampler loops outside the range are dropped. An AIFF output
+/// keeps the samples only. Compressed sources are written as
+/// 16-bit PCM.
+fn write_range<R: Read + Seek>(reader: &mut WavReader<R>, output: &str, range: Range<u64>) -> Result<(), WavError> {
+    let spec = convert::for_output(&Conversion::default(), output).spec(reader.header())?;
+    let mut writer = WavWriter::create(output, spec)?;
+    copy_frames(reader, &mut writer, range.clone())?;
+    for (id, data) in copied_chunks(reader, range.start, spec.container)? {
+        writer.add_chunk(id, data);
+    }
+    let source = reader.metadata()?;
+    if spec.container == Container::Wave {
+        if let Some(sampler) = &source.sampler {
+            writer.add_chunk(riff::fourcc(b"smpl"), metadata::encode_smpl(&shifted_sampler(sampler, &range)));
+        }
+        let cues = shifted_cues(&source.cues, &range, 0);
+        if !cues.is_empty() {
+            for (id, data) in metadata::encode_cues(&cues) {
+                writer.add_chunk(id, data);
+            }
+        }
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Writes frames `start` to `end` (or the end of the file) of `input` to
+/// `output`. Returns the frame range written.
+pub fn trim(input: &str, output: &str, start: Position, end: Option<Position>) -> Result<Range<u64>, WavError> {
+    convert::check_output(input, output)?;
+    let mut reader = WavReader::open(input)?;
+    let rate = reader.header().sample_rate;
+    let frames = reader.frame_count();
+    let range = start.to_frame(rate).min(frames)..end.map_or(frames, |end| end.to_frame(rate).min(frames));
+    if range.is_empty() {
+        return Err(WavError::InvalidRange { start: range.start, end: range.end });
+    }
+    write_range(&mut reader, output, range.clone())?;
+    Ok(range)
+}
+
+/// Frame positions of the cue points of a file.
+pub fn cue_points<R: Read + Seek>(reader: &mut WavReader<R>) -> Result<Vec<u64>, WavError> {
+    Ok(reader.metadata()?.cues.iter().map(|cue| cue.position as u64).collect())
+}
+
+/// Middles of the silent runs of a file, as found by `SilenceDetector`,
+/// leaving out silence at the very start or end.
+pub fn silence_points<R: Read + Seek>(reader: &mut WavReader<R>, threshold: f64, min_frames: u64) -> Result<Vec<u64>, WavError> {
+    let bits = reader.header().container_bits();
+    let frames = reader.frame_count();
+    let mut detector = SilenceDetector::new(threshold, min_frames);
+    reader.seek_frame(0)?;
+    while let Some(samples) = reader.read_block(BLOCK_FRAMES)? {
+        detector.push(&samples.to_f64(bits));
+    }
+    Ok(detector
+        .finish()
+        .into_iter()
+        .filter(|run| run.start > 0 && run.end < frames)
+        .map(|run| (run.start + run.end) / 2)
+        .collect())
+}
+
+/// Splits `input` at frame `points` into files named by `output`, which is
+/// given the 1-based segment number. Points outside the file are ignored.
+/// Returns the files written with their frame ranges.
+pub fn split(input: &str, points: &[u64], output: impl Fn(usize) -> String) -> Result<Vec<(String, Range<u64>)>, WavError> {
+    let mut reader = WavReader::open(input)?;
+    let frames = reader.frame_count();
+    let mut bounds: Vec<u64> = points.iter().copied().filter(|&point| point > 0 && point < frames).collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds.insert(0, 0);
+    bounds.push(frames);
+
+    let mut written = Vec::new();
+    for (index, pair) in bounds.windows(2).enumerate() {
+        let path = output(index + 1);
+        convert::check_output(input, &path)?;
+        write_range(&mut reader, &path, pair[0]..pair[1])?;
+        written.push((path, pair[0]..pair[1]));
+    }
+    Ok(written)
+}
+
+/// Joins `inputs`, at least one, which must share format, channel count and
+/// sample rate, into `output`. Metadata, sampler loops included, comes from
+/// the first file; the cue points of all of them are kept and renumbered,
+/// unless `output` is AIFF.
+/// Returns the number of frames written.
+pub fn concat(inputs: &[String], output: &str) -> Result<u64, WavError> {
+    let Some(first) = inputs.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no input files").into());
+    };
+    for input in inputs {
+        convert::check_output(input, output)?;
+    }
+    let conversion = convert::for_output(&Conversion::default(), output);
+    let mut reader = WavReader::open(first)?;
+    let spec = conversion.spec(reader.header())?;
+    let mut writer = WavWriter::create(output, spec)?;
+    let chunks = copied_chunks(&mut reader, 0, spec.container)?;
+    let sampler = reader.metadata()?.sampler;
+
+    let mut cues = Vec::new();
+    let mut offset = 0;
+    for (index, input) in inputs.iter().enumerate() {
+        if index > 0 {
+            reader = WavReader::open(input)?;
+            if conversion.spec(reader.header())? != spec {
+                return Err(WavError::FormatMismatch);
+            }
+        }
+        let frames = reader.frame_count();
+        copy_frames(&mut reader, &mut writer, 0..frames)?;
+        cues.extend(shifted_cues(&reader.metadata()?.cues, &(0..frames), offset));
+        offset += frames;
+    }
+
+    for (id, data) in chunks {
+        writer.add_chunk(id, data);
+    }
+    if let Some(sampler) = sampler.filter(|_| spec.container == Container::Wave) {
+        writer.add_chunk(riff::fourcc(b"smpl"), metadata::encode_smpl(&sampler));
+    }
+    if !cues.is_empty() && spec.container == Container::Wave {
+        for (index, cue) in cues.iter_mut().enumerate() {
+            cue.id = index as u32 + 1;
+        }
+        for (id, data) in metadata::encode_cues(&cues) {
+            writer.add_chunk(id, data);
+        }
+    }
+    writer.finalize()?;
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+    use crate::format::AudioFormat;
+    use crate::metadata::SampleLoop;
+    use crate::samples::Samples;
+    use crate::writer::WavSpec;
+
+    /// A path in the temporary directory, unique to this process.
+    fn temp(name: &str) -> String {
+        let path: PathBuf = std::env::temp_dir().join(format!("wavheader-edit-{}-{}", process::id(), name));
+        path.to_string_lossy().to_string()
+    }
+
+    fn cue(id: u32, position: u32, label: &str, length: Option<u32>) -> CuePoint {
+        CuePoint {
+            id,
+            position,
+            chunk_id: riff::fourcc(b"data"),
+            chunk_start: 0,
+            block_start: 0,
+            sample_offset: position,
+            label: Some(label.to_string()),
+            note: None,
+            length,
+        }
+    }
+
+    fn sample_loop(id: u32, start: u32, end: u32) -> SampleLoop {
+        SampleLoop { id, loop_type: 0, start, end, fraction: 0, play_count: 0 }
+    }
+
+    /// Writes a mono 16-bit WAVE file of 100 frames, each holding its index,
+    /// with an INFO list, padding, cues at 10, 40 (a 30-frame region) and 90,
+    /// and three sampler loops.
+    fn source(name: &str) -> String {
+        let spec = WavSpec { format: AudioFormat::Pcm, channels: 1, sample_rate: 8000, bits_per_sample: 16, container: Container::Wave };
+        let path = temp(name);
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        let samples: Vec<u8> = (0..100i16).flat_map(|sample| sample.to_le_bytes()).collect();
+        writer.write_bytes(&samples).unwrap();
+        writer.add_chunk(riff::fourcc(b"LIST"), b"INFOINAM\x06\x00\x00\x00title\x00".to_vec());
+        writer.add_chunk(riff::fourcc(b"JUNK"), vec![0; 8]);
+        let cues = [cue(1, 10, "a", None), cue(2, 40, "b", Some(30)), cue(3, 90, "c", None)];
+        for (id, data) in metadata::encode_cues(&cues) {
+            writer.add_chunk(id, data);
+        }
+        let sampler = Sampler {
+            manufacturer: 0,
+            product: 0,
+            sample_period: 125_000,
+            midi_unity_note: 60,
+            midi_pitch_fraction: 0,
+            smpte_format: 0,
+            smpte_offset: 0,
+            loops: vec![sample_loop(1, 20, 50), sample_loop(2, 35, 45), sample_loop(3, 50, 59)],
+        };
+        writer.add_chunk(riff::fourcc(b"smpl"), metadata::encode_smpl(&sampler));
+        writer.finalize().unwrap();
+        path
+    }
+
+    /// The samples, chunk ids, cues as (id, position, label, length) and
+    /// sampler loops as (id, start, end) of a file, which is then removed.
+    #[allow(clippy::type_complexity)]
+    fn contents(path: &str) -> (Vec<i16>, Vec<String>, Vec<(u32, u32, String, Option<u32>)>, Vec<(u32, u32, u32)>) {
+        let mut reader = WavReader::open(path).unwrap();
+        let Some(Samples::I16(samples)) = reader.read_block(1000).unwrap() else {
+            panic!("expected 16-bit samples");
+        };
+        let ids = reader.chunks().iter().map(|chunk| riff::fourcc_str(chunk.id)).collect();
+        let metadata = reader.metadata().unwrap();
+        let cues = metadata.cues.iter().map(|cue| (cue.id, cue.position, cue.label.clone().unwrap_or_default(), cue.length)).collect();
+        let loops = metadata.sampler.map_or(Vec::new(), |sampler| sampler.loops.iter().map(|entry| (entry.id, entry.start, entry.end)).collect());
+        fs::remove_file(path).unwrap();
+        (samples.data, ids, cues, loops)
+    }
+
+    #[test]
+    fn trim_shifts_cues_and_loops_inside_the_range() {
+        let input = source("trim.wav");
+        let output = temp("trim-out.wav");
+        assert_eq!(trim(&input, &output, Position::Frame(30), Some(Position::Frame(60))).unwrap(), 30..60);
+        let (samples, ids, cues, loops) = contents(&output);
+        fs::remove_file(&input).unwrap();
+
+        assert_eq!(samples, (30..60).collect::<Vec<i16>>());
+        // the trailing padding is dropped, and the cues and loops rebuilt
+        assert_eq!(ids, ["JUNK", "fmt ", "data", "LIST", "smpl", "cue ", "LIST"]);
+        // the region is cut at the end of the range
+        assert_eq!(cues, [(2, 10, "b".to_string(), Some(20))]);
+        // the loop from 20 starts before the range; the last ends on its last frame
+        assert_eq!(loops, [(2, 5, 15), (3, 20, 29)]);
+    }
+
+    #[test]
+    fn split_at_cues_starts_each_file_on_its_cue() {
+        let input = source("split.wav");
+        let mut reader = WavReader::open(&input).unwrap();
+        let points = cue_points(&mut reader).unwrap();
+        let written = split(&input, &points, |number| temp(&format!("split-{}.wav", number))).unwrap();
+        let ranges: Vec<Range<u64>> = written.iter().map(|(_, range)| range.clone()).collect();
+        assert_eq!(ranges, [0..10, 10..40, 40..90, 90..100]);
+
+        let files: Vec<_> = written.iter().map(|(path, _)| contents(path)).collect();
+        fs::remove_file(&input).unwrap();
+        assert_eq!(files[1].0[0], 10);
+        let cues: Vec<_> = files.iter().map(|file| file.2.clone()).collect();
+        assert_eq!(
+            cues,
+            [
+                vec![],
+                vec![(1, 0, "a".to_string(), None)],
+                vec![(2, 0, "b".to_string(), Some(30))],
+                vec![(3, 0, "c".to_string(), None)],
+            ]
+        );
+        let loops: Vec<_> = files.iter().map(|file| file.3.clone()).collect();
+        assert_eq!(loops, [vec![], vec![], vec![(3, 10, 19)], vec![]]);
+    }
+
+    #[test]
+    fn concat_renumbers_the_cues_of_every_file() {
+        let inputs = [source("concat-1.wav"), source("concat-2.wav")];
+        let output = temp("concat-out.wav");
+        assert_eq!(concat(&inputs, &output).unwrap(), 200);
+        let (samples, _, cues, loops) = contents(&output);
+        for input in &inputs {
+            fs::remove_file(input).unwrap();
+        }
+
+        assert_eq!(samples[100..103], [0, 1, 2]);
+        let positions: Vec<(u32, u32, &str)> = cues.iter().map(|(id, position, label, _)| (*id, *position, label.as_str())).collect();
+        assert_eq!(positions, [(1, 10, "a"), (2, 40, "b"), (3, 90, "c"), (4, 110, "a"), (5, 140, "b"), (6, 190, "c")]);
+        // the loops of the first file, unmoved
+        assert_eq!(loops, [(1, 20, 50), (2, 35, 45), (3, 50, 59)]);
+    }
+
+    #[test]
+    fn aiff_output_keeps_the_samples_only() {
+        let input = source("aiff.wav");
+        let output = temp("aiff-out.aiff");
+        trim(&input, &output, Position::Frame(30), None).unwrap();
+        let (samples, ids, cues, loops) = contents(&output);
+        assert_eq!(samples, (30..100).collect::<Vec<i16>>());
+        assert_eq!(ids, ["COMM", "SSND"]);
+        assert!(cues.is_empty() && loops.is_empty());
+
+        let output = temp("concat-out.aiff");
+        concat(&[input.clone(), input.clone()], &output).unwrap();
+        let (samples, ids, _, _) = contents(&output);
+        fs::remove_file(&input).unwrap();
+        assert_eq!(samples.len(), 200);
+        assert_eq!(ids, ["COMM", "SSND"]);
+    }
+}
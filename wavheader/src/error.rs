@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::riff;
+
+/// Everything that can go wrong reading a WAV file.
+#[derive(Debug)]
+pub enum WavError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file does not start with `RIFF`.
+    NotRiff,
+    /// The RIFF form type is not `WAVE`.
+    NotWave,
+    /// The file ends before a structure it must contain.
+    Truncated { expected: usize, got: usize },
+    /// A required chunk, such as `fmt ` or `data`, is absent.
+    MissingChunk(u32),
+    /// Header fields contradict each other so the data cannot be interpreted.
+    InconsistentSizes,
+    /// The `fmt ` chunk names an audio format this tool does not handle.
+    UnsupportedFormat(u16),
+    /// The `COMM` chunk of an AIFF-C file names a compression type this tool does not handle.
+    UnsupportedCompression(u32),
+    /// A channel index names a channel the file does not have.
+    NoSuchChannel { index: usize, channels: u16 },
+    /// A frame range is empty or starts past its end.
+    InvalidRange { start: u64, end: u64 },
+    /// Files to be joined differ in format, channels or sample rate.
+    FormatMismatch,
+}
+
+impl WavError {
+    /// Process exit code for the error, following the BSD sysexits convention.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WavError::Io(_) => 74,
+            _ => 65,
+        }
+    }
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WavError::Io(e) => write!(f, "I/O error: {}", e),
+            WavError::NotRiff => write!(f, "not a RIFF file"),
+            WavError::NotWave => write!(f, "RIFF file is not of type WAVE"),
+            WavError::Truncated { expected, got } => {
+                write!(f, "file is truncated: expected {} bytes, got {}", expected, got)
+            }
+            WavError::MissingChunk(id) => write!(f, "missing '{}' chunk", riff::fourcc_str(*id)),
+            WavError::InconsistentSizes => write!(f, "header sizes are inconsistent"),
+            WavError::UnsupportedFormat(format) => write!(f, "unsupported audio format 0x{:04X}", format),
+            WavError::UnsupportedCompression(id) => write!(f, "unsupported AIFF-C compression type '{}'", riff::fourcc_str(*id)),
+            WavError::NoSuchChannel { index, channels } => {
+                write!(f, "channel {} does not exist in a {}-channel file", index + 1, channels)
+            }
+            WavError::InvalidRange { start, end } => write!(f, "frame range {}..{} is empty", start, end),
+            WavError::FormatMismatch => write!(f, "files differ in format, channels or sample rate"),
+        }
+    }
+}
+
+impl Error for WavError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WavError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WavError {
+    fn from(e: io::Error) -> Self {
+        WavError::Io(e)
+    }
+}
@@ -0,0 +1,552 @@
+use std::io::{self, Write};
+
+use wavheader::error::WavError;
+use wavheader::metadata::Metadata;
+use wavheader::riff;
+use wavheader::stats;
+use wavheader::utils::WAVHeader;
+
+use crate::options::{Command, Options};
+use crate::report::{Report, SampleInfo};
+
+/// Quotes a string for JSON.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats a number for JSON; infinities and NaN, such as the level of silence in dB, become `null`.
+fn json_number(value: f64) -> String {
+    if value.is_finite() { format!("{}", value) } else { "null".to_string() }
+}
+
+/// Joins `"key": value` pairs into an object.
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}: {}", json_string(key), value)).collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn json_header(header: &WAVHeader, file_len: u64) -> String {
+    json_object(&[
+        ("chunk_id", json_string(&riff::fourcc_str(header.chunk_id))),
+        ("chunk_size", header.chunk_size.to_string()),
+        ("format", json_string(&riff::fourcc_str(header.format))),
+        ("sub_chunk_1_id", json_string(&riff::fourcc_str(header.sub_chunk_1_id))),
+        ("sub_chunk_1_size", header.sub_chunk_1_size.to_string()),
+        ("audio_format", header.audio_format.to_string()),
+        ("audio_format_name", json_string(header.format_tag().name())),
+        ("codec", json_string(header.codec().name())),
+        ("num_channels", header.num_channels.to_string()),
+        ("sample_rate", header.sample_rate.to_string()),
+        ("byte_rate", header.byte_rate.to_string()),
+        ("block_align", header.block_align.to_string()),
+        ("bits_per_sample", header.bits_per_sample.to_string()),
+        ("extension_size", header.extension_size.to_string()),
+        ("valid_bits_per_sample", header.valid_bits_per_sample.to_string()),
+        ("channel_mask", header.channel_mask.to_string()),
+        ("sub_format", header.sub_format.map_or("null".to_string(), |guid| json_string(&guid.to_string()))),
+        ("compression", header.compression.map_or("null".to_string(), |id| json_string(&riff::fourcc_str(id)))),
+        ("sub_chunk_2_id", json_string(&riff::fourcc_str(header.sub_chunk_2_id))),
+        ("sub_chunk_2_size", header.sub_chunk_2_size.to_string()),
+        ("duration", stats::duration(header, file_len).map_or("null".to_string(), json_number)),
+    ])
+}
+
+fn json_metadata(metadata: &Metadata) -> String {
+    let tags: Vec<String> = metadata
+        .info
+        .iter()
+        .map(|tag| {
+            json_object(&[
+                ("id", json_string(&riff::fourcc_str(tag.id))),
+                ("name", tag.name().map_or("null".to_string(), json_string)),
+                ("value", json_string(&tag.value)),
+            ])
+        })
+        .collect();
+    let mut fields = vec![("info", format!("[{}]", tags.join(", ")))];
+    if let Some(bext) = &metadata.bext {
+        let umid: String = bext.umid.iter().map(|b| format!("{:02X}", b)).collect();
+        fields.push((
+            "bext",
+            json_object(&[
+                ("description", json_string(&bext.description)),
+                ("originator", json_string(&bext.originator)),
+                ("originator_reference", json_string(&bext.originator_reference)),
+                ("origination_date", json_string(&bext.origination_date)),
+                ("origination_time", json_string(&bext.origination_time)),
+                ("time_reference", bext.time_reference.to_string()),
+                ("version", bext.version.to_string()),
+                ("umid", if bext.has_umid() { json_string(&umid) } else { "null".to_string() }),
+                ("loudness_value", bext.loudness_value.to_string()),
+                ("loudness_range", bext.loudness_range.to_string()),
+                ("max_true_peak_level", bext.max_true_peak_level.to_string()),
+                ("max_momentary_loudness", bext.max_momentary_loudness.to_string()),
+                ("max_short_term_loudness", bext.max_short_term_loudness.to_string()),
+                ("coding_history", json_string(&bext.coding_history)),
+            ]),
+        ));
+    }
+    if let Some(xml) = &metadata.ixml {
+        fields.push(("ixml", json_string(xml)));
+    }
+    let cues: Vec<String> = metadata
+        .cues
+        .iter()
+        .map(|cue| {
+            json_object(&[
+                ("id", cue.id.to_string()),
+                ("position", cue.position.to_string()),
+                ("sample_offset", cue.sample_offset.to_string()),
+                ("length", cue.length.map_or("null".to_string(), |length| length.to_string())),
+                ("label", cue.label.as_deref().map_or("null".to_string(), json_string)),
+                ("note", cue.note.as_deref().map_or("null".to_string(), json_string)),
+            ])
+        })
+        .collect();
+    fields.push(("cues", format!("[{}]", cues.join(", "))));
+    if let Some(sampler) = &metadata.sampler {
+        let loops: Vec<String> = sampler
+            .loops
+            .iter()
+            .map(|sample_loop| {
+                json_object(&[
+                    ("id", sample_loop.id.to_string()),
+                    ("type", sample_loop.loop_type.to_string()),
+                    ("start", sample_loop.start.to_string()),
+                    ("end", sample_loop.end.to_string()),
+                    ("fraction", sample_loop.fraction.to_string()),
+                    ("play_count", sample_loop.play_count.to_string()),
+                ])
+            })
+            .collect();
+        fields.push((
+            "smpl",
+            json_object(&[
+                ("manufacturer", sampler.manufacturer.to_string()),
+                ("product", sampler.product.to_string()),
+                ("sample_period", sampler.sample_period.to_string()),
+                ("midi_unity_note", sampler.midi_unity_note.to_string()),
+                ("midi_pitch_fraction", sampler.midi_pitch_fraction.to_string()),
+                ("smpte_format", sampler.smpte_format.to_string()),
+                ("smpte_offset", sampler.smpte_offset.to_string()),
+                ("loops", format!("[{}]", loops.join(", "))),
+            ]),
+        ));
+    }
+    json_object(&fields)
+}
+
+fn json_report(report: &Report, options: &Options) -> String {
+    let mut fields = vec![("path", json_string(&report.path)), ("file_size", report.file_len.to_string())];
+    if options.sections.header {
+        fields.push(("header", json_header(&report.header, report.file_len as u64)));
+    }
+    if options.sections.chunks {
+        let chunks: Vec<String> = report
+            .chunks
+            .iter()
+            .map(|chunk| {
+                json_object(&[
+                    ("id", json_string(&riff::fourcc_str(chunk.id))),
+                    ("offset", chunk.offset.to_string()),
+                    ("size", chunk.size.to_string()),
+                    ("truncated", chunk.is_truncated(report.file_len).to_string()),
+                ])
+            })
+            .collect();
+        fields.push(("chunks", format!("[{}]", chunks.join(", "))));
+    }
+    if options.sections.metadata {
+        fields.push(("metadata", json_metadata(&report.metadata)));
+    }
+    match &report.samples {
+        Some(Ok(samples)) => {
+            if options.sections.samples {
+                fields.push((
+                    "samples",
+                    json_object(&[
+                        ("type", json_string(samples.type_name)),
+                        ("channels", samples.channels.to_string()),
+                        ("frames", samples.frames.to_string()),
+                    ]),
+                ));
+            }
+            if let Some(channels) = &samples.stats {
+                let channels: Vec<String> = channels
+                    .iter()
+                    .map(|stats| {
+                        json_object(&[
+                            ("peak_dbfs", json_number(stats::to_db(stats.peak))),
+                            ("rms_dbfs", json_number(stats::to_db(stats.rms))),
+                            ("dc_offset", json_number(stats.dc_offset)),
+                            ("clipped", stats.clipped.to_string()),
+                            ("true_peak_dbtp", json_number(stats::to_db(stats.true_peak))),
+                        ])
+                    })
+                    .collect();
+                fields.push(("stats", format!("[{}]", channels.join(", "))));
+            }
+            if let Some(info) = &samples.loudness {
+                let loudness = &info.loudness;
+                let seconds = |frames: u64| json_number(frames as f64 / report.header.sample_rate as f64);
+                fields.push((
+                    "loudness",
+                    json_object(&[
+                        ("integrated_lufs", json_number(loudness.integrated)),
+                        ("range_lu", json_number(loudness.range)),
+                        ("max_momentary_lufs", json_number(loudness.max_momentary)),
+                        ("max_short_term_lufs", json_number(loudness.max_short_term)),
+                        ("true_peak_dbtp", json_number(stats::to_db(info.true_peak))),
+                        ("leading_silence", seconds(info.leading_silence)),
+                        ("trailing_silence", seconds(info.trailing_silence)),
+                    ]),
+                ));
+            }
+        }
+        Some(Err(e)) => fields.push(("samples_error", json_string(&e.to_string()))),
+        None => {}
+    }
+    if options.command != Command::Info {
+        let issues: Vec<String> = report
+            .issues
+            .iter()
+            .map(|issue| {
+                json_object(&[
+                    ("field", json_string(issue.field)),
+                    ("found", issue.found.to_string()),
+                    ("expected", issue.expected.to_string()),
+                    ("reason", json_string(issue.reason)),
+                    ("repairable", issue.patch.is_some().to_string()),
+                ])
+            })
+            .collect();
+        fields.push(("issues", format!("[{}]", issues.join(", "))));
+    }
+    json_object(&fields)
+}
+
+/// Writes a JSON array with one object per file; failed files carry an `error`.
+pub fn write_json(out: &mut dyn Write, reports: &[Report], failures: &[(String, WavError)], options: &Options) -> io::Result<()> {
+    let mut objects: Vec<String> = reports.iter().map(|report| json_report(report, options)).collect();
+    objects.extend(
+        failures
+            .iter()
+            .map(|(path, e)| json_object(&[("path", json_string(path)), ("error", json_string(&e.to_string()))])),
+    );
+    writeln!(out, "[")?;
+    for (i, object) in objects.iter().enumerate() {
+        let separator = if i + 1 < objects.len() { "," } else { "" };
+        writeln!(out, "  {}{}", object, separator)?;
+    }
+    writeln!(out, "]")
+}
+
+const CSV_COLUMNS: [&str; 59] = [
+    "path",
+    "file_size",
+    "chunk_id",
+    "chunk_size",
+    "format",
+    "sub_chunk_1_id",
+    "sub_chunk_1_size",
+    "audio_format",
+    "audio_format_name",
+    "codec",
+    "num_channels",
+    "sample_rate",
+    "byte_rate",
+    "block_align",
+    "bits_per_sample",
+    "extension_size",
+    "valid_bits_per_sample",
+    "channel_mask",
+    "sub_format",
+    "compression",
+    "sub_chunk_2_id",
+    "sub_chunk_2_size",
+    "duration",
+    "extra_chunks",
+    "info",
+    "bext_description",
+    "bext_originator",
+    "bext_originator_reference",
+    "bext_origination_date",
+    "bext_origination_time",
+    "bext_time_reference",
+    "bext_version",
+    "bext_umid",
+    "bext_loudness_value",
+    "bext_loudness_range",
+    "bext_max_true_peak_level",
+    "bext_max_momentary_loudness",
+    "bext_max_short_term_loudness",
+    "bext_coding_history",
+    "ixml",
+    "cues",
+    "smpl_manufacturer",
+    "smpl_product",
+    "smpl_sample_period",
+    "smpl_midi_unity_note",
+    "smpl_midi_pitch_fraction",
+    "smpl_smpte_format",
+    "smpl_smpte_offset",
+    "smpl_loops",
+    "peak_dbfs",
+    "rms_dbfs",
+    "clipped",
+    "true_peak_dbtp",
+    "integrated_lufs",
+    "loudness_range_lu",
+    "leading_silence",
+    "trailing_silence",
+    "issues",
+    "error",
+];
+
+/// Quotes a CSV field if it holds a comma, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a level in dB, leaving silence empty.
+fn csv_db(level: f64) -> String {
+    let db = stats::to_db(level);
+    if db.is_finite() { format!("{:.2}", db) } else { String::new() }
+}
+
+/// Metadata fields of a row, as the JSON `metadata` object holds them. Lists
+/// are joined with `;`: INFO tags as `id=value`, cues as
+/// `id:sample_offset:length:label` and loops as `id:type:start-end:play_count`.
+fn csv_metadata(metadata: &Metadata) -> Vec<String> {
+    let info: Vec<String> = metadata.info.iter().map(|tag| format!("{}={}", riff::fourcc_str(tag.id), tag.value)).collect();
+    let mut fields = vec![info.join(";")];
+    match &metadata.bext {
+        Some(bext) => fields.extend([
+            bext.description.clone(),
+            bext.originator.clone(),
+            bext.originator_reference.clone(),
+            bext.origination_date.clone(),
+            bext.origination_time.clone(),
+            bext.time_reference.to_string(),
+            bext.version.to_string(),
+            if bext.has_umid() { bext.umid.iter().map(|b| format!("{:02X}", b)).collect() } else { String::new() },
+            bext.loudness_value.to_string(),
+            bext.loudness_range.to_string(),
+            bext.max_true_peak_level.to_string(),
+            bext.max_momentary_loudness.to_string(),
+            bext.max_short_term_loudness.to_string(),
+            bext.coding_history.clone(),
+        ]),
+        None => fields.extend(vec![String::new(); 14]),
+    }
+    fields.push(metadata.ixml.clone().unwrap_or_default());
+    let cues: Vec<String> = metadata
+        .cues
+        .iter()
+        .map(|cue| {
+            let length = cue.length.map_or(String::new(), |length| length.to_string());
+            format!("{}:{}:{}:{}", cue.id, cue.sample_offset, length, cue.label.as_deref().unwrap_or_default())
+        })
+        .collect();
+    fields.push(cues.join(";"));
+    match &metadata.sampler {
+        Some(sampler) => {
+            let loops: Vec<String> = sampler
+                .loops
+                .iter()
+                .map(|sample_loop| format!("{}:{}:{}-{}:{}", sample_loop.id, sample_loop.loop_type, sample_loop.start, sample_loop.end, sample_loop.play_count))
+                .collect();
+            fields.extend([
+                sampler.manufacturer.to_string(),
+                sampler.product.to_string(),
+                sampler.sample_period.to_string(),
+                sampler.midi_unity_note.to_string(),
+                sampler.midi_pitch_fraction.to_string(),
+                sampler.smpte_format.to_string(),
+                sampler.smpte_offset.to_string(),
+                loops.join(";"),
+            ]);
+        }
+        None => fields.extend(vec![String::new(); 8]),
+    }
+    fields
+}
+
+fn csv_row(report: &Report) -> Vec<String> {
+    let header = &report.header;
+    let extra_chunks: Vec<String> = report
+        .chunks
+        .iter()
+        .filter(|chunk| chunk.id != header.sub_chunk_1_id && chunk.id != header.sub_chunk_2_id)
+        .map(|chunk| format!("{}:{}", riff::fourcc_str(chunk.id), chunk.size))
+        .collect();
+    let (peak, rms, clipped, true_peak) = match &report.samples {
+        Some(Ok(SampleInfo { stats: Some(channels), .. })) => (
+            csv_db(channels.iter().map(|c| c.peak).fold(0.0, f64::max)),
+            csv_db(channels.iter().map(|c| c.rms).fold(0.0, f64::max)),
+            channels.iter().map(|c| c.clipped).sum::<usize>().to_string(),
+            csv_db(channels.iter().map(|c| c.true_peak).fold(0.0, f64::max)),
+        ),
+        _ => Default::default(),
+    };
+    let (integrated, range, leading, trailing) = match &report.samples {
+        Some(Ok(SampleInfo { loudness: Some(info), .. })) => {
+            let lufs = |value: f64| if value.is_finite() { format!("{:.1}", value) } else { String::new() };
+            let seconds = |frames: u64| match header.sample_rate {
+                0 => String::new(),
+                rate => format!("{:.6}", frames as f64 / rate as f64),
+            };
+            (lufs(info.loudness.integrated), lufs(info.loudness.range), seconds(info.leading_silence), seconds(info.trailing_silence))
+        }
+        _ => Default::default(),
+    };
+    let mut row = vec![
+        report.path.clone(),
+        report.file_len.to_string(),
+        riff::fourcc_str(header.chunk_id),
+        header.chunk_size.to_string(),
+        riff::fourcc_str(header.format),
+        riff::fourcc_str(header.sub_chunk_1_id),
+        header.sub_chunk_1_size.to_string(),
+        header.audio_format.to_string(),
+        header.format_tag().name().to_string(),
+        header.codec().name().to_string(),
+        header.num_channels.to_string(),
+        header.sample_rate.to_string(),
+        header.byte_rate.to_string(),
+        header.block_align.to_string(),
+        header.bits_per_sample.to_string(),
+        header.extension_size.to_string(),
+        header.valid_bits_per_sample.to_string(),
+        header.channel_mask.to_string(),
+        header.sub_format.map_or(String::new(), |guid| guid.to_string()),
+        header.compression.map_or(String::new(), riff::fourcc_str),
+        riff::fourcc_str(header.sub_chunk_2_id),
+        header.sub_chunk_2_size.to_string(),
+        stats::duration(header, report.file_len as u64).map_or(String::new(), |duration| format!("{:.6}", duration)),
+        extra_chunks.join(";"),
+    ];
+    row.extend(csv_metadata(&report.metadata));
+    row.extend([
+        peak,
+        rms,
+        clipped,
+        true_peak,
+        integrated,
+        range,
+        leading,
+        trailing,
+        report.issues.len().to_string(),
+        String::new(),
+    ]);
+    row
+}
+
+/// Writes one CSV row per file: header fields, then metadata and file-level statistics;
+/// failed files fill only `path` and `error`.
+pub fn write_csv(out: &mut dyn Write, reports: &[Report], failures: &[(String, WavError)]) -> io::Result<()> {
+    writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+    for report in reports {
+        let row: Vec<String> = csv_row(report).iter().map(|field| csv_field(field)).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    for (path, e) in failures {
+        let mut row = vec![String::new(); CSV_COLUMNS.len()];
+        row[0] = csv_field(path);
+        row[CSV_COLUMNS.len() - 1] = csv_field(&e.to_string());
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use wavheader::format::{AudioFormat, Container};
+    use wavheader::metadata::InfoTag;
+    use wavheader::writer::{WavSpec, WavWriter};
+
+    use super::*;
+    use crate::options;
+
+    /// A report on an empty mono file named `path`, with an INFO title.
+    fn report(path: &str, title: &str) -> Report {
+        let spec = WavSpec { format: AudioFormat::Pcm, channels: 1, sample_rate: 8000, bits_per_sample: 16, container: Container::Wave };
+        let bytes = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap().finalize().unwrap().into_inner();
+        let metadata = Metadata { info: vec![InfoTag { id: riff::fourcc(b"INAM"), value: title.to_string() }], ..Default::default() };
+        Report {
+            path: path.to_string(),
+            file_len: bytes.len(),
+            header: WAVHeader::parse(&bytes).unwrap(),
+            chunks: riff::walk_chunks(&bytes),
+            metadata,
+            samples: None,
+            issues: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn json_escapes_strings_and_non_finite_numbers() {
+        assert_eq!(json_string("a\"b\\c\nd\te\u{1}é"), "\"a\\\"b\\\\c\\nd\\te\\u0001é\"");
+        assert_eq!(json_number(1.5), "1.5");
+        assert_eq!(json_number(f64::NEG_INFINITY), "null");
+        assert_eq!(json_number(f64::NAN), "null");
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_rows_fill_every_column() {
+        assert_eq!(csv_row(&report("a.wav", "title")).len(), CSV_COLUMNS.len());
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &[report("my,file.wav", "say \"hi\"")], &[("bad.wav".to_string(), WavError::NotRiff)]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_COLUMNS.join(","));
+        assert!(lines[1].starts_with("\"my,file.wav\","));
+        assert!(lines[1].contains(",\"INAM=say \"\"hi\"\"\","));
+        assert_eq!(lines[2], format!("bad.wav{}{}", ",".repeat(CSV_COLUMNS.len() - 1), WavError::NotRiff));
+    }
+
+    #[test]
+    fn json_holds_one_object_per_file() {
+        let args = vec!["a.wav".to_string()];
+        let options = options::parse(&args).unwrap();
+        let mut out = Vec::new();
+        write_json(&mut out, &[report("my \"file\".wav", "title")], &[("bad.wav".to_string(), WavError::NotRiff)], &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("  {\"path\": \"my \\\"file\\\".wav\", "));
+        assert!(lines[1].contains("\"value\": \"title\""));
+        assert!(lines[1].ends_with("},"));
+        assert_eq!(lines[2], format!("  {{\"path\": \"bad.wav\", \"error\": \"{}\"}}", WavError::NotRiff));
+        assert_eq!((lines[0], lines[3]), ("[", "]"));
+    }
+}
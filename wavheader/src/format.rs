@@ -0,0 +1,142 @@
+use std::fmt;
+use std::path::Path;
+
+/// Tail shared by every `KSDATAFORMAT_SUBTYPE_*` GUID; the first two bytes hold the format code.
+const SUBTYPE_SUFFIX: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// The codec named by a `fmt ` chunk format code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Pcm,
+    MsAdpcm,
+    IeeeFloat,
+    ALaw,
+    MuLaw,
+    ImaAdpcm,
+    Extensible,
+    Unknown(u16),
+}
+
+impl AudioFormat {
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            0x0001 => AudioFormat::Pcm,
+            0x0002 => AudioFormat::MsAdpcm,
+            0x0003 => AudioFormat::IeeeFloat,
+            0x0006 => AudioFormat::ALaw,
+            0x0007 => AudioFormat::MuLaw,
+            0x0011 => AudioFormat::ImaAdpcm,
+            0xFFFE => AudioFormat::Extensible,
+            code => AudioFormat::Unknown(code),
+        }
+    }
+
+    pub fn code(self) -> u16 {
+        match self {
+            AudioFormat::Pcm => 0x0001,
+            AudioFormat::MsAdpcm => 0x0002,
+            AudioFormat::IeeeFloat => 0x0003,
+            AudioFormat::ALaw => 0x0006,
+            AudioFormat::MuLaw => 0x0007,
+            AudioFormat::ImaAdpcm => 0x0011,
+            AudioFormat::Extensible => 0xFFFE,
+            AudioFormat::Unknown(code) => code,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioFormat::Pcm => "PCM",
+            AudioFormat::MsAdpcm => "MS ADPCM",
+            AudioFormat::IeeeFloat => "IEEE float",
+            AudioFormat::ALaw => "A-law",
+            AudioFormat::MuLaw => "µ-law",
+            AudioFormat::ImaAdpcm => "IMA ADPCM",
+            AudioFormat::Extensible => "extensible",
+            AudioFormat::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The file layout samples are stored in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Container {
+    /// RIFF/WAVE, or RF64 once it outgrows 32-bit sizes.
+    #[default]
+    Wave,
+    /// AIFF for PCM, AIFF-C for float and G.711.
+    Aiff,
+}
+
+impl Container {
+    /// The container named by a file extension, in any case.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "wav" | "wave" | "bwf" | "rf64" => Some(Container::Wave),
+            "aif" | "aiff" | "aifc" => Some(Container::Aiff),
+            _ => None,
+        }
+    }
+}
+
+/// A GUID as stored in a file: the first three fields little-endian, the rest as bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// Returns the format code if this is a `KSDATAFORMAT_SUBTYPE_*` GUID.
+    pub fn format_code(&self) -> Option<u16> {
+        if self.0[2..] == SUBTYPE_SUFFIX {
+            Some(u16::from_le_bytes([self.0[0], self.0[1]]))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]])
+        )?;
+        for byte in &b[8..10] {
+            write!(f, "{:02X}", byte)?;
+        }
+        f.write_str("-")?;
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Speaker position names for the `dwChannelMask` bits, lowest bit first.
+const SPEAKERS: [&str; 18] = [
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC", "SL", "SR", "TC", "TFL", "TFC", "TFR", "TBL", "TBC",
+    "TBR",
+];
+
+/// Returns the speaker positions in a channel mask, e.g. `FL FR`.
+pub fn channel_mask_str(mask: u32) -> String {
+    let names: Vec<&str> = SPEAKERS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() { "none".to_string() } else { names.join(" ") }
+}
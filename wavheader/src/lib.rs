@@ -0,0 +1,19 @@
+pub mod aiff;
+pub mod adpcm;
+pub mod convert;
+pub mod diff;
+pub mod edit;
+pub mod error;
+pub mod format;
+pub mod loudness;
+pub mod metadata;
+pub mod png;
+pub mod reader;
+pub mod render;
+pub mod resample;
+pub mod riff;
+pub mod samples;
+pub mod stats;
+pub mod utils;
+pub mod writer;
+pub mod validate;
@@ -0,0 +1,219 @@
+use std::f64::consts::PI;
+
+use crate::samples::Buffer;
+use crate::utils::WAVHeader;
+
+/// Blocks below this loudness are left out of the integrated loudness and
+/// the loudness range, as in EBU R128.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Relative gates below the ungated loudness, for the integrated loudness
+/// and for the loudness range.
+const INTEGRATED_GATE: f64 = -10.0;
+const RANGE_GATE: f64 = -20.0;
+/// Windows are measured in 100 ms steps: 4 steps for momentary loudness,
+/// 30 for short-term.
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+/// EBU R128 measurements of a file, in LUFS except the range in LU.
+/// Levels with nothing above the gates are negative infinity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    pub integrated: f64,
+    pub range: f64,
+    pub max_momentary: f64,
+    pub max_short_term: f64,
+}
+
+/// Second-order IIR section, transposed direct form II.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two stages of the BS.1770 K-weighting filter, a high shelf modelling
+/// the head and a high-pass, derived for any sample rate from the analog
+/// prototypes so that they match the published 48 kHz coefficients.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad { b: [1.0, -2.0, 1.0], a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0], z: [0.0; 2] };
+
+    [shelf, high_pass]
+}
+
+/// Weight of each channel in the sum: 0 for LFE, 1.41 for the surround
+/// channels and 1 for the rest. Without a channel mask, 5 and 6 channels
+/// are taken to be 5.0 and 5.1 in WAVE order.
+pub fn channel_weights(header: &WAVHeader) -> Vec<f64> {
+    let channels = header.num_channels as usize;
+    let speakers: Vec<u32> = match (header.channel_mask, channels) {
+        (0, 5) => vec![0, 1, 2, 4, 5],
+        (0, 6) => vec![0, 1, 2, 3, 4, 5],
+        (0, _) => Vec::new(),
+        (mask, _) => (0..32).filter(|bit| mask & (1 << bit) != 0).collect(),
+    };
+    (0..channels)
+        .map(|channel| match speakers.get(channel) {
+            Some(3) => 0.0,
+            Some(4 | 5 | 9 | 10) => 1.41,
+            _ => 1.0,
+        })
+        .collect()
+}
+
+/// Loudness of a weighted mean square.
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn mean(powers: &[f64]) -> f64 {
+    powers.iter().sum::<f64>() / powers.len() as f64
+}
+
+/// Mean squares of the windows of `steps` consecutive 100 ms steps.
+fn windows(steps: &[f64], len: usize) -> Vec<f64> {
+    steps.windows(len).map(mean).collect()
+}
+
+/// Window powers above the absolute gate and the relative gate `relative`
+/// LU below their mean.
+fn gated(powers: &[f64], relative: f64) -> Vec<f64> {
+    let absolute: Vec<f64> = powers.iter().copied().filter(|&power| lufs(power) > ABSOLUTE_GATE).collect();
+    if absolute.is_empty() {
+        return absolute;
+    }
+    let threshold = lufs(mean(&absolute)) + relative;
+    absolute.into_iter().filter(|&power| lufs(power) > threshold).collect()
+}
+
+/// Measures loudness as in ITU-R BS.1770 and EBU Tech 3341/3342, fed block
+/// by block. Only the mean square of each 100 ms step is kept, so memory
+/// grows by a few bytes per second of audio.
+pub struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    /// Frames in a 100 ms step.
+    step_frames: usize,
+    /// Weighted sum of squares and frame count of the step in progress.
+    sum: f64,
+    frames: usize,
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(header: &WAVHeader) -> Self {
+        let weights = channel_weights(header);
+        LoudnessMeter {
+            filters: vec![k_weighting(header.sample_rate); weights.len()],
+            weights,
+            step_frames: (header.sample_rate as usize / 10).max(1),
+            sum: 0.0,
+            frames: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, buffer: &Buffer<f64>) {
+        for frame in buffer.frames() {
+            for ((sample, filters), weight) in frame.iter().zip(&mut self.filters).zip(&self.weights) {
+                let [shelf, high_pass] = filters;
+                let filtered = high_pass.process(shelf.process(*sample));
+                self.sum += weight * filtered * filtered;
+            }
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.sum / self.frames as f64);
+                self.sum = 0.0;
+                self.frames = 0;
+            }
+        }
+    }
+
+    /// Returns the measurements. A last step shorter than 100 ms is dropped.
+    pub fn finish(self) -> Loudness {
+        let momentary = windows(&self.steps, MOMENTARY_STEPS);
+        let short_term = windows(&self.steps, SHORT_TERM_STEPS);
+        let max = |powers: &[f64]| powers.iter().copied().fold(f64::NEG_INFINITY, |max, power| max.max(lufs(power)));
+
+        let integrated = gated(&momentary, INTEGRATED_GATE);
+        let integrated = if integrated.is_empty() { f64::NEG_INFINITY } else { lufs(mean(&integrated)) };
+
+        let mut levels: Vec<f64> = gated(&short_term, RANGE_GATE).into_iter().map(lufs).collect();
+        levels.sort_by(f64::total_cmp);
+        let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+        let range = if levels.is_empty() { 0.0 } else { percentile(0.95) - percentile(0.10) };
+
+        Loudness { integrated, range, max_momentary: max(&momentary), max_short_term: max(&short_term) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::format::{AudioFormat, Container};
+    use crate::writer::{WavSpec, WavWriter};
+
+    /// Measures `seconds` of a stereo sine of `amplitude` on each channel.
+    fn measure_sine(sample_rate: u32, frequency: f64, amplitude: f64, seconds: usize) -> Loudness {
+        let spec = WavSpec { format: AudioFormat::Pcm, channels: 2, sample_rate, bits_per_sample: 16, container: Container::Wave };
+        let bytes = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap().finalize().unwrap().into_inner();
+        let mut meter = LoudnessMeter::new(&WAVHeader::parse(&bytes).unwrap());
+        let rate = sample_rate as f64;
+        for second in 0..seconds {
+            let start = second * sample_rate as usize;
+            let data = (start..start + sample_rate as usize)
+                .flat_map(|n| [amplitude * (2.0 * PI * frequency * n as f64 / rate).sin(); 2])
+                .collect();
+            meter.push(&Buffer::new(2, data));
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn reference_tone_measures_minus_23_lufs() {
+        // EBU Tech 3341: a 1 kHz sine at -23 dBFS on both channels reads -23 LUFS.
+        let amplitude = 10f64.powf(-23.0 / 20.0);
+        for sample_rate in [44100, 48000] {
+            let loudness = measure_sine(sample_rate, 1000.0, amplitude, 20);
+            assert!((loudness.integrated + 23.0).abs() < 0.1, "{sample_rate} Hz: {}", loudness.integrated);
+            assert!((loudness.max_momentary + 23.0).abs() < 0.1, "{sample_rate} Hz: {}", loudness.max_momentary);
+            assert!((loudness.max_short_term + 23.0).abs() < 0.1, "{sample_rate} Hz: {}", loudness.max_short_term);
+            assert!(loudness.range < 0.1, "{sample_rate} Hz: {}", loudness.range);
+        }
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let loudness = measure_sine(48000, 1000.0, 0.0, 5);
+        assert_eq!(loudness.integrated, f64::NEG_INFINITY);
+        assert_eq!(loudness.range, 0.0);
+    }
+}
@@ -1,52 +1,275 @@
-mod utils;
-use std::convert::TryInto;
+mod export;
+mod options;
+mod report;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
 
-fn main() {
-    let filepath = "../samples/BAK.wav";
-    let mut header: Vec<u8> = Vec::with_capacity(44);
-    match utils::read_file_bytes(filepath) {
-        Ok(bytes) => {
-            println!("Successfully read {} bytes from {}", bytes.len(), filepath);
-            header = bytes[..44].to_vec();
+use options::{Command, Options, OutputFormat, ParseError, SplitAt, View};
+use wavheader::convert;
+use wavheader::diff;
+use wavheader::edit::{self, Position};
+use wavheader::error::WavError;
+use wavheader::png;
+use wavheader::reader::WavReader;
+use wavheader::render;
+use wavheader::riff;
+
+/// Returns true for files named `*.wav`, `*.wave`, `*.aif`, `*.aiff` or `*.aifc`, in any case.
+fn is_audio(path: &Path) -> bool {
+    path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| {
+        ["wav", "wave", "aif", "aiff", "aifc"].iter().any(|known| extension.eq_ignore_ascii_case(known))
+    })
+}
+
+/// Adds the WAV and AIFF files under a directory, recursively and in name order.
+fn collect_dir(dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_dir(&path, files)?;
+        } else if is_audio(&path) {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Expands the inputs into files. Files named directly are kept whatever their extension.
+fn collect_files(inputs: &[String]) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            collect_dir(path, &mut files).map_err(|e| format!("Error at reading directory {}: {}", input, e))?;
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Inserts a 1-based segment number before the extension: `out.wav` becomes `out_001.wav`.
+fn numbered(output: &str, number: usize) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:03}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{:03}", stem, number),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// Runs a command that writes files rather than reporting on them. The last
+/// input is the output file.
+fn write_files(options: &Options) -> Result<(), WavError> {
+    let (output, inputs) = options.inputs.split_last().unwrap();
+    let input = &inputs[0];
+    match options.command {
+        Command::Convert => {
+            let spec = convert::convert_file(input, output, &options.conversion)?;
+            println!(
+                "Converted {} to {}: {} {}-bit, {} channel(s), {} Hz",
+                input, output, spec.format, spec.bits_per_sample, spec.channels, spec.sample_rate
+            );
+        }
+        Command::Trim => {
+            let range = edit::trim(input, output, options.start.unwrap_or(Position::Frame(0)), options.end)?;
+            println!("Wrote frames {}..{} of {} to {}", range.start, range.end, input, output);
+        }
+        Command::Split => {
+            let mut reader = WavReader::open(input)?;
+            let points = match options.split_at {
+                SplitAt::Cues => edit::cue_points(&mut reader)?,
+                SplitAt::Silence => {
+                    let min_frames = options.min_silence.to_frame(reader.header().sample_rate);
+                    edit::silence_points(&mut reader, 10f64.powf(options.threshold / 20.0), min_frames)?
+                }
+            };
+            for (path, range) in edit::split(input, &points, |number| numbered(output, number))? {
+                println!("Wrote frames {}..{} of {} to {}", range.start, range.end, input, path);
+            }
+        }
+        Command::Concat => {
+            let frames = edit::concat(inputs, output)?;
+            println!("Wrote {} frames from {} file(s) to {}", frames, inputs.len(), output);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Draws a file to the terminal, or to the PNG file given after it.
+fn render_file(options: &Options) -> Result<(), WavError> {
+    let input = &options.inputs[0];
+    let output = options.inputs.get(1);
+    let mut reader = WavReader::open(input)?;
+    let width = options.width.unwrap_or(if output.is_some() { 1024 } else { 80 });
+    let height = options.height.unwrap_or(match (options.view, output.is_some()) {
+        (View::Waveform, false) => 8,
+        (View::Spectrogram, false) => 24,
+        (View::Waveform, true) => 128,
+        (View::Spectrogram, true) => 512,
+    });
+    match (options.view, output) {
+        (View::Waveform, None) => println!("{}", render::waveform_text(&render::waveform(&mut reader, width)?, height)),
+        (View::Spectrogram, None) => {
+            let spectrogram = render::spectrogram(&mut reader, width, height)?;
+            println!("{}", render::spectrogram_text(&spectrogram));
+            println!("0 to {} Hz, levels down to {} dBFS", spectrogram.sample_rate / 2, render::SPECTROGRAM_FLOOR);
+        }
+        (view, Some(output)) => {
+            let image = match view {
+                View::Waveform => render::waveform_image(&render::waveform(&mut reader, width)?, height),
+                View::Spectrogram => render::spectrogram_image(&render::spectrogram(&mut reader, width, height)?),
+            };
+            let mut out = BufWriter::new(File::create(output)?);
+            png::write_rgb(&mut out, image.width, image.height, &image.pixels)?;
+            out.flush()?;
+            println!("Wrote {}x{} image of {} to {}", image.width, image.height, input, output);
+        }
+    }
+    Ok(())
+}
+
+/// Compares two files and prints what differs. Returns true if anything does.
+fn diff_files(options: &Options) -> Result<bool, WavError> {
+    let (left_path, right_path) = (&options.inputs[0], &options.inputs[1]);
+    let mut left = WavReader::open(left_path)?;
+    let mut right = WavReader::open(right_path)?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "Comparing {} with {}", left_path, right_path)?;
+
+    let fields = diff::compare_headers(left.header(), right.header());
+    writeln!(out, "Header {{")?;
+    if fields.is_empty() {
+        writeln!(out, "\tIdentical")?;
+    }
+    for field in &fields {
+        writeln!(out, "\t{}: {} vs {}", field.field, field.left, field.right)?;
+    }
+    writeln!(out, "}}")?;
+
+    let chunks = diff::compare_chunks(&mut left, &mut right)?;
+    writeln!(out, "Chunks {{")?;
+    if chunks.is_empty() {
+        writeln!(out, "\tIdentical")?;
+    }
+    for chunk in &chunks {
+        let id = riff::fourcc_str(chunk.id);
+        match (chunk.left, chunk.right) {
+            (Some(left), Some(right)) => writeln!(out, "\t{}: contents differ ({} vs {} bytes)", id, left, right)?,
+            (Some(size), None) => writeln!(out, "\t{}: only in {} ({} bytes)", id, left_path, size)?,
+            (None, Some(size)) => writeln!(out, "\t{}: only in {} ({} bytes)", id, right_path, size)?,
+            (None, None) => {}
         }
+    }
+    writeln!(out, "}}")?;
+
+    let samples = match diff::compare_samples(&mut left, &mut right) {
+        Ok(samples) => samples,
         Err(e) => {
-            eprintln!("Error at handling file: {}", e)
-        }
-    }
-
-    let wav_header: utils::WAVHeader = utils::WAVHeader::new(
-        u32::from_be_bytes(header[0..4].try_into().unwrap()),
-        u32::from_le_bytes(header[4..8].try_into().unwrap()),
-        u32::from_be_bytes(header[8..12].try_into().unwrap()),
-        u32::from_be_bytes(header[12..16].try_into().unwrap()),
-        u32::from_le_bytes(header[16..20].try_into().unwrap()),
-        u16::from_le_bytes(header[20..22].try_into().unwrap()),
-        u16::from_le_bytes(header[22..24].try_into().unwrap()),
-        u32::from_le_bytes(header[24..28].try_into().unwrap()),
-        u32::from_le_bytes(header[28..32].try_into().unwrap()),
-        u16::from_le_bytes(header[32..34].try_into().unwrap()),
-        u16::from_le_bytes(header[34..36].try_into().unwrap()),
-        u32::from_be_bytes(header[36..40].try_into().unwrap()),
-        u32::from_le_bytes(header[40..44].try_into().unwrap()),
-    );
-
-    let chunk_id_str = String::from_utf8(wav_header.chunk_id.to_be_bytes().to_vec()).unwrap_or_else(|_| "Invalid".to_string());
-    let format_str = String::from_utf8(wav_header.format.to_be_bytes().to_vec()).unwrap_or_else(|_| "Invalid".to_string());
-
-    println!(
-        ".WAV Header extracted {{\n\tChunkID: {}\n\tChunkSize: {}\n\tFormat: {}\n\tSubChunk1ID: {}\n\tSubChunk1Size: {}\n\tAudioFormat: {}\n\tNumChannels: {}\n\tSampleRate: {}\n\tByteRate: {}\n\tBlockAlign: {}\n\tBitsPerSample: {}\n\tSubChunk2ID: {}\n\tSubChunk2Size: {}\n}}",
-        chunk_id_str,
-        wav_header.chunk_size.to_string(),
-        format_str,
-        wav_header.sub_chunk_1_id.to_string(),
-        wav_header.sub_chunk_1_size.to_string(),
-        wav_header.audio_format.to_string(),
-        wav_header.num_channels.to_string(),
-        wav_header.sample_rate.to_string(),
-        wav_header.byte_rate.to_string(),
-        wav_header.block_align.to_string(),
-        wav_header.bits_per_sample.to_string(),
-        wav_header.sub_chunk_2_id.to_string(),
-        wav_header.sub_chunk_2_size.to_string(),
-    );
+            writeln!(out, "Samples {{\n\tNot compared: {}\n}}", e)?;
+            return Ok(true);
+        }
+    };
+    writeln!(out, "Samples {{")?;
+    writeln!(out, "\tFrames: {} vs {}", samples.left_frames, samples.right_frames)?;
+    match samples.first_difference {
+        Some(frame) => {
+            let seconds = frame as f64 / left.header().sample_rate.max(1) as f64;
+            writeln!(out, "\tFirst difference: frame {} ({:.3} s)", frame, seconds)?;
+            writeln!(out, "\tMax difference: {:.6} ({:.2} dBFS)", samples.max_difference, 20.0 * samples.max_difference.log10())?;
+            writeln!(out, "\tSNR: {:.2} dB", samples.snr)?;
+        }
+        None => writeln!(out, "\tFirst difference: none")?,
+    }
+    writeln!(out, "\tBit-identical: {}", if samples.identical { "yes" } else { "no" })?;
+    writeln!(out, "}}")?;
+    out.flush()?;
+    Ok(!fields.is_empty() || !chunks.is_empty() || !samples.identical)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match options::parse(&args) {
+        Ok(options) => options,
+        Err(ParseError::Help) => {
+            println!("{}", options::USAGE);
+            return Ok(());
+        }
+        Err(ParseError::Invalid(e)) => {
+            eprintln!("{}\n", e);
+            eprintln!("{}", options::USAGE);
+
+            process::exit(2);
+        }
+    };
+
+    if matches!(options.command, Command::Convert | Command::Trim | Command::Split | Command::Concat) {
+        if let Err(e) = write_files(&options) {
+            eprintln!("Error at writing file {}: {}", options.inputs[options.inputs.len() - 1], e);
+            process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+    if options.command == Command::Render {
+        if let Err(e) = render_file(&options) {
+            eprintln!("Error at rendering file {}: {}", options.inputs[0], e);
+            process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+
+    if options.command == Command::Diff {
+        match diff_files(&options) {
+            Ok(true) => process::exit(1),
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                eprintln!("Error at comparing {} with {}: {}", options.inputs[0], options.inputs[1], e);
+                process::exit(e.exit_code());
+            }
+        }
+    }
+
+    let files = collect_files(&options.inputs)?;
+    let mut out = io::stdout().lock();
+    let mut reports = Vec::new();
+    let mut failures: Vec<(String, WavError)> = Vec::new();
+    for path in &files {
+        match report::build(path, &options) {
+            Ok(report) => reports.push(report),
+            Err(e) => failures.push((path.clone(), e)),
+        }
+    }
+
+    match options.format {
+        OutputFormat::Json => export::write_json(&mut out, &reports, &failures, &options)?,
+        OutputFormat::Csv => export::write_csv(&mut out, &reports, &failures)?,
+        OutputFormat::Text if files.len() == 1 && !options.table => {
+            if let Some((path, e)) = failures.first() {
+                eprintln!("Error at handling file {}: {}", path, e);
+            }
+            for report in &reports {
+                report::write_block(&mut out, report, &options)?;
+            }
+        }
+        OutputFormat::Text => {
+            report::write_table(&mut out, &reports, &options)?;
+            report::write_summary(&mut out, &reports, &failures)?;
+        }
+    }
+    out.flush()?;
+
+    if let Some((_, e)) = failures.first() {
+        process::exit(e.exit_code());
+    }
+    if options.command == Command::Validate && reports.iter().any(|report| !report.issues.is_empty()) {
+        process::exit(1);
+    }
+    Ok(())
 }
@@ -0,0 +1,350 @@
+use crate::riff;
+
+/// A `LIST`/`INFO` tag such as `INAM` (title) or `IART` (artist).
+pub struct InfoTag {
+    pub id: u32,
+    pub value: String,
+}
+
+impl InfoTag {
+    /// Readable name of the tag, if it is a registered one.
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match &self.id.to_be_bytes() {
+            b"INAM" => "Title",
+            b"IART" => "Artist",
+            b"IPRD" => "Product",
+            b"ICMT" => "Comment",
+            b"ISFT" => "Software",
+            b"ICRD" => "Creation date",
+            b"IGNR" => "Genre",
+            b"ICOP" => "Copyright",
+            b"IENG" => "Engineer",
+            b"ITCH" => "Technician",
+            b"IKEY" => "Keywords",
+            b"ISBJ" => "Subject",
+            b"ISRC" => "Source",
+            b"ITRK" | b"IPRT" => "Track",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+/// Broadcast Wave Format `bext` chunk (EBU Tech 3285).
+pub struct Bext {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+    /// `yyyy-mm-dd`.
+    pub origination_date: String,
+    /// `hh:mm:ss`.
+    pub origination_time: String,
+    /// First sample's position in samples since midnight.
+    pub time_reference: u64,
+    pub version: u16,
+    /// SMPTE 330M UMID; all zero when absent.
+    pub umid: [u8; 64],
+    /// Loudness fields of version 2, in hundredths of LUFS, LU or dBTP.
+    pub loudness_value: i16,
+    pub loudness_range: i16,
+    pub max_true_peak_level: i16,
+    pub max_momentary_loudness: i16,
+    pub max_short_term_loudness: i16,
+    pub coding_history: String,
+}
+
+impl Bext {
+    /// Returns true if the file has a UMID.
+    pub fn has_umid(&self) -> bool {
+        self.umid.iter().any(|&b| b != 0)
+    }
+}
+
+/// A `cue ` point, with its `adtl` label, note and region length when present.
+#[derive(Clone)]
+pub struct CuePoint {
+    pub id: u32,
+    /// Sample position of the point in the play order.
+    pub position: u32,
+    pub chunk_id: u32,
+    pub chunk_start: u32,
+    pub block_start: u32,
+    pub sample_offset: u32,
+    pub label: Option<String>,
+    pub note: Option<String>,
+    /// Length in samples of the region starting at the point, from an `ltxt` entry.
+    pub length: Option<u32>,
+}
+
+/// A loop of a `smpl` chunk.
+#[derive(Clone)]
+pub struct SampleLoop {
+    pub id: u32,
+    /// 0 forward, 1 alternating, 2 backward.
+    pub loop_type: u32,
+    pub start: u32,
+    pub end: u32,
+    pub fraction: u32,
+    /// 0 for an infinite loop.
+    pub play_count: u32,
+}
+
+/// Sampler `smpl` chunk.
+#[derive(Clone)]
+pub struct Sampler {
+    pub manufacturer: u32,
+    pub product: u32,
+    /// Sample period in nanoseconds.
+    pub sample_period: u32,
+    pub midi_unity_note: u32,
+    pub midi_pitch_fraction: u32,
+    pub smpte_format: u32,
+    pub smpte_offset: u32,
+    pub loops: Vec<SampleLoop>,
+}
+
+/// The metadata chunks of a file.
+#[derive(Default)]
+pub struct Metadata {
+    pub info: Vec<InfoTag>,
+    pub bext: Option<Bext>,
+    pub ixml: Option<String>,
+    pub cues: Vec<CuePoint>,
+    pub sampler: Option<Sampler>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.info.is_empty() && self.bext.is_none() && self.ixml.is_none() && self.cues.is_empty() && self.sampler.is_none()
+    }
+
+    /// Returns the value of an `INFO` tag.
+    pub fn info_tag(&self, id: &[u8; 4]) -> Option<&str> {
+        self.info.iter().find(|tag| tag.id == riff::fourcc(id)).map(|tag| tag.value.as_str())
+    }
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decodes a text field, which ends at the first NUL byte or fills its space.
+fn text(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim_end().to_string()
+}
+
+/// Walks the sub-chunks of a `LIST` chunk, after its four-byte list type.
+fn walk_list(data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut entries = Vec::new();
+    let mut offset = 4;
+    while offset + 8 <= data.len() {
+        let id = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let size = le_u32(data, offset + 4) as usize;
+        let start = offset + 8;
+        let end = (start + size).min(data.len());
+        entries.push((id, &data[start..end]));
+        offset = start + size + (size & 1);
+    }
+    entries
+}
+
+fn parse_bext(data: &[u8]) -> Option<Bext> {
+    if data.len() < 602 {
+        return None;
+    }
+    Some(Bext {
+        description: text(&data[0..256]),
+        originator: text(&data[256..288]),
+        originator_reference: text(&data[288..320]),
+        origination_date: text(&data[320..330]),
+        origination_time: text(&data[330..338]),
+        time_reference: le_u32(data, 338) as u64 | (le_u32(data, 342) as u64) << 32,
+        version: le_u16(data, 346),
+        umid: data[348..412].try_into().unwrap(),
+        loudness_value: le_u16(data, 412) as i16,
+        loudness_range: le_u16(data, 414) as i16,
+        max_true_peak_level: le_u16(data, 416) as i16,
+        max_momentary_loudness: le_u16(data, 418) as i16,
+        max_short_term_loudness: le_u16(data, 420) as i16,
+        coding_history: text(&data[602..]),
+    })
+}
+
+fn parse_cues(data: &[u8]) -> Vec<CuePoint> {
+    if data.len() < 4 {
+        return Vec::new();
+    }
+    let count = le_u32(data, 0) as usize;
+    data[4..]
+        .chunks_exact(24)
+        .take(count)
+        .map(|point| CuePoint {
+            id: le_u32(point, 0),
+            position: le_u32(point, 4),
+            chunk_id: u32::from_be_bytes(point[8..12].try_into().unwrap()),
+            chunk_start: le_u32(point, 12),
+            block_start: le_u32(point, 16),
+            sample_offset: le_u32(point, 20),
+            label: None,
+            note: None,
+            length: None,
+        })
+        .collect()
+}
+
+/// Attaches `labl`, `note` and `ltxt` entries of a `LIST`/`adtl` chunk to their cue points.
+fn apply_adtl(cues: &mut [CuePoint], entries: &[(u32, &[u8])]) {
+    for &(id, data) in entries {
+        if data.len() < 4 {
+            continue;
+        }
+        let Some(cue) = cues.iter_mut().find(|cue| cue.id == le_u32(data, 0)) else {
+            continue;
+        };
+        match &id.to_be_bytes() {
+            b"labl" => cue.label = Some(text(&data[4..])),
+            b"note" => cue.note = Some(text(&data[4..])),
+            b"ltxt" if data.len() >= 8 => cue.length = Some(le_u32(data, 4)),
+            _ => {}
+        }
+    }
+}
+
+fn parse_smpl(data: &[u8]) -> Option<Sampler> {
+    if data.len() < 36 {
+        return None;
+    }
+    let count = le_u32(data, 28) as usize;
+    let loops = data[36..]
+        .chunks_exact(24)
+        .take(count)
+        .map(|entry| SampleLoop {
+            id: le_u32(entry, 0),
+            loop_type: le_u32(entry, 4),
+            start: le_u32(entry, 8),
+            end: le_u32(entry, 12),
+            fraction: le_u32(entry, 16),
+            play_count: le_u32(entry, 20),
+        })
+        .collect();
+    Some(Sampler {
+        manufacturer: le_u32(data, 0),
+        product: le_u32(data, 4),
+        sample_period: le_u32(data, 8),
+        midi_unity_note: le_u32(data, 12),
+        midi_pitch_fraction: le_u32(data, 16),
+        smpte_format: le_u32(data, 20),
+        smpte_offset: le_u32(data, 24),
+        loops,
+    })
+}
+
+/// Returns true for the chunks `read` looks at.
+pub fn is_metadata_chunk(id: u32) -> bool {
+    matches!(&id.to_be_bytes(), b"LIST" | b"bext" | b"iXML" | b"cue " | b"smpl")
+}
+
+/// Reads the metadata chunks of a file from their ids and payloads. Chunks
+/// too short for their fixed fields are skipped.
+pub fn read<'a>(chunks: impl IntoIterator<Item = (u32, &'a [u8])>) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut adtl = Vec::new();
+    for (id, data) in chunks {
+        match &id.to_be_bytes() {
+            b"LIST" if data.starts_with(b"INFO") => {
+                metadata.info.extend(walk_list(data).into_iter().map(|(id, value)| InfoTag { id, value: text(value) }));
+            }
+            b"LIST" if data.starts_with(b"adtl") => adtl.extend(walk_list(data)),
+            b"bext" => metadata.bext = parse_bext(data),
+            b"iXML" => metadata.ixml = Some(text(data)),
+            b"cue " => metadata.cues = parse_cues(data),
+            b"smpl" => metadata.sampler = parse_smpl(data),
+            _ => {}
+        }
+    }
+    apply_adtl(&mut metadata.cues, &adtl);
+    metadata
+}
+
+/// Appends a sub-chunk to a `LIST` payload, with its pad byte.
+fn push_entry(list: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    list.extend_from_slice(id);
+    list.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    list.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        list.push(0);
+    }
+}
+
+/// Encodes cue points as a `cue ` chunk and, if any has a label, note or
+/// length, a `LIST`/`adtl` chunk. Returns (id, payload) pairs.
+pub fn encode_cues(cues: &[CuePoint]) -> Vec<(u32, Vec<u8>)> {
+    let mut cue = (cues.len() as u32).to_le_bytes().to_vec();
+    let mut adtl = b"adtl".to_vec();
+    for point in cues {
+        for field in [point.id, point.position] {
+            cue.extend_from_slice(&field.to_le_bytes());
+        }
+        cue.extend_from_slice(&point.chunk_id.to_be_bytes());
+        for field in [point.chunk_start, point.block_start, point.sample_offset] {
+            cue.extend_from_slice(&field.to_le_bytes());
+        }
+
+        let text = |value: &str| [&point.id.to_le_bytes()[..], value.as_bytes(), &[0]].concat();
+        if let Some(label) = &point.label {
+            push_entry(&mut adtl, b"labl", &text(label));
+        }
+        if let Some(note) = &point.note {
+            push_entry(&mut adtl, b"note", &text(note));
+        }
+        if let Some(length) = point.length {
+            // Name, sample length, purpose `rgn `, then country, language,
+            // dialect and code page left at zero.
+            let mut ltxt = [point.id.to_le_bytes(), length.to_le_bytes(), *b"rgn "].concat();
+            ltxt.extend_from_slice(&[0; 8]);
+            push_entry(&mut adtl, b"ltxt", &ltxt);
+        }
+    }
+    let mut chunks = vec![(riff::fourcc(b"cue "), cue)];
+    if adtl.len() > 4 {
+        chunks.push((riff::fourcc(b"LIST"), adtl));
+    }
+    chunks
+}
+
+/// Encodes a sampler as a `smpl` chunk payload, without sampler-specific data.
+pub fn encode_smpl(sampler: &Sampler) -> Vec<u8> {
+    let mut smpl = Vec::with_capacity(36 + 24 * sampler.loops.len());
+    for field in [
+        sampler.manufacturer,
+        sampler.product,
+        sampler.sample_period,
+        sampler.midi_unity_note,
+        sampler.midi_pitch_fraction,
+        sampler.smpte_format,
+        sampler.smpte_offset,
+        sampler.loops.len() as u32,
+        0,
+    ] {
+        smpl.extend_from_slice(&field.to_le_bytes());
+    }
+    for entry in &sampler.loops {
+        for field in [entry.id, entry.loop_type, entry.start, entry.end, entry.fraction, entry.play_count] {
+            smpl.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    smpl
+}
+
+/// Returns the text of the first `<name>` element of an iXML document.
+pub fn ixml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{}>", name))? + start;
+    Some(xml[start..end].trim())
+}
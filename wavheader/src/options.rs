@@ -0,0 +1,314 @@
+use wavheader::convert::{Conversion, Remix};
+use wavheader::edit::Position;
+use wavheader::format::AudioFormat;
+use wavheader::resample::Quality;
+
+pub const USAGE: &str = "\
+Use: wavheader [info | validate | repair] [options] <file.wav | directory>...
+     wavheader convert [convert options] <input.wav> <output.wav>
+     wavheader trim [--start <pos>] [--end <pos>] <input.wav> <output.wav>
+     wavheader split [split options] <input.wav> <output.wav>
+     wavheader concat <input.wav>... <output.wav>
+     wavheader render [render options] <input.wav> [<output.png>]
+     wavheader diff <a.wav> <b.wav>
+
+Directories are searched recursively for .wav, .aif, .aiff and .aifc files. Convert, trim,
+split and concat write AIFF or WAV as the output's extension says, or else as the input is.
+
+Options:
+  -s, --sections <list>  comma-separated sections to show: header, chunks, metadata, samples,
+                         stats, loudness (default all)
+  --threshold <dBFS>     level below which audio is silent, for the loudness section and
+                         split --at silence (default -50)
+  -t, --table            print one table row per file, the default for more than one file
+  -f, --format <name>    text | json | csv (default text)
+  -h, --help             show this message
+
+Convert options:
+  -e, --encoding <name>  pcm | float | alaw | ulaw (default the input's)
+  -b, --bits <n>         8, 16, 24 or 32 for PCM, 32 or 64 for float
+  -c, --channels <map>   mono to downmix, or comma-separated input channels counted from 1,
+                         e.g. 1,1 (mono to stereo), 2,1 (swap) or 3 (extract)
+  -r, --rate <hz>        resample to this sample rate
+  -q, --quality <name>   low | medium | high resampling filter (default medium)
+  -d, --dither           add TPDF dither when quantizing to PCM
+
+Positions are frame numbers, or times such as 1.5s or 250ms. Split writes output_001.wav,
+output_002.wav and so on.
+
+Split options:
+  --at <where>           cues | silence (default cues)
+  --min-silence <pos>    shortest silence to split at (default 0.5s)
+
+Diff compares header fields, metadata chunks and decoded samples, and exits with 1 if the
+files differ.
+
+Render draws to the terminal, or to a PNG image when an output file is given.
+
+Render options:
+  --view <name>          waveform | spectrogram (default waveform)
+  --width <n>            columns or pixels (default 80, or 1024 for PNG)
+  --height <n>           rows or pixels per channel for a waveform, in all for a spectrogram
+                         (default 8 and 24, or 128 and 512 for PNG)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Info,
+    Validate,
+    Repair,
+    Convert,
+    Trim,
+    Split,
+    Concat,
+    Render,
+    Diff,
+}
+
+/// Where the split command cuts a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitAt {
+    Cues,
+    Silence,
+}
+
+/// What the render command draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Waveform,
+    Spectrogram,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// Which parts of the report to show for each file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sections {
+    pub header: bool,
+    pub chunks: bool,
+    pub metadata: bool,
+    pub samples: bool,
+    pub stats: bool,
+    pub loudness: bool,
+}
+
+impl Sections {
+    fn parse(list: &str) -> Result<Self, String> {
+        let mut sections = Sections { header: false, chunks: false, metadata: false, samples: false, stats: false, loudness: false };
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "header" => sections.header = true,
+                "chunks" => sections.chunks = true,
+                "metadata" => sections.metadata = true,
+                "samples" => sections.samples = true,
+                "stats" => sections.stats = true,
+                "loudness" => sections.loudness = true,
+                other => return Err(format!("Unknown section: {}", other)),
+            }
+        }
+        Ok(sections)
+    }
+}
+
+/// Command-line options shared by every command.
+pub struct Options {
+    pub command: Command,
+    /// Files and directories to process.
+    pub inputs: Vec<String>,
+    pub sections: Sections,
+    /// Print a table even for a single file.
+    pub table: bool,
+    pub format: OutputFormat,
+    /// Target format of the convert command.
+    pub conversion: Conversion,
+    /// Range kept by the trim command.
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+    pub split_at: SplitAt,
+    /// Silence level in dBFS for leading and trailing silence and for
+    /// splitting at silence.
+    pub threshold: f64,
+    pub min_silence: Position,
+    pub view: View,
+    /// Size of the rendered view, when not the default.
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+}
+
+fn parse_position(text: &str) -> Result<Position, String> {
+    Position::parse(text).ok_or_else(|| format!("Invalid position: {}", text))
+}
+
+fn parse_size(text: &str) -> Result<usize, String> {
+    match text.parse() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("Invalid size: {}", text)),
+    }
+}
+
+fn parse_remix(map: &str) -> Result<Remix, String> {
+    if map == "mono" {
+        return Ok(Remix::Downmix);
+    }
+    let indexes = map
+        .split(',')
+        .map(|channel| match channel.trim().parse::<usize>() {
+            Ok(channel) if channel > 0 => Ok(channel - 1),
+            _ => Err(format!("Invalid channel: {}", channel)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Remix::Select(indexes))
+}
+
+/// Why the command line was not run.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Help was asked for.
+    Help,
+    /// The arguments are invalid, with the reason.
+    Invalid(String),
+}
+
+impl From<String> for ParseError {
+    fn from(reason: String) -> Self {
+        ParseError::Invalid(reason)
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Options, ParseError> {
+    let mut options = Options {
+        command: Command::Info,
+        inputs: Vec::new(),
+        sections: Sections { header: true, chunks: true, metadata: true, samples: true, stats: true, loudness: true },
+        table: false,
+        format: OutputFormat::Text,
+        conversion: Conversion::default(),
+        start: None,
+        end: None,
+        split_at: SplitAt::Cues,
+        threshold: -50.0,
+        min_silence: Position::Seconds(0.5),
+        view: View::Waveform,
+        width: None,
+        height: None,
+    };
+
+    let mut args = args.iter();
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "info" if first => options.command = Command::Info,
+            "validate" if first => options.command = Command::Validate,
+            "repair" if first => options.command = Command::Repair,
+            "convert" if first => options.command = Command::Convert,
+            "trim" if first => options.command = Command::Trim,
+            "split" if first => options.command = Command::Split,
+            "concat" if first => options.command = Command::Concat,
+            "render" if first => options.command = Command::Render,
+            "diff" if first => options.command = Command::Diff,
+            "-s" | "--sections" => options.sections = Sections::parse(value()?)?,
+            "-t" | "--table" => options.table = true,
+            "-f" | "--format" => {
+                options.format = match value()?.as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    "csv" => OutputFormat::Csv,
+                    other => return Err(format!("Unknown format: {}", other).into()),
+                }
+            }
+            "-e" | "--encoding" => {
+                options.conversion.format = Some(match value()?.as_str() {
+                    "pcm" => AudioFormat::Pcm,
+                    "float" => AudioFormat::IeeeFloat,
+                    "alaw" => AudioFormat::ALaw,
+                    "ulaw" => AudioFormat::MuLaw,
+                    other => return Err(format!("Unknown encoding: {}", other).into()),
+                })
+            }
+            "-b" | "--bits" => {
+                let bits = value()?;
+                options.conversion.bits_per_sample = Some(bits.parse().map_err(|_| format!("Invalid bit depth: {}", bits))?);
+            }
+            "-c" | "--channels" => options.conversion.remix = Some(parse_remix(value()?)?),
+            "-r" | "--rate" => {
+                let rate = value()?;
+                match rate.parse() {
+                    Ok(rate) if rate > 0 => options.conversion.sample_rate = Some(rate),
+                    _ => return Err(format!("Invalid sample rate: {}", rate).into()),
+                }
+            }
+            "-q" | "--quality" => {
+                options.conversion.quality = match value()?.as_str() {
+                    "low" => Quality::Low,
+                    "medium" => Quality::Medium,
+                    "high" => Quality::High,
+                    other => return Err(format!("Unknown quality: {}", other).into()),
+                }
+            }
+            "-d" | "--dither" => options.conversion.dither = true,
+            "--start" => options.start = Some(parse_position(value()?)?),
+            "--end" => options.end = Some(parse_position(value()?)?),
+            "--at" => {
+                options.split_at = match value()?.as_str() {
+                    "cues" => SplitAt::Cues,
+                    "silence" => SplitAt::Silence,
+                    other => return Err(format!("Unknown split point: {}", other).into()),
+                }
+            }
+            "--threshold" => {
+                let threshold = value()?;
+                options.threshold = threshold.parse().map_err(|_| format!("Invalid threshold: {}", threshold))?;
+            }
+            "--min-silence" => options.min_silence = parse_position(value()?)?,
+            "--view" => {
+                options.view = match value()?.as_str() {
+                    "waveform" => View::Waveform,
+                    "spectrogram" => View::Spectrogram,
+                    other => return Err(format!("Unknown view: {}", other).into()),
+                }
+            }
+            "--width" => options.width = Some(parse_size(value()?)?),
+            "--height" => options.height = Some(parse_size(value()?)?),
+            "-h" | "--help" => return Err(ParseError::Help),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag).into()),
+            input => options.inputs.push(input.to_string()),
+        }
+        first = false;
+    }
+
+    match options.command {
+        Command::Convert | Command::Trim | Command::Split if options.inputs.len() != 2 => {
+            return Err("Expected an input and an output file".to_string().into());
+        }
+        Command::Concat if options.inputs.len() < 2 => return Err("Expected input files and an output file".to_string().into()),
+        Command::Diff if options.inputs.len() != 2 => return Err("Expected two files to compare".to_string().into()),
+        Command::Render if options.inputs.len() > 2 => return Err("Expected an input file and at most one output file".to_string().into()),
+        _ => {}
+    }
+    if options.inputs.is_empty() {
+        return Err("Expected at least one file or directory".to_string().into());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Options, ParseError> {
+        parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn help_is_not_a_usage_error() {
+        assert!(matches!(parse_args("--help"), Err(ParseError::Help)));
+        assert!(matches!(parse_args("convert -h a.wav"), Err(ParseError::Help)));
+        assert!(matches!(parse_args("--frobnicate a.wav"), Err(ParseError::Invalid(reason)) if reason == "Unknown option: --frobnicate"));
+        assert!(matches!(parse_args("diff a.wav"), Err(ParseError::Invalid(reason)) if reason == "Expected two files to compare"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest payload of a stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK: usize = 65535;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Wraps `data` in a zlib stream of stored blocks, which every decoder
+/// reads, at the cost of no compression.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn write_chunk(out: &mut dyn Write, id: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut body = id.to_vec();
+    body.extend_from_slice(data);
+    out.write_all(&body)?;
+    out.write_all(&crc32(&body).to_be_bytes())
+}
+
+/// Writes an 8-bit RGB image of `width * height` pixels, row by row from the top.
+pub fn write_rgb(out: &mut dyn Write, width: usize, height: usize, pixels: &[[u8; 3]]) -> io::Result<()> {
+    out.write_all(&SIGNATURE)?;
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), default compression and filter, no interlace.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for row in pixels.chunks(width).take(height) {
+        // Filter type 0: the row is stored as is.
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(out, b"IEND", &[])
+}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use crate::aiff;
+use crate::error::WavError;
+use crate::metadata::{self, Metadata};
+use crate::riff::{self, Chunk};
+use crate::samples::{self, Samples};
+use crate::utils::WAVHeader;
+
+/// Frames per block for callers that stream a whole file, small enough that
+/// memory use does not grow with the file.
+pub const BLOCK_FRAMES: usize = 65536;
+
+/// Metadata chunks larger than this are skipped rather than read into memory.
+pub const MAX_METADATA_CHUNK: u64 = 16 << 20;
+
+/// Reads a WAV or AIFF file through `Read + Seek`: the header is parsed by
+/// seeking from chunk header to chunk header, and samples are read in
+/// blocks, so memory use does not grow with the file.
+pub struct WavReader<R: Read + Seek> {
+    inner: R,
+    header: WAVHeader,
+    chunks: Vec<Chunk>,
+    file_len: u64,
+    /// Bytes of the `data` chunk present in the file, in whole frames, or
+    /// up to a short last block of a compressed codec.
+    data_len: u64,
+    /// Frames in each `block_align` bytes; see `samples::frames_per_block`.
+    frames_per_block: u64,
+    /// Read position within the `data` chunk, at the start of a block.
+    position: u64,
+    /// Index of the next frame `read_block` returns.
+    frame: u64,
+    /// Frames to drop from the start of the next block after `seek_frame`.
+    skip: u64,
+}
+
+impl WavReader<BufReader<File>> {
+    pub fn open(path: &str) -> Result<Self, WavError> {
+        WavReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Reads up to `len` bytes at `offset`, fewer at the end of the file.
+fn read_at<R: Read + Seek>(inner: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    inner.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::with_capacity(len);
+    inner.take(len as u64).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, WavError> {
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        let mut prefix = read_at(&mut inner, 0, 20)?;
+        if prefix.len() == 20 && prefix[12..16] == *b"ds64" {
+            let size = u32::from_le_bytes(prefix[16..20].try_into().unwrap()) as usize;
+            prefix = read_at(&mut inner, 0, 20 + size.min(1 << 20))?;
+        }
+
+        let read_header = |offset: usize| {
+            let mut header = [0; 8];
+            inner.seek(SeekFrom::Start(offset as u64)).ok()?;
+            inner.read_exact(&mut header).ok()?;
+            Some(header)
+        };
+        let is_aiff = aiff::is_aiff(&prefix);
+        let ds64 = riff::read_ds64(&prefix);
+        let chunks = if is_aiff { aiff::walk(file_len, read_header) } else { riff::walk(file_len, ds64.as_ref(), read_header) };
+        let mut payload = |id, len: u64| match riff::find_chunk(&chunks, id) {
+            Some(chunk) => read_at(&mut inner, chunk.data_offset() as u64, chunk.size.min(len) as usize),
+            None => Ok(Vec::new()),
+        };
+        let header = if is_aiff {
+            let comm_bytes = payload(b"COMM", 1 << 16)?;
+            let ssnd_bytes = payload(b"SSND", 8)?;
+            aiff::header(&prefix, &chunks, &comm_bytes, &ssnd_bytes, file_len)?
+        } else {
+            let fmt_bytes = payload(b"fmt ", 1 << 16)?;
+            let fact_bytes = payload(b"fact", 1 << 16)?;
+            WAVHeader::from_chunks(&prefix, &chunks, &fmt_bytes, &fact_bytes, file_len)?
+        };
+
+        let available = header.data_len(file_len);
+        let data_len = if samples::is_compressed(&header) { available } else { available - available % header.block_align.max(1) as u64 };
+        // An invalid ADPCM block layout is reported by `read_block`.
+        let frames_per_block = samples::frames_per_block(&header).unwrap_or(1) as u64;
+        inner.seek(SeekFrom::Start(header.data_offset as u64))?;
+        Ok(WavReader { inner, header, chunks, file_len, data_len, frames_per_block, position: 0, frame: 0, skip: 0 })
+    }
+
+    pub fn header(&self) -> &WAVHeader {
+        &self.header
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Gives up the reader, keeping what was parsed.
+    pub fn into_parts(self) -> (WAVHeader, Vec<Chunk>) {
+        (self.header, self.chunks)
+    }
+
+    /// Number of whole frames in the `data` chunk.
+    pub fn frame_count(&self) -> u64 {
+        samples::frame_count(&self.header, self.data_len).unwrap_or(0)
+    }
+
+    /// Reads the payload of a chunk, cut short if the file is truncated.
+    pub fn read_chunk(&mut self, chunk: &Chunk) -> io::Result<Vec<u8>> {
+        let bytes = read_at(&mut self.inner, chunk.data_offset() as u64, chunk.available(self.file_len) as usize)?;
+        self.inner.seek(SeekFrom::Start(self.header.data_offset as u64 + self.position))?;
+        Ok(bytes)
+    }
+
+    /// Reads the metadata chunks; see `metadata::read`.
+    pub fn metadata(&mut self) -> io::Result<Metadata> {
+        let mut payloads = Vec::new();
+        for chunk in self.chunks.iter().filter(|chunk| metadata::is_metadata_chunk(chunk.id) && chunk.size <= MAX_METADATA_CHUNK) {
+            payloads.push((chunk.id, read_at(&mut self.inner, chunk.data_offset() as u64, chunk.available(self.file_len) as usize)?));
+        }
+        self.inner.seek(SeekFrom::Start(self.header.data_offset as u64 + self.position))?;
+        Ok(metadata::read(payloads.iter().map(|(id, data)| (*id, data.as_slice()))))
+    }
+
+    /// Moves to a frame of the `data` chunk, or its end. Compressed data is
+    /// read from the start of the block holding the frame.
+    pub fn seek_frame(&mut self, frame: u64) -> io::Result<()> {
+        self.frame = frame;
+        self.locate()
+    }
+
+    /// Moves to the block holding `frame`, seeking only if it is not the next one.
+    fn locate(&mut self) -> io::Result<()> {
+        let block = self.frame / self.frames_per_block;
+        let position = (block * self.header.block_align as u64).min(self.data_len);
+        self.skip = self.frame - block * self.frames_per_block;
+        if position != self.position {
+            self.position = position;
+            self.inner.seek(SeekFrom::Start(self.header.data_offset as u64 + position))?;
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes up to `frames` frames, or returns `None` at the end of the data.
+    pub fn read_block(&mut self, frames: usize) -> Result<Option<Samples>, WavError> {
+        let blocks = (frames as u64 + self.skip).div_ceil(self.frames_per_block);
+        let len = (blocks * self.header.block_align as u64).min(self.data_len - self.position);
+        let remaining = self.frame_count().saturating_sub(self.frame);
+        if len == 0 || remaining == 0 {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; len as usize];
+        self.inner.read_exact(&mut bytes)?;
+        self.position += len;
+        let mut samples = samples::decode_data(&self.header, &bytes)?;
+        let count = (frames as u64).min(remaining) as usize;
+        samples.retain_frames(self.skip as usize, self.skip as usize + count);
+        self.frame += samples.frame_count() as u64;
+        // A block only partly returned is decoded again by the next call.
+        self.locate()?;
+        Ok(Some(samples))
+    }
+
+    /// Reads every remaining frame at once.
+    pub fn read_all(&mut self) -> Result<Samples, WavError> {
+        let frames = self.frame_count().saturating_sub(self.frame);
+        match self.read_block(frames as usize)? {
+            Some(samples) => Ok(samples),
+            None => samples::decode_data(&self.header, &[]),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+use std::io::{Read, Seek};
+
+use crate::error::WavError;
+use crate::reader::{WavReader, BLOCK_FRAMES};
+use crate::samples::Buffer;
+
+/// Spectrogram levels are drawn from this level in dBFS (black) up to 0 dBFS.
+pub const SPECTROGRAM_FLOOR: f64 = -120.0;
+/// Smallest FFT used for a spectrogram; more rows need a larger one.
+const MIN_FFT_SIZE: usize = 2048;
+
+/// Shades of the terminal spectrogram, from silent to full scale.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Lowest and highest sample of each column, per channel.
+pub struct Waveform {
+    pub channels: Vec<Vec<(f64, f64)>>,
+}
+
+/// Level in dBFS of each frequency band, per column; band 0 starts at 0 Hz
+/// and the last one ends at the Nyquist frequency.
+pub struct Spectrogram {
+    pub columns: Vec<Vec<f64>>,
+    pub sample_rate: u32,
+}
+
+/// An RGB image, row by row from the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize, background: [u8; 3]) -> Self {
+        Image { width, height, pixels: vec![background; width * height] }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
+
+/// Collects the waveform of a file split into `columns` equal ranges of
+/// frames, fed block by block.
+pub struct WaveformBuilder {
+    columns: usize,
+    frames: u64,
+    /// Index of the next frame pushed.
+    frame: u64,
+    peaks: Vec<Vec<(f64, f64)>>,
+}
+
+impl WaveformBuilder {
+    pub fn new(channels: usize, frames: u64, columns: usize) -> Self {
+        WaveformBuilder { columns, frames, frame: 0, peaks: vec![vec![(f64::INFINITY, f64::NEG_INFINITY); columns]; channels] }
+    }
+
+    pub fn push(&mut self, buffer: &Buffer<f64>) {
+        for frame in buffer.frames() {
+            let column = (self.frame * self.columns as u64 / self.frames.max(1)) as usize;
+            if column >= self.columns {
+                break;
+            }
+            for (peaks, &sample) in self.peaks.iter_mut().zip(frame) {
+                let (min, max) = &mut peaks[column];
+                *min = min.min(sample);
+                *max = max.max(sample);
+            }
+            self.frame += 1;
+        }
+    }
+
+    /// Returns the waveform; columns no frame fell into are flat at zero.
+    pub fn finish(self) -> Waveform {
+        let channels = self
+            .peaks
+            .into_iter()
+            .map(|peaks| peaks.into_iter().map(|(min, max)| if min > max { (0.0, 0.0) } else { (min, max) }).collect())
+            .collect();
+        Waveform { channels }
+    }
+}
+
+/// In-place radix-2 FFT; the length must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Computes a spectrogram of `columns` Hann-windowed FFTs of the channels'
+/// average, spread evenly over a file of `frames` frames, fed block by block.
+pub struct SpectrogramBuilder {
+    rows: usize,
+    columns: usize,
+    frames: u64,
+    window: Vec<f64>,
+    /// Mono samples from frame `start` onwards, not yet needed by earlier columns.
+    samples: Vec<f64>,
+    start: u64,
+    levels: Vec<Vec<f64>>,
+}
+
+impl SpectrogramBuilder {
+    pub fn new(frames: u64, columns: usize, rows: usize) -> Self {
+        let size = (2 * rows).next_power_of_two().max(MIN_FFT_SIZE);
+        let window = (0..size).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / size as f64).cos()).collect();
+        SpectrogramBuilder { rows, columns, frames, window, samples: Vec::new(), start: 0, levels: Vec::with_capacity(columns) }
+    }
+
+    /// First frame of the FFT for `column`.
+    fn column_start(&self, column: usize) -> u64 {
+        column as u64 * self.frames / self.columns as u64
+    }
+
+    /// Levels of the FFT starting at `samples[offset]`, zero-padded past the end.
+    fn analyze(&self, offset: usize) -> Vec<f64> {
+        let size = self.window.len();
+        let mut re: Vec<f64> = (0..size).map(|n| self.samples.get(offset + n).copied().unwrap_or(0.0) * self.window[n]).collect();
+        let mut im = vec![0.0; size];
+        fft(&mut re, &mut im);
+        // A full-scale sine reads 0 dB: the window's sum scales the peak bin.
+        let scale = 2.0 / self.window.iter().sum::<f64>();
+        let bins = size / 2;
+        (0..self.rows)
+            .map(|row| {
+                let first = row * bins / self.rows;
+                let last = ((row + 1) * bins / self.rows).max(first + 1);
+                let peak = (first..last).map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt()).fold(0.0, f64::max);
+                (20.0 * (peak * scale).log10()).max(SPECTROGRAM_FLOOR)
+            })
+            .collect()
+    }
+
+    /// Analyzes every column whose FFT is complete, then drops the samples
+    /// no later column needs.
+    fn drain(&mut self, flush: bool) {
+        let end = self.start + self.samples.len() as u64;
+        while self.levels.len() < self.columns {
+            let column_start = self.column_start(self.levels.len());
+            if !flush && column_start + self.window.len() as u64 > end {
+                break;
+            }
+            let levels = self.analyze((column_start - self.start) as usize);
+            self.levels.push(levels);
+        }
+        let needed = if self.levels.len() < self.columns { self.column_start(self.levels.len()) } else { end };
+        let drop = (needed.min(end) - self.start) as usize;
+        self.samples.drain(..drop);
+        self.start += drop as u64;
+    }
+
+    pub fn push(&mut self, buffer: &Buffer<f64>) {
+        let channels = buffer.channels as f64;
+        self.samples.extend(buffer.frames().map(|frame| frame.iter().sum::<f64>() / channels));
+        self.drain(false);
+    }
+
+    pub fn finish(mut self, sample_rate: u32) -> Spectrogram {
+        self.drain(true);
+        Spectrogram { columns: self.levels, sample_rate }
+    }
+}
+
+/// Reads the waveform of a file into `columns` columns.
+pub fn waveform<R: Read + Seek>(reader: &mut WavReader<R>, columns: usize) -> Result<Waveform, WavError> {
+    let bits = reader.header().container_bits();
+    let mut builder = WaveformBuilder::new(reader.header().num_channels as usize, reader.frame_count(), columns);
+    reader.seek_frame(0)?;
+    while let Some(samples) = reader.read_block(BLOCK_FRAMES)? {
+        builder.push(&samples.to_f64(bits));
+    }
+    Ok(builder.finish())
+}
+
+/// Reads the spectrogram of a file into `columns` columns of `rows` frequency bands.
+pub fn spectrogram<R: Read + Seek>(reader: &mut WavReader<R>, columns: usize, rows: usize) -> Result<Spectrogram, WavError> {
+    let bits = reader.header().container_bits();
+    let mut builder = SpectrogramBuilder::new(reader.frame_count(), columns, rows);
+    reader.seek_frame(0)?;
+    while let Some(samples) = reader.read_block(BLOCK_FRAMES)? {
+        builder.push(&samples.to_f64(bits));
+    }
+    Ok(builder.finish(reader.header().sample_rate))
+}
+
+/// Row of a level within a band of `height` rows, full scale at the top.
+fn level_row(level: f64, height: usize) -> usize {
+    (((1.0 - level.clamp(-1.0, 1.0)) / 2.0 * height as f64) as usize).min(height - 1)
+}
+
+/// Draws each channel in a band of `height` pixels, one column per pixel.
+pub fn waveform_image(waveform: &Waveform, height: usize) -> Image {
+    let width = waveform.channels.first().map_or(0, Vec::len);
+    let mut image = Image::new(width, height * waveform.channels.len(), [24, 24, 32]);
+    for (channel, peaks) in waveform.channels.iter().enumerate() {
+        let top = channel * height;
+        for x in 0..width {
+            image.set(x, top + height / 2, [72, 72, 88]);
+        }
+        for (x, &(min, max)) in peaks.iter().enumerate() {
+            for y in level_row(max, height)..=level_row(min, height) {
+                image.set(x, top + y, [96, 200, 120]);
+            }
+        }
+    }
+    image
+}
+
+/// Color of a level from the floor (black) through blue, red and yellow to white at 0 dBFS.
+fn heat(level: f64) -> [u8; 3] {
+    const STOPS: [[f64; 3]; 5] = [[0.0, 0.0, 0.0], [40.0, 20.0, 140.0], [200.0, 30.0, 60.0], [250.0, 200.0, 30.0], [255.0, 255.0, 255.0]];
+    let position = (1.0 - level / SPECTROGRAM_FLOOR).clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let frac = position - index as f64;
+    let color = |i: usize| (STOPS[index][i] + (STOPS[index + 1][i] - STOPS[index][i]) * frac).round() as u8;
+    [color(0), color(1), color(2)]
+}
+
+/// Draws one pixel per column and band, low frequencies at the bottom.
+pub fn spectrogram_image(spectrogram: &Spectrogram) -> Image {
+    let height = spectrogram.columns.first().map_or(0, Vec::len);
+    let mut image = Image::new(spectrogram.columns.len(), height, [0, 0, 0]);
+    for (x, levels) in spectrogram.columns.iter().enumerate() {
+        for (row, &level) in levels.iter().enumerate() {
+            image.set(x, height - 1 - row, heat(level));
+        }
+    }
+    image
+}
+
+/// Draws each channel in `rows` lines of block characters, two levels per
+/// character, with a rule between channels.
+pub fn waveform_text(waveform: &Waveform, rows: usize) -> String {
+    let mut lines = Vec::new();
+    for (channel, peaks) in waveform.channels.iter().enumerate() {
+        if channel > 0 {
+            lines.push("─".repeat(peaks.len()));
+        }
+        let spans: Vec<(usize, usize)> = peaks.iter().map(|&(min, max)| (level_row(max, 2 * rows), level_row(min, 2 * rows))).collect();
+        for row in 0..rows {
+            let line = spans
+                .iter()
+                .map(|&(top, bottom)| match (top <= 2 * row && 2 * row <= bottom, top <= 2 * row + 1 && 2 * row < bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect::<String>();
+            lines.push(line.trim_end().to_string());
+        }
+    }
+    lines.join("\n")
+}
+
+/// Draws one character per column and band, low frequencies at the bottom,
+/// shaded by level.
+pub fn spectrogram_text(spectrogram: &Spectrogram) -> String {
+    let rows = spectrogram.columns.first().map_or(0, Vec::len);
+    (0..rows)
+        .rev()
+        .map(|row| {
+            let line: String = spectrogram
+                .columns
+                .iter()
+                .map(|levels| {
+                    let shade = (1.0 - levels[row] / SPECTROGRAM_FLOOR) * SHADES.len() as f64;
+                    SHADES[(shade as usize).min(SHADES.len() - 1)]
+                })
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
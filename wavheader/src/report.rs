@@ -0,0 +1,452 @@
+use std::io::{self, Read, Seek, Write};
+
+use wavheader::error::WavError;
+use wavheader::format::{self, AudioFormat};
+use wavheader::loudness::{Loudness, LoudnessMeter};
+use wavheader::metadata::{self, Metadata};
+use wavheader::reader::{WavReader, BLOCK_FRAMES};
+use wavheader::riff::{self, Chunk};
+use wavheader::stats::{self, Analyzer, ChannelStats, SilenceDetector};
+use wavheader::utils::WAVHeader;
+use wavheader::validate::{self, Issue};
+use wavheader::samples;
+
+use crate::options::{Command, Options};
+
+/// What was learned about the decoded samples of a file.
+pub struct SampleInfo {
+    pub type_name: &'static str,
+    pub channels: usize,
+    pub frames: usize,
+    /// Per-channel levels, when the stats section is shown.
+    pub stats: Option<Vec<ChannelStats>>,
+    /// Loudness and silence, when the loudness section is shown.
+    pub loudness: Option<LoudnessInfo>,
+}
+
+/// EBU R128 loudness of a file with its true peak and the silence at either end.
+pub struct LoudnessInfo {
+    pub loudness: Loudness,
+    /// Highest true peak of any channel.
+    pub true_peak: f64,
+    /// Frames quieter than the threshold at the start and at the end.
+    pub leading_silence: u64,
+    pub trailing_silence: u64,
+}
+
+/// Everything shown for one file.
+pub struct Report {
+    pub path: String,
+    pub file_len: usize,
+    pub header: WAVHeader,
+    pub chunks: Vec<Chunk>,
+    pub metadata: Metadata,
+    /// Decoded samples, or why they could not be decoded; `None` when not requested.
+    pub samples: Option<Result<SampleInfo, WavError>>,
+    /// Header inconsistencies, for the validate and repair commands.
+    pub issues: Vec<Issue>,
+}
+
+/// Streams the samples of a file block by block, measuring levels if
+/// `stats` is set and loudness if `silence_threshold`, linear, is given.
+fn read_samples<R: Read + Seek>(reader: &mut WavReader<R>, stats: bool, silence_threshold: Option<f64>) -> Result<SampleInfo, WavError> {
+    let header = reader.header();
+    let bits = header.container_bits();
+    let clip_level = stats::clip_level(header);
+    let mut meter = silence_threshold.map(|_| LoudnessMeter::new(header));
+    let mut detector = silence_threshold.map(|threshold| SilenceDetector::new(threshold, 1));
+    let mut block = match reader.read_block(BLOCK_FRAMES)? {
+        Some(samples) => samples,
+        None => samples::decode_data(reader.header(), &[])?,
+    };
+    let mut info = SampleInfo { type_name: block.type_name(), channels: block.channels(), frames: 0, stats: None, loudness: None };
+    let mut analyzer = Analyzer::new(info.channels, clip_level);
+    loop {
+        info.frames += block.frame_count();
+        if stats || meter.is_some() {
+            let buffer = block.to_f64(bits);
+            analyzer.push(&buffer);
+            if let (Some(meter), Some(detector)) = (&mut meter, &mut detector) {
+                meter.push(&buffer);
+                detector.push(&buffer);
+            }
+        }
+        match reader.read_block(BLOCK_FRAMES)? {
+            Some(samples) => block = samples,
+            None => break,
+        }
+    }
+    if !stats && meter.is_none() {
+        return Ok(info);
+    }
+    let channels = analyzer.finish();
+    if let (Some(meter), Some(detector)) = (meter, detector) {
+        let (leading_silence, trailing_silence) = stats::edge_silence(&detector.finish(), info.frames as u64);
+        info.loudness = Some(LoudnessInfo {
+            loudness: meter.finish(),
+            true_peak: channels.iter().map(|c| c.true_peak).fold(0.0, f64::max),
+            leading_silence,
+            trailing_silence,
+        });
+    }
+    info.stats = stats.then_some(channels);
+    Ok(info)
+}
+
+/// Reads and parses a file, and runs the command on it.
+pub fn build(path: &str, options: &Options) -> Result<Report, WavError> {
+    let mut reader = WavReader::open(path)?;
+    let sections = options.sections;
+
+    let samples = if options.command == Command::Info && (sections.samples || sections.stats || sections.loudness) {
+        let silence_threshold = sections.loudness.then(|| 10f64.powf(options.threshold / 20.0));
+        Some(read_samples(&mut reader, sections.stats, silence_threshold))
+    } else {
+        None
+    };
+
+    let file_len = reader.file_len();
+    let issues = match options.command {
+        Command::Validate => validate::validate(reader.header(), reader.chunks(), file_len),
+        Command::Repair => validate::repair(path, reader.header(), reader.chunks(), file_len)?,
+        _ => Vec::new(),
+    };
+
+    let metadata = reader.metadata()?;
+    let (header, chunks) = reader.into_parts();
+    Ok(Report { path: path.to_string(), file_len: file_len as usize, header, chunks, metadata, samples, issues })
+}
+
+/// The format code followed by the codec name, e.g. `1 (PCM)`.
+fn format_name(header: &WAVHeader) -> String {
+    match header.format_tag() {
+        AudioFormat::Extensible => format!("{} (extensible: {})", header.audio_format, header.codec()),
+        format => format!("{} ({})", header.audio_format, format),
+    }
+}
+
+fn write_header(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    let header = &report.header;
+    writeln!(
+        out,
+        ".WAV Header extracted {{\n\tChunkID: {}\n\tChunkSize: {}\n\tFormat: {}\n\tSubChunk1ID: {}\n\tSubChunk1Size: {}\n\tAudioFormat: {}\n\tNumChannels: {}\n\tSampleRate: {}\n\tByteRate: {}\n\tBlockAlign: {}\n\tBitsPerSample: {}",
+        riff::fourcc_str(header.chunk_id),
+        header.chunk_size,
+        riff::fourcc_str(header.format),
+        riff::fourcc_str(header.sub_chunk_1_id),
+        header.sub_chunk_1_size,
+        format_name(header),
+        header.num_channels,
+        header.sample_rate,
+        header.byte_rate,
+        header.block_align,
+        header.bits_per_sample,
+    )?;
+    if let Some(compression) = header.compression {
+        writeln!(out, "\tCompressionType: {}", riff::fourcc_str(compression))?;
+    } else if header.sub_chunk_1_size >= 18 && !header.is_aiff() {
+        writeln!(out, "\tExtensionSize: {}", header.extension_size)?;
+    }
+    if let Some(sub_format) = header.sub_format {
+        writeln!(
+            out,
+            "\tValidBitsPerSample: {}\n\tChannelMask: 0x{:08X} ({})\n\tSubFormat: {}",
+            header.valid_bits_per_sample,
+            header.channel_mask,
+            format::channel_mask_str(header.channel_mask),
+            sub_format,
+        )?;
+    }
+    writeln!(
+        out,
+        "\tSubChunk2ID: {}\n\tSubChunk2Size: {}\n}}",
+        riff::fourcc_str(header.sub_chunk_2_id),
+        header.sub_chunk_2_size,
+    )
+}
+
+fn write_chunks(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    writeln!(out, "Chunks {{")?;
+    for chunk in &report.chunks {
+        let truncated = if chunk.is_truncated(report.file_len) { " (truncated)" } else { "" };
+        writeln!(
+            out,
+            "\t{}: offset {}, size {}{}",
+            riff::fourcc_str(chunk.id),
+            chunk.offset,
+            chunk.size,
+            truncated
+        )?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_metadata(out: &mut dyn Write, metadata: &Metadata) -> io::Result<()> {
+    writeln!(out, "Metadata {{")?;
+    for tag in &metadata.info {
+        let name = tag.name().map_or(String::new(), |name| format!(" ({})", name));
+        writeln!(out, "\tINFO {}{}: {}", riff::fourcc_str(tag.id), name, tag.value)?;
+    }
+    if let Some(bext) = &metadata.bext {
+        writeln!(
+            out,
+            "\tbext Description: {}\n\tbext Originator: {}\n\tbext OriginatorReference: {}\n\tbext Origination: {} {}\n\tbext TimeReference: {}\n\tbext Version: {}",
+            bext.description,
+            bext.originator,
+            bext.originator_reference,
+            bext.origination_date,
+            bext.origination_time,
+            bext.time_reference,
+            bext.version
+        )?;
+        if bext.has_umid() {
+            let umid: String = bext.umid.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(out, "\tbext UMID: {}", umid.trim_end_matches("00"))?;
+        }
+        if bext.version >= 2 {
+            writeln!(
+                out,
+                "\tbext Loudness: {:.2} LUFS, range {:.2} LU, max true peak {:.2} dBTP, max momentary {:.2} LUFS, max short-term {:.2} LUFS",
+                bext.loudness_value as f64 / 100.0,
+                bext.loudness_range as f64 / 100.0,
+                bext.max_true_peak_level as f64 / 100.0,
+                bext.max_momentary_loudness as f64 / 100.0,
+                bext.max_short_term_loudness as f64 / 100.0
+            )?;
+        }
+        for line in bext.coding_history.lines() {
+            writeln!(out, "\tbext CodingHistory: {}", line)?;
+        }
+    }
+    if let Some(xml) = &metadata.ixml {
+        writeln!(out, "\tiXML: {} bytes", xml.len())?;
+        for name in ["PROJECT", "SCENE", "TAKE", "TAPE", "NOTE"] {
+            if let Some(value) = metadata::ixml_element(xml, name).filter(|value| !value.is_empty()) {
+                writeln!(out, "\tiXML {}: {}", name, value)?;
+            }
+        }
+    }
+    for cue in &metadata.cues {
+        write!(out, "\tCue {}: sample {}", cue.id, cue.position)?;
+        if let Some(length) = cue.length {
+            write!(out, ", length {}", length)?;
+        }
+        if let Some(label) = &cue.label {
+            write!(out, ", label \"{}\"", label)?;
+        }
+        if let Some(note) = &cue.note {
+            write!(out, ", note \"{}\"", note)?;
+        }
+        writeln!(out)?;
+    }
+    if let Some(sampler) = &metadata.sampler {
+        writeln!(out, "\tsmpl: unity note {}, {} loop(s)", sampler.midi_unity_note, sampler.loops.len())?;
+        for sample_loop in &sampler.loops {
+            let loop_type = match sample_loop.loop_type {
+                0 => "forward",
+                1 => "alternating",
+                2 => "backward",
+                _ => "custom",
+            };
+            let count = if sample_loop.play_count == 0 { "infinite".to_string() } else { sample_loop.play_count.to_string() };
+            writeln!(
+                out,
+                "\tLoop {}: {} {}..{}, play count {}",
+                sample_loop.id, loop_type, sample_loop.start, sample_loop.end, count
+            )?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+fn write_stats(out: &mut dyn Write, header: &WAVHeader, file_len: u64, channels: &[ChannelStats]) -> io::Result<()> {
+    writeln!(out, "Statistics {{")?;
+    if let Some(duration) = stats::duration(header, file_len) {
+        writeln!(out, "\tDuration: {:.3} s", duration)?;
+    }
+    for (channel, stats) in channels.iter().enumerate() {
+        writeln!(
+            out,
+            "\tChannel {}: Peak {:.2} dBFS, RMS {:.2} dBFS, DC offset {:.6}, Clipped {}, True peak {:.2} dBTP",
+            channel + 1,
+            stats::to_db(stats.peak),
+            stats::to_db(stats.rms),
+            stats.dc_offset,
+            stats.clipped,
+            stats::to_db(stats.true_peak)
+        )?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_loudness(out: &mut dyn Write, header: &WAVHeader, info: &LoudnessInfo) -> io::Result<()> {
+    let loudness = &info.loudness;
+    let seconds = |frames: u64| if header.sample_rate > 0 { frames as f64 / header.sample_rate as f64 } else { 0.0 };
+    writeln!(
+        out,
+        "Loudness {{\n\tIntegrated: {:.1} LUFS\n\tRange: {:.1} LU\n\tMax momentary: {:.1} LUFS\n\tMax short-term: {:.1} LUFS\n\tTrue peak: {:.2} dBTP\n\tLeading silence: {:.3} s\n\tTrailing silence: {:.3} s\n}}",
+        loudness.integrated,
+        loudness.range,
+        loudness.max_momentary,
+        loudness.max_short_term,
+        stats::to_db(info.true_peak),
+        seconds(info.leading_silence),
+        seconds(info.trailing_silence)
+    )
+}
+
+fn write_issues(out: &mut dyn Write, report: &Report, command: Command) -> io::Result<()> {
+    let repairing = command == Command::Repair;
+    writeln!(out, "{} {{", if repairing { "Repair" } else { "Validation" })?;
+    for issue in &report.issues {
+        let action = if repairing && issue.patch.is_some() { " (repaired)" } else { "" };
+        writeln!(out, "\t{}: {} (expected {}) {}{}", issue.field, issue.found, issue.expected, issue.reason, action)?;
+    }
+    writeln!(out, "}}")?;
+    if repairing {
+        let repaired = report.issues.iter().filter(|issue| issue.patch.is_some()).count();
+        writeln!(out, "{} of {} issue(s) repaired", repaired, report.issues.len())
+    } else {
+        writeln!(out, "{} issue(s) found", report.issues.len())
+    }
+}
+
+/// Writes the full report for one file, one block per section.
+pub fn write_block(out: &mut dyn Write, report: &Report, options: &Options) -> io::Result<()> {
+    let sections = options.sections;
+    writeln!(out, "File {} ({} bytes)", report.path, report.file_len)?;
+    if sections.header {
+        write_header(out, report)?;
+    }
+    if sections.chunks {
+        write_chunks(out, report)?;
+    }
+    if sections.metadata && !report.metadata.is_empty() {
+        write_metadata(out, &report.metadata)?;
+    }
+    match &report.samples {
+        Some(Ok(samples)) => {
+            if sections.samples {
+                writeln!(
+                    out,
+                    "Samples {{\n\tType: {}\n\tChannels: {}\n\tFrames: {}\n}}",
+                    samples.type_name, samples.channels, samples.frames
+                )?;
+            }
+            if let Some(channels) = &samples.stats {
+                write_stats(out, &report.header, report.file_len as u64, channels)?;
+            }
+            if let Some(loudness) = &samples.loudness {
+                write_loudness(out, &report.header, loudness)?;
+            }
+        }
+        Some(Err(e)) => writeln!(out, "Samples {{\n\t{}\n}}", e)?,
+        None => {}
+    }
+    if options.command != Command::Info {
+        write_issues(out, report, options.command)?;
+    }
+    Ok(())
+}
+
+/// Column titles of the table for the command and sections.
+fn table_titles(options: &Options) -> Vec<&'static str> {
+    let mut titles = vec!["File", "Format", "Ch", "Rate", "Bits", "Duration"];
+    match options.command {
+        Command::Info => {
+            if options.sections.stats {
+                titles.extend(["Peak", "RMS", "Clipped"]);
+            }
+            if options.sections.loudness {
+                titles.extend(["Loudness", "LRA"]);
+            }
+        }
+        Command::Validate => titles.push("Issues"),
+        Command::Repair => titles.extend(["Issues", "Repaired"]),
+        _ => {}
+    }
+    titles
+}
+
+fn table_row(report: &Report, options: &Options) -> Vec<String> {
+    let header = &report.header;
+    let mut row = vec![
+        report.path.clone(),
+        header.codec().to_string(),
+        header.num_channels.to_string(),
+        header.sample_rate.to_string(),
+        header.bits_per_sample.to_string(),
+        stats::duration(header, report.file_len as u64).map_or("-".to_string(), |duration| format!("{:.3} s", duration)),
+    ];
+    match options.command {
+        Command::Info => {
+            if options.sections.stats {
+                match &report.samples {
+                    Some(Ok(SampleInfo { stats: Some(channels), .. })) => {
+                        let peak = channels.iter().map(|c| c.peak).fold(0.0, f64::max);
+                        let rms = channels.iter().map(|c| c.rms).fold(0.0, f64::max);
+                        let clipped: usize = channels.iter().map(|c| c.clipped).sum();
+                        row.extend([format!("{:.2} dBFS", stats::to_db(peak)), format!("{:.2} dBFS", stats::to_db(rms)), clipped.to_string()]);
+                    }
+                    _ => row.extend(["-".to_string(), "-".to_string(), "-".to_string()]),
+                }
+            }
+            if options.sections.loudness {
+                match &report.samples {
+                    Some(Ok(SampleInfo { loudness: Some(info), .. })) => {
+                        row.extend([format!("{:.1} LUFS", info.loudness.integrated), format!("{:.1} LU", info.loudness.range)]);
+                    }
+                    _ => row.extend(["-".to_string(), "-".to_string()]),
+                }
+            }
+        }
+        Command::Validate => row.push(report.issues.len().to_string()),
+        Command::Repair => {
+            row.push(report.issues.len().to_string());
+            row.push(report.issues.iter().filter(|issue| issue.patch.is_some()).count().to_string());
+        }
+        _ => {}
+    }
+    row
+}
+
+/// Writes one row per successfully processed file, with aligned columns.
+pub fn write_table(out: &mut dyn Write, reports: &[Report], options: &Options) -> io::Result<()> {
+    let titles = table_titles(options);
+    let rows: Vec<Vec<String>> = reports.iter().map(|report| table_row(report, options)).collect();
+    let widths: Vec<usize> = (0..titles.len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).fold(titles[column].len(), usize::max))
+        .collect();
+
+    let titles: Vec<String> = titles.iter().map(|title| title.to_string()).collect();
+    for row in std::iter::once(&titles).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, width))| {
+                let padding = " ".repeat(width - cell.chars().count());
+                if column == 0 { format!("{}{}", cell, padding) } else { format!("{}{}", padding, cell) }
+            })
+            .collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+/// Writes the files that could not be processed and a count of each outcome.
+pub fn write_summary(out: &mut dyn Write, reports: &[Report], failures: &[(String, WavError)]) -> io::Result<()> {
+    if !failures.is_empty() {
+        writeln!(out, "Failures {{")?;
+        for (path, e) in failures {
+            writeln!(out, "\t{}: {}", path, e)?;
+        }
+        writeln!(out, "}}")?;
+    }
+    let with_issues = reports.iter().filter(|report| !report.issues.is_empty()).count();
+    write!(out, "{} file(s) processed, {} failed", reports.len() + failures.len(), failures.len())?;
+    if with_issues > 0 {
+        write!(out, ", {} with header issues", with_issues)?;
+    }
+    writeln!(out)
+}
@@ -0,0 +1,210 @@
+use std::f64::consts::PI;
+
+use crate::samples::Buffer;
+
+/// Trade-off between filter length and how sharply the resampler cuts above
+/// the lower of the two Nyquist frequencies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// 8 zero crossings per side, passband to 85% of Nyquist.
+    Low,
+    /// 16 zero crossings per side, passband to 90% of Nyquist.
+    #[default]
+    Medium,
+    /// 32 zero crossings per side, passband to 95% of Nyquist.
+    High,
+}
+
+impl Quality {
+    fn zero_crossings(self) -> usize {
+        match self {
+            Quality::Low => 8,
+            Quality::Medium => 16,
+            Quality::High => 32,
+        }
+    }
+
+    fn rolloff(self) -> f64 {
+        match self {
+            Quality::Low => 0.85,
+            Quality::Medium => 0.90,
+            Quality::High => 0.95,
+        }
+    }
+}
+
+/// Phases of the filter table; ratios with a larger numerator use the
+/// nearest phase.
+const MAX_PHASES: u64 = 4096;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Polyphase windowed-sinc resampler. Rates are reduced to a ratio `up /
+/// down`, and output frame `n` is interpolated at input position
+/// `n * down / up` with a Blackman-windowed sinc whose cutoff is the lower
+/// Nyquist frequency. Frames are streamed through `process`, and `finish`
+/// flushes the filter tail.
+pub struct Resampler {
+    channels: usize,
+    up: u64,
+    down: u64,
+    /// Filter taps on each side of the interpolation point.
+    half: usize,
+    phases: u64,
+    /// `phases` rows of `2 * half` coefficients.
+    table: Vec<f64>,
+    /// Interleaved input frames still needed, starting at frame `start`.
+    history: Vec<f64>,
+    start: i64,
+    /// Input frames pushed so far.
+    input_frames: u64,
+    /// Index of the next output frame.
+    next: u64,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, from: u32, to: u32, quality: Quality) -> Self {
+        let divisor = gcd(from as u64, to as u64);
+        let (up, down) = (to as u64 / divisor, from as u64 / divisor);
+        let cutoff = 0.5 * (up as f64 / down as f64).min(1.0) * quality.rolloff();
+        let half = (quality.zero_crossings() as f64 / (2.0 * cutoff)).ceil() as usize;
+        let phases = up.min(MAX_PHASES);
+
+        let mut table = Vec::with_capacity(phases as usize * 2 * half);
+        for phase in 0..phases {
+            let frac = phase as f64 / phases as f64;
+            let row: Vec<f64> = (0..2 * half)
+                .map(|k| {
+                    let x = half as f64 - 1.0 - k as f64 + frac;
+                    let sinc = if x == 0.0 { 1.0 } else { (2.0 * PI * cutoff * x).sin() / (2.0 * PI * cutoff * x) };
+                    let w = x / half as f64;
+                    let window = if w.abs() >= 1.0 { 0.0 } else { 0.42 + 0.5 * (PI * w).cos() + 0.08 * (2.0 * PI * w).cos() };
+                    sinc * window
+                })
+                .collect();
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|c| c / sum));
+        }
+
+        Resampler {
+            channels,
+            up,
+            down,
+            half,
+            phases,
+            table,
+            history: vec![0.0; (half - 1) * channels],
+            start: -(half as i64 - 1),
+            input_frames: 0,
+            next: 0,
+        }
+    }
+
+    /// Computes every output frame whose filter window lies within the history,
+    /// stopping at `limit` output frames.
+    fn drain(&mut self, limit: u64) -> Buffer<f64> {
+        let channels = self.channels;
+        let available = self.start + (self.history.len() / channels) as i64;
+        let mut out = Vec::new();
+        while self.next < limit {
+            let position = self.next * self.down;
+            let mut index = (position / self.up) as i64;
+            let mut phase = ((position % self.up) * self.phases + self.up / 2) / self.up;
+            // rounding up past the last phase lands on the next input sample
+            if phase == self.phases {
+                index += 1;
+                phase = 0;
+            }
+            if index + self.half as i64 >= available {
+                break;
+            }
+            let row = &self.table[phase as usize * 2 * self.half..][..2 * self.half];
+            let first = (index - self.half as i64 + 1 - self.start) as usize;
+            for channel in 0..channels {
+                let sum: f64 = row.iter().enumerate().map(|(k, c)| c * self.history[(first + k) * channels + channel]).sum();
+                out.push(sum);
+            }
+            self.next += 1;
+        }
+
+        let needed = (self.next * self.down / self.up) as i64 - self.half as i64 + 1;
+        if needed > self.start {
+            let drop = ((needed - self.start) as usize).min(self.history.len() / channels);
+            self.history.drain(..drop * channels);
+            self.start += drop as i64;
+        }
+        Buffer::new(channels, out)
+    }
+
+    /// Pushes interleaved input frames and returns the output frames now complete.
+    pub fn process(&mut self, input: &Buffer<f64>) -> Buffer<f64> {
+        self.history.extend_from_slice(&input.data[..input.frame_count() * self.channels]);
+        self.input_frames += input.frame_count() as u64;
+        self.drain(u64::MAX)
+    }
+
+    /// Returns the remaining output frames, up to `input_frames * up / down`
+    /// in all, rounded up.
+    pub fn finish(&mut self) -> Buffer<f64> {
+        self.history.extend(std::iter::repeat_n(0.0, self.half * self.channels));
+        let total = (self.input_frames * self.up).div_ceil(self.down);
+        self.drain(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resamples `frames` frames of a mono sine in blocks, as `convert` does.
+    fn resample_sine(frames: usize, frequency: f64, from: u32, to: u32) -> Vec<f64> {
+        let input: Vec<f64> = (0..frames).map(|n| 0.5 * (2.0 * PI * frequency * n as f64 / from as f64).sin()).collect();
+        let mut resampler = Resampler::new(1, from, to, Quality::Medium);
+        let mut output = Vec::new();
+        for block in input.chunks(4096) {
+            output.extend(resampler.process(&Buffer::new(1, block.to_vec())).data);
+        }
+        output.extend(resampler.finish().data);
+        output
+    }
+
+    /// Level of the middle of a signal relative to a 0.5 amplitude sine, in dB.
+    fn gain_db(output: &[f64]) -> f64 {
+        let middle = &output[output.len() / 10..output.len() * 9 / 10];
+        let rms = (middle.iter().map(|s| s * s).sum::<f64>() / middle.len() as f64).sqrt();
+        20.0 * (rms / (0.5 / 2f64.sqrt())).log10()
+    }
+
+    #[test]
+    fn output_frame_count_follows_ratio() {
+        assert_eq!(resample_sine(44100, 1000.0, 44100, 48000).len(), 48000);
+        assert_eq!(resample_sine(48000, 1000.0, 48000, 44100).len(), 44100);
+        // 1000 * 160 / 147 rounded up.
+        assert_eq!(resample_sine(1000, 1000.0, 44100, 48000).len(), 1089);
+    }
+
+    #[test]
+    fn passband_gain_is_flat() {
+        for frequency in [100.0, 1000.0, 10000.0, 15000.0] {
+            let up = gain_db(&resample_sine(44100, frequency, 44100, 48000));
+            let down = gain_db(&resample_sine(48000, frequency, 48000, 44100));
+            assert!(up.abs() < 0.05 && down.abs() < 0.05, "{} Hz: {:.4} dB up, {:.4} dB down", frequency, up, down);
+        }
+    }
+
+    #[test]
+    fn ratios_past_max_phases_use_the_nearest_phase() {
+        // 44100 -> 44101 needs 44101 phases; rounding down to one of 4096
+        // instead delays some output by up to a whole phase step
+        let output = resample_sine(44100, 5000.0, 44100, 44101);
+        assert_eq!(output.len(), 44101);
+        let error = output[4410..39690]
+            .iter()
+            .enumerate()
+            .map(|(n, sample)| (sample - 0.5 * (2.0 * PI * 5000.0 * (n + 4410) as f64 / 44101.0).sin()).abs())
+            .fold(0.0, f64::max);
+        assert!(20.0 * (error / 0.5).log10() < -78.0, "{}", error);
+    }
+}
@@ -0,0 +1,196 @@
+/// A chunk inside a RIFF file.
+#[derive(Clone, Copy)]
+pub struct Chunk {
+    /// Four-character chunk ID, read big-endian like the header IDs.
+    pub id: u32,
+    /// Offset of the chunk header from the start of the file.
+    pub offset: usize,
+    /// Size of the chunk data without the pad byte, from its header or, in
+    /// an RF64 file, from the `ds64` chunk.
+    pub size: u64,
+}
+
+impl Chunk {
+    /// Offset of the chunk data from the start of the file.
+    pub fn data_offset(&self) -> usize {
+        self.offset + 8
+    }
+
+    /// Returns the chunk data, cut short if the file is truncated.
+    pub fn data<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        let start = self.data_offset().min(bytes.len());
+        let end = (self.data_offset() as u64).saturating_add(self.size).min(bytes.len() as u64) as usize;
+        &bytes[start..end]
+    }
+
+    /// Bytes of the chunk data present in a file of `file_len` bytes.
+    pub fn available(&self, file_len: u64) -> u64 {
+        file_len.saturating_sub(self.data_offset() as u64).min(self.size)
+    }
+
+    /// Returns true if the chunk data extends past the end of the file.
+    pub fn is_truncated(&self, file_len: usize) -> bool {
+        (self.data_offset() as u64).saturating_add(self.size) > file_len as u64
+    }
+}
+
+/// Returns the four-character code of a chunk ID.
+pub fn fourcc(id: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*id)
+}
+
+/// Returns a chunk ID as text, or its hex value if it is not printable.
+pub fn fourcc_str(id: u32) -> String {
+    if is_valid_fourcc(id) {
+        String::from_utf8_lossy(&id.to_be_bytes()).to_string()
+    } else {
+        format!("0x{:08X}", id)
+    }
+}
+
+/// Returns true if every byte of a chunk ID is printable ASCII, as in every
+/// registered chunk type.
+pub fn is_valid_fourcc(id: u32) -> bool {
+    id.to_be_bytes().iter().all(|b| b.is_ascii_graphic() || *b == b' ')
+}
+
+/// Returns true for the IDs of RF64 and BW64 files, whose 32-bit sizes are
+/// replaced by 64-bit ones in a `ds64` chunk.
+pub fn is_rf64(id: u32) -> bool {
+    id == fourcc(b"RF64") || id == fourcc(b"BW64")
+}
+
+/// The `ds64` chunk of an RF64 file.
+pub struct Ds64 {
+    /// Offset of the chunk data from the start of the file.
+    pub offset: usize,
+    pub riff_size: u64,
+    pub data_size: u64,
+    pub sample_count: u64,
+    /// 64-bit sizes of chunks other than `data`.
+    pub table: Vec<(u32, u64)>,
+}
+
+/// Size of a `ds64` chunk without a table, which writers reserve up front.
+pub const DS64_SIZE: usize = 28;
+
+fn le_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Reads the `ds64` chunk, which must be the first chunk of an RF64 file.
+pub fn read_ds64(bytes: &[u8]) -> Option<Ds64> {
+    if bytes.len() < 20 + DS64_SIZE || !is_rf64(u32::from_be_bytes(bytes[0..4].try_into().unwrap())) {
+        return None;
+    }
+    if bytes[12..16] != *b"ds64" {
+        return None;
+    }
+    let size = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+    let data = &bytes[20..(20 + size).min(bytes.len())];
+    if data.len() < DS64_SIZE {
+        return None;
+    }
+    let count = u32::from_le_bytes(data[24..28].try_into().unwrap()) as usize;
+    let table = data[DS64_SIZE..]
+        .chunks_exact(12)
+        .take(count)
+        .map(|entry| (u32::from_be_bytes(entry[0..4].try_into().unwrap()), le_u64(entry, 4)))
+        .collect();
+    Some(Ds64 { offset: 20, riff_size: le_u64(data, 0), data_size: le_u64(data, 8), sample_count: le_u64(data, 16), table })
+}
+
+/// Walks every chunk after the 12-byte RIFF header. Each chunk is an ID, a
+/// little-endian size and its data, padded to an even length. In an RF64 file
+/// a size of 0xFFFFFFFF is looked up in the `ds64` chunk. Stops at the first
+/// chunk header that does not fit in the file; the last chunk may be truncated.
+pub fn walk_chunks(bytes: &[u8]) -> Vec<Chunk> {
+    walk(bytes.len() as u64, read_ds64(bytes).as_ref(), |offset| {
+        bytes.get(offset..offset + 8).map(|header| header.try_into().unwrap())
+    })
+}
+
+/// Walks the chunks of a file of `len` bytes like `walk_chunks`, getting the
+/// 8-byte header at each offset from `read_header`, so the file need not be in memory.
+pub fn walk(len: u64, ds64: Option<&Ds64>, mut read_header: impl FnMut(usize) -> Option<[u8; 8]>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset as u64 + 8 <= len {
+        let Some(header) = read_header(offset) else {
+            break;
+        };
+        let id = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let mut size = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+        if size == u32::MAX as u64
+            && let Some(ds64) = ds64
+        {
+            if id == fourcc(b"data") {
+                size = ds64.data_size;
+            } else if let Some(&(_, table_size)) = ds64.table.iter().find(|(table_id, _)| *table_id == id) {
+                size = table_size;
+            }
+        }
+        let chunk = Chunk { id, offset, size };
+        offset = chunk.data_offset().saturating_add(size as usize).saturating_add(size as usize & 1);
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Returns the first chunk with the given ID.
+pub fn find_chunk<'a>(chunks: &'a [Chunk], id: &[u8; 4]) -> Option<&'a Chunk> {
+    chunks.iter().find(|chunk| chunk.id == fourcc(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::WAVHeader;
+
+    /// The start of an RF64 file of 4 GiB + 256 bytes whose `ds64` chunk
+    /// lists a 64-bit `LIST` size, followed by `fmt ` and `data` chunks.
+    fn rf64(ds64_size: u32) -> Vec<u8> {
+        let mut bytes = b"RF64\xFF\xFF\xFF\xFFWAVEds64".to_vec();
+        bytes.extend_from_slice(&ds64_size.to_le_bytes());
+        let mut ds64 = Vec::new();
+        ds64.extend_from_slice(&0x1_0000_0100u64.to_le_bytes());
+        ds64.extend_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        ds64.extend_from_slice(&0x4000_0000u64.to_le_bytes());
+        ds64.extend_from_slice(&1u32.to_le_bytes());
+        ds64.extend_from_slice(b"LIST");
+        ds64.extend_from_slice(&0x1_0000_0002u64.to_le_bytes());
+        ds64.truncate(ds64_size as usize);
+        bytes.extend_from_slice(&ds64);
+        bytes.extend_from_slice(b"fmt \x10\0\0\0\x01\0\x02\0\x44\xAC\0\0\x10\xB1\x02\0\x04\0\x10\0");
+        bytes.extend_from_slice(b"data\xFF\xFF\xFF\xFF\0\0\0\0");
+        bytes
+    }
+
+    #[test]
+    fn reads_ds64_sizes_and_table() {
+        let bytes = rf64(40);
+        let ds64 = read_ds64(&bytes).unwrap();
+        assert_eq!(ds64.offset, 20);
+        assert_eq!((ds64.riff_size, ds64.data_size, ds64.sample_count), (0x1_0000_0100, 0x1_0000_0000, 0x4000_0000));
+        assert_eq!(ds64.table, [(fourcc(b"LIST"), 0x1_0000_0002)]);
+
+        let chunks = walk_chunks(&bytes);
+        let ids: Vec<String> = chunks.iter().map(|chunk| fourcc_str(chunk.id)).collect();
+        assert_eq!(ids, ["ds64", "fmt ", "data"]);
+        assert_eq!((chunks[2].offset, chunks[2].size), (84, 0x1_0000_0000));
+        assert!(chunks[2].is_truncated(bytes.len()));
+
+        let header = WAVHeader::parse(&bytes).unwrap();
+        assert_eq!((header.chunk_size, header.sub_chunk_2_size, header.sample_count), (0x1_0000_0100, 0x1_0000_0000, Some(0x4000_0000)));
+        assert_eq!(header.ds64_offset, Some(20));
+    }
+
+    #[test]
+    fn ds64_must_be_whole_and_in_an_rf64_file() {
+        assert!(read_ds64(&rf64(28)).is_some_and(|ds64| ds64.table.is_empty()));
+        assert!(read_ds64(&rf64(24)).is_none());
+        let mut riff = rf64(40);
+        riff[0..4].copy_from_slice(b"RIFF");
+        assert!(read_ds64(&riff).is_none());
+    }
+}
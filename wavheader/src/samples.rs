@@ -0,0 +1,334 @@
+use std::slice::ChunksExact;
+
+use crate::adpcm;
+use crate::aiff;
+use crate::error::WavError;
+use crate::format::AudioFormat;
+use crate::utils::WAVHeader;
+
+/// Interleaved samples of one type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Buffer<T> {
+    pub channels: usize,
+    pub data: Vec<T>,
+}
+
+impl<T: Copy> Buffer<T> {
+    pub fn new(channels: usize, data: Vec<T>) -> Self {
+        Buffer { channels, data }
+    }
+
+    /// Number of complete frames; a trailing partial frame is ignored.
+    pub fn frame_count(&self) -> usize {
+        self.data.len() / self.channels
+    }
+
+    /// Iterates over frames, each holding one sample per channel.
+    pub fn frames(&self) -> ChunksExact<'_, T> {
+        self.data.chunks_exact(self.channels)
+    }
+
+    /// Returns the samples of one channel.
+    pub fn channel(&self, channel: usize) -> Vec<T> {
+        self.frames().map(|frame| frame[channel]).collect()
+    }
+
+    /// Splits the buffer into one vector per channel.
+    pub fn deinterleave(&self) -> Vec<Vec<T>> {
+        (0..self.channels).map(|channel| self.channel(channel)).collect()
+    }
+
+    /// Builds a buffer from one vector per channel, truncated to the shortest.
+    pub fn interleave(channels: &[Vec<T>]) -> Self {
+        let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        let data = (0..frames).flat_map(|i| channels.iter().map(move |channel| channel[i])).collect();
+        Buffer { channels: channels.len(), data }
+    }
+
+    /// Keeps frames `start..end`, clamped to the buffer.
+    pub fn retain_frames(&mut self, start: usize, end: usize) {
+        let end = end.min(self.frame_count());
+        self.data.truncate(end * self.channels);
+        self.data.drain(..start.min(end) * self.channels);
+    }
+
+    fn map<U>(&self, f: impl Fn(T) -> U) -> Buffer<U> {
+        Buffer { channels: self.channels, data: self.data.iter().map(|&s| f(s)).collect() }
+    }
+}
+
+/// Decoded sample data. 8-bit PCM, A-law, µ-law and ADPCM expand to `I16`;
+/// 24-bit PCM is sign-extended into `I32`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Samples {
+    I16(Buffer<i16>),
+    I32(Buffer<i32>),
+    F32(Buffer<f32>),
+    F64(Buffer<f64>),
+}
+
+impl Samples {
+    pub fn channels(&self) -> usize {
+        match self {
+            Samples::I16(buffer) => buffer.channels,
+            Samples::I32(buffer) => buffer.channels,
+            Samples::F32(buffer) => buffer.channels,
+            Samples::F64(buffer) => buffer.channels,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        match self {
+            Samples::I16(buffer) => buffer.frame_count(),
+            Samples::I32(buffer) => buffer.frame_count(),
+            Samples::F32(buffer) => buffer.frame_count(),
+            Samples::F64(buffer) => buffer.frame_count(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Samples::I16(_) => "i16",
+            Samples::I32(_) => "i32",
+            Samples::F32(_) => "f32",
+            Samples::F64(_) => "f64",
+        }
+    }
+
+    /// Keeps frames `start..end`, clamped to the buffer.
+    pub fn retain_frames(&mut self, start: usize, end: usize) {
+        match self {
+            Samples::I16(buffer) => buffer.retain_frames(start, end),
+            Samples::I32(buffer) => buffer.retain_frames(start, end),
+            Samples::F32(buffer) => buffer.retain_frames(start, end),
+            Samples::F64(buffer) => buffer.retain_frames(start, end),
+        }
+    }
+
+    /// Converts to floating point in [-1.0, 1.0). `bits` is the PCM container
+    /// width, which tells 24-bit from 32-bit values held in `I32`.
+    pub fn to_f64(&self, bits: u16) -> Buffer<f64> {
+        match self {
+            Samples::I16(buffer) => buffer.map(|s| s as f64 / 32768.0),
+            Samples::I32(buffer) => {
+                let scale = (1u64 << (bits.clamp(17, 32) - 1)) as f64;
+                buffer.map(|s| s as f64 / scale)
+            }
+            Samples::F32(buffer) => buffer.map(|s| s as f64),
+            Samples::F64(buffer) => buffer.clone(),
+        }
+    }
+}
+
+/// Expands a G.711 A-law byte to 16-bit linear PCM.
+pub fn alaw_to_linear(byte: u8) -> i16 {
+    let a = byte ^ 0x55;
+    let exponent = (a >> 4) & 0x07;
+    let mantissa = (a & 0x0F) as i16;
+    let magnitude = match exponent {
+        0 => (mantissa << 4) + 8,
+        _ => ((mantissa << 4) + 0x108) << (exponent - 1),
+    };
+    if a & 0x80 != 0 { magnitude } else { -magnitude }
+}
+
+/// Expands a G.711 µ-law byte to 16-bit linear PCM.
+pub fn ulaw_to_linear(byte: u8) -> i16 {
+    let u = !byte;
+    let exponent = (u >> 4) & 0x07;
+    let mantissa = (u & 0x0F) as i16;
+    let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+    if u & 0x80 != 0 { -magnitude } else { magnitude }
+}
+
+/// Compresses 16-bit linear PCM to a G.711 A-law byte.
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    let sign = if sample >= 0 { 0x80 } else { 0x00 };
+    let magnitude = ((sample as i32).abs().min(32767) >> 3) as u32;
+    let (exponent, mantissa) = if magnitude < 32 {
+        (0, magnitude >> 1)
+    } else {
+        let exponent = 31 - magnitude.leading_zeros() - 4;
+        (exponent, (magnitude >> exponent) & 0x0F)
+    };
+    (sign | (exponent << 4) as u8 | mantissa as u8) ^ 0x55
+}
+
+/// Compresses 16-bit linear PCM to a G.711 µ-law byte.
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let sign = if sample < 0 { 0x80 } else { 0x00 };
+    let magnitude = (sample as i32).abs().min(32635) as u32 + 0x84;
+    let exponent = (31 - magnitude.leading_zeros() - 7).min(7);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+/// Returns true for block-compressed codecs, whose `block_align` bytes hold
+/// many frames and whose last block may be short.
+pub fn is_compressed(header: &WAVHeader) -> bool {
+    matches!(header.codec(), AudioFormat::ImaAdpcm | AudioFormat::MsAdpcm)
+}
+
+/// Frames held by each `block_align` bytes: one, or an ADPCM block.
+pub fn frames_per_block(header: &WAVHeader) -> Result<usize, WavError> {
+    if is_compressed(header) { adpcm::samples_per_block(header) } else { Ok(1) }
+}
+
+/// Number of frames `data_len` bytes of sample data decode to, capped by
+/// the `fact` chunk of a compressed file.
+pub fn frame_count(header: &WAVHeader, data_len: u64) -> Result<u64, WavError> {
+    if !is_compressed(header) {
+        return Ok(data_len / header.block_align.max(1) as u64);
+    }
+    let frames = adpcm::frame_count(header, data_len)?;
+    Ok(header.fact_samples.map_or(frames, |fact| frames.min(fact as u64)))
+}
+
+/// Decodes the `data` chunk of a file. A truncated chunk yields the complete
+/// samples that are present.
+pub fn decode(header: &WAVHeader, bytes: &[u8]) -> Result<Samples, WavError> {
+    let start = header.data_offset.min(bytes.len());
+    let end = start + header.data_len(bytes.len() as u64) as usize;
+    let mut samples = decode_data(header, &bytes[start..end])?;
+    if is_compressed(header)
+        && let Some(fact) = header.fact_samples
+    {
+        samples.retain_frames(0, fact as usize);
+    }
+    Ok(samples)
+}
+
+/// Decodes raw interleaved sample bytes described by `header`: whole frames,
+/// or whole blocks of a compressed codec, which decode to `I16`. AIFF
+/// samples are put in WAV order first.
+pub fn decode_data(header: &WAVHeader, data: &[u8]) -> Result<Samples, WavError> {
+    let channels = header.num_channels as usize;
+    if is_compressed(header) {
+        return Ok(Samples::I16(Buffer::new(channels, adpcm::decode(header, data)?)));
+    }
+    if header.block_align == 0 || !(header.block_align as usize).is_multiple_of(channels) {
+        return Err(WavError::InconsistentSizes);
+    }
+    let width = header.block_align as usize / channels;
+    let codec = header.codec();
+    let unsupported = Err(WavError::UnsupportedFormat(codec.code()));
+    let swapped;
+    let data = if header.is_big_endian() {
+        swapped = aiff::swap_sample_order(data, width, codec);
+        &swapped[..]
+    } else {
+        data
+    };
+
+    let samples = match (codec, width) {
+        (AudioFormat::Pcm, 1) => Samples::I16(Buffer::new(
+            channels,
+            data.iter().map(|&b| ((b as i16) - 128) << 8).collect(),
+        )),
+        (AudioFormat::Pcm, 2) => Samples::I16(Buffer::new(
+            channels,
+            data.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect(),
+        )),
+        (AudioFormat::Pcm, 3) => Samples::I32(Buffer::new(
+            channels,
+            data.chunks_exact(3).map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8).collect(),
+        )),
+        (AudioFormat::Pcm, 4) => Samples::I32(Buffer::new(
+            channels,
+            data.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap())).collect(),
+        )),
+        (AudioFormat::IeeeFloat, 4) => Samples::F32(Buffer::new(
+            channels,
+            data.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect(),
+        )),
+        (AudioFormat::IeeeFloat, 8) => Samples::F64(Buffer::new(
+            channels,
+            data.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect(),
+        )),
+        (AudioFormat::ALaw, 1) => Samples::I16(Buffer::new(channels, data.iter().map(|&b| alaw_to_linear(b)).collect())),
+        (AudioFormat::MuLaw, 1) => Samples::I16(Buffer::new(channels, data.iter().map(|&b| ulaw_to_linear(b)).collect())),
+        _ => return unsupported,
+    };
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mono file of one ADPCM block, with a `fmt ` chunk of 20 bytes whose
+    /// extension gives no samples per block, so they follow from the block.
+    fn adpcm_file(format: u16, block: &[u8]) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&format.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&8000u32.to_le_bytes());
+        fmt.extend_from_slice(&(8000 * block.len() as u32).to_le_bytes());
+        fmt.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        fmt.extend_from_slice(&4u16.to_le_bytes());
+        fmt.extend_from_slice(&2u16.to_le_bytes());
+        fmt.extend_from_slice(&0u16.to_le_bytes());
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        bytes.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&fmt);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(block);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        bytes
+    }
+
+    fn decode_file(bytes: &[u8]) -> Vec<i16> {
+        let header = WAVHeader::parse(bytes).unwrap();
+        match decode(&header, bytes).unwrap() {
+            Samples::I16(buffer) => buffer.data,
+            samples => panic!("ADPCM decoded to {}", samples.type_name()),
+        }
+    }
+
+    #[test]
+    fn decodes_ima_adpcm_block() {
+        // Predictor 0 at step index 0, then nibbles 7, 1, 8 (minus 0) and zeros,
+        // low nibble first: steps 7, 16, 14, 13, 12, 11, 10, 9.
+        let block = [0x00, 0x00, 0x00, 0x00, 0x17, 0x08, 0x00, 0x00];
+        assert_eq!(decode_file(&adpcm_file(0x0011, &block)), [0, 11, 17, 16, 17, 18, 19, 20, 21]);
+    }
+
+    #[test]
+    fn decodes_ms_adpcm_block() {
+        // Coefficient pair 1 (512, -256), delta 16, sample1 100, sample2 50,
+        // then nibbles 1, 7, -1 and 0, high nibble first; delta adapts to 38 and 34.
+        let block = [0x01, 0x10, 0x00, 0x64, 0x00, 0x32, 0x00, 0x17, 0xF0];
+        assert_eq!(decode_file(&adpcm_file(0x0002, &block)), [50, 100, 166, 344, 484, 624]);
+    }
+
+    #[test]
+    fn g711_matches_reference_table() {
+        // (linear, A-law, A-law decoded, µ-law, µ-law decoded), from ITU-T G.711.
+        let table: [(i16, u8, i16, u8, i16); 6] = [
+            (0, 0xD5, 8, 0xFF, 0),
+            (-8, 0x55, -8, 0x7E, -8),
+            (1000, 0xFA, 1008, 0xCE, 988),
+            (-1000, 0x7A, -1008, 0x4E, -988),
+            (32767, 0xAA, 32256, 0x80, 32124),
+            (-32768, 0x2A, -32256, 0x00, -32124),
+        ];
+        for (linear, alaw, alaw_linear, ulaw, ulaw_linear) in table {
+            assert_eq!(linear_to_alaw(linear), alaw, "A-law of {}", linear);
+            assert_eq!(alaw_to_linear(alaw), alaw_linear, "A-law byte 0x{:02X}", alaw);
+            assert_eq!(linear_to_ulaw(linear), ulaw, "µ-law of {}", linear);
+            assert_eq!(ulaw_to_linear(ulaw), ulaw_linear, "µ-law byte 0x{:02X}", ulaw);
+        }
+    }
+
+    #[test]
+    fn g711_round_trips_every_byte() {
+        for byte in 0..=255u8 {
+            assert_eq!(linear_to_alaw(alaw_to_linear(byte)), byte, "A-law byte 0x{:02X}", byte);
+            // 0x7F is µ-law's negative zero, which encodes back as positive zero.
+            let expected = if byte == 0x7F { 0xFF } else { byte };
+            assert_eq!(linear_to_ulaw(ulaw_to_linear(byte)), expected, "µ-law byte 0x{:02X}", byte);
+        }
+    }
+}
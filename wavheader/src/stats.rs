@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use crate::format::AudioFormat;
+use crate::samples::{self, Buffer};
+use crate::utils::WAVHeader;
+
+/// Oversampling factor for the true-peak estimate, as in ITU-R BS.1770.
+const OVERSAMPLING: usize = 4;
+/// Input samples on each side of an interpolated point.
+const HALF_TAPS: usize = 6;
+
+/// Level measurements for one channel, relative to full scale (1.0).
+pub struct ChannelStats {
+    pub peak: f64,
+    pub rms: f64,
+    pub dc_offset: f64,
+    /// Samples at or beyond the largest representable value.
+    pub clipped: usize,
+    /// Peak of the signal reconstructed between samples.
+    pub true_peak: f64,
+}
+
+/// Length of the audio in seconds, from the `data` bytes in a file of
+/// `file_len` bytes and the byte rate, or from the `fact` frame count of a
+/// compressed file.
+pub fn duration(header: &WAVHeader, file_len: u64) -> Option<f64> {
+    if samples::is_compressed(header)
+        && let Some(fact) = header.fact_samples
+        && header.sample_rate > 0
+    {
+        Some(fact as f64 / header.sample_rate as f64)
+    } else if header.byte_rate == 0 {
+        None
+    } else {
+        Some(header.data_len(file_len) as f64 / header.byte_rate as f64)
+    }
+}
+
+/// Level at which a sample counts as clipped: the largest positive value of
+/// the format, or 1.0 for float.
+pub fn clip_level(header: &WAVHeader) -> f64 {
+    match header.codec() {
+        AudioFormat::IeeeFloat => 1.0,
+        AudioFormat::ALaw => samples::alaw_to_linear(0xAA) as f64 / 32768.0,
+        AudioFormat::MuLaw => samples::ulaw_to_linear(0x80) as f64 / 32768.0,
+        AudioFormat::ImaAdpcm | AudioFormat::MsAdpcm => 1.0 - 1.0 / 32768.0,
+        _ => {
+            let bits = header.container_bits().clamp(8, 32);
+            1.0 - 1.0 / (1u64 << (bits - 1)) as f64
+        }
+    }
+}
+
+/// Converts a linear level to decibels relative to full scale.
+pub fn to_db(level: f64) -> f64 {
+    20.0 * level.log10()
+}
+
+/// Windowed-sinc coefficients for each fractional position between samples.
+fn interpolation_filters() -> Vec<Vec<f64>> {
+    (1..OVERSAMPLING)
+        .map(|phase| {
+            let frac = phase as f64 / OVERSAMPLING as f64;
+            (0..2 * HALF_TAPS)
+                .map(|k| {
+                    let x = k as f64 - (HALF_TAPS as f64 - 1.0) - frac;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.5 + 0.5 * (PI * x / HALF_TAPS as f64).cos();
+                    sinc * window
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Accumulates the measurements of one channel a sample at a time, so a
+/// file can be measured block by block.
+pub struct Meter {
+    clip_level: f64,
+    filters: Vec<Vec<f64>>,
+    peak: f64,
+    sum: f64,
+    sum_sq: f64,
+    count: u64,
+    clipped: usize,
+    true_peak: f64,
+    /// The samples around the next gap to interpolate, padded with zeros before the first.
+    window: VecDeque<f64>,
+    /// Index of the sample left of the next gap.
+    gap: u64,
+}
+
+impl Meter {
+    pub fn new(clip_level: f64) -> Self {
+        Meter {
+            clip_level,
+            filters: interpolation_filters(),
+            peak: 0.0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            count: 0,
+            clipped: 0,
+            true_peak: 0.0,
+            window: VecDeque::from(vec![0.0; HALF_TAPS - 1]),
+            gap: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f64) {
+        let level = sample.abs();
+        self.peak = self.peak.max(level);
+        self.true_peak = self.true_peak.max(level);
+        self.sum += sample;
+        self.sum_sq += sample * sample;
+        self.count += 1;
+        if level >= self.clip_level {
+            self.clipped += 1;
+        }
+        self.window.push_back(sample);
+        self.interpolate();
+    }
+
+    /// Once the window spans a gap, estimates the signal inside it.
+    fn interpolate(&mut self) {
+        if self.window.len() < 2 * HALF_TAPS {
+            return;
+        }
+        if self.gap + 1 < self.count {
+            for filter in &self.filters {
+                let value: f64 = filter.iter().zip(&self.window).map(|(c, s)| c * s).sum();
+                self.true_peak = self.true_peak.max(value.abs());
+            }
+        }
+        self.gap += 1;
+        self.window.pop_front();
+    }
+
+    pub fn finish(mut self) -> ChannelStats {
+        for _ in 0..HALF_TAPS {
+            self.window.push_back(0.0);
+            self.interpolate();
+        }
+        let count = self.count.max(1) as f64;
+        ChannelStats {
+            peak: self.peak,
+            rms: (self.sum_sq / count).sqrt(),
+            dc_offset: self.sum / count,
+            clipped: self.clipped,
+            true_peak: self.true_peak,
+        }
+    }
+}
+
+/// Measures one channel.
+pub fn channel_stats(samples: &[f64], clip_level: f64) -> ChannelStats {
+    let mut meter = Meter::new(clip_level);
+    samples.iter().for_each(|&sample| meter.push(sample));
+    meter.finish()
+}
+
+/// Measures every channel of interleaved blocks.
+pub struct Analyzer {
+    meters: Vec<Meter>,
+}
+
+impl Analyzer {
+    pub fn new(channels: usize, clip_level: f64) -> Self {
+        Analyzer { meters: (0..channels).map(|_| Meter::new(clip_level)).collect() }
+    }
+
+    pub fn push(&mut self, buffer: &Buffer<f64>) {
+        for frame in buffer.frames() {
+            for (meter, &sample) in self.meters.iter_mut().zip(frame) {
+                meter.push(sample);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<ChannelStats> {
+        self.meters.into_iter().map(Meter::finish).collect()
+    }
+}
+
+/// Measures every channel of a buffer.
+pub fn analyze(buffer: &Buffer<f64>, clip_level: f64) -> Vec<ChannelStats> {
+    let mut analyzer = Analyzer::new(buffer.channels, clip_level);
+    analyzer.push(buffer);
+    analyzer.finish()
+}
+
+/// Finds runs of at least `min_frames` frames in which every channel stays
+/// below `threshold`, a linear level.
+pub struct SilenceDetector {
+    threshold: f64,
+    min_frames: u64,
+    /// Index of the next frame pushed.
+    frame: u64,
+    /// Start of the quiet run in progress.
+    run_start: Option<u64>,
+    runs: Vec<Range<u64>>,
+}
+
+impl SilenceDetector {
+    pub fn new(threshold: f64, min_frames: u64) -> Self {
+        SilenceDetector { threshold, min_frames: min_frames.max(1), frame: 0, run_start: None, runs: Vec::new() }
+    }
+
+    pub fn push(&mut self, buffer: &Buffer<f64>) {
+        for frame in buffer.frames() {
+            let quiet = frame.iter().all(|sample| sample.abs() < self.threshold);
+            match (quiet, self.run_start) {
+                (true, None) => self.run_start = Some(self.frame),
+                (false, Some(start)) => {
+                    self.end_run(start);
+                    self.run_start = None;
+                }
+                _ => {}
+            }
+            self.frame += 1;
+        }
+    }
+
+    fn end_run(&mut self, start: u64) {
+        if self.frame - start >= self.min_frames {
+            self.runs.push(start..self.frame);
+        }
+    }
+
+    /// Returns the silent runs as frame ranges, in order.
+    pub fn finish(mut self) -> Vec<Range<u64>> {
+        if let Some(start) = self.run_start {
+            self.end_run(start);
+        }
+        self.runs
+    }
+}
+
+/// Frames of silence at the start and at the end of a file of `frames`
+/// frames, from the runs found by a `SilenceDetector`.
+pub fn edge_silence(runs: &[Range<u64>], frames: u64) -> (u64, u64) {
+    let leading = runs.first().filter(|run| run.start == 0).map_or(0, |run| run.end);
+    let trailing = runs.last().filter(|run| run.end == frames).map_or(0, |run| run.end - run.start);
+    (leading, trailing)
+}
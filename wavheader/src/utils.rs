@@ -1,13 +1,13 @@
-use std::fs;
-use std::io;
-
-pub fn read_file_bytes(filepath: &str) -> io::Result<Vec<u8>> {
-    fs::read(filepath)
-}
+use crate::aiff;
+use crate::error::WavError;
+use crate::format::{AudioFormat, Guid};
+use crate::riff;
+use crate::samples;
 
 pub struct WAVHeader {
     pub chunk_id: u32,
-    pub chunk_size: u32,
+    /// RIFF size; taken from the `ds64` chunk in an RF64 file.
+    pub chunk_size: u64,
     pub format: u32,
     pub sub_chunk_1_id: u32,
     pub sub_chunk_1_size: u32,
@@ -18,40 +18,212 @@ pub struct WAVHeader {
     pub block_align: u16,
     pub bits_per_sample: u16,
     pub sub_chunk_2_id: u32,
-    pub sub_chunk_2_size: u32,
+    /// `data` size; taken from the `ds64` chunk in an RF64 file.
+    pub sub_chunk_2_size: u64,
+    /// Offset of the `ds64` chunk payload of an RF64 file.
+    pub ds64_offset: Option<usize>,
+    /// Frame count stored in the `ds64` chunk, or in the `COMM` chunk of an AIFF file.
+    pub sample_count: Option<u64>,
+    /// Frames per channel from the `fact` chunk, which compressed formats
+    /// need to tell how many samples the last block holds.
+    pub fact_samples: Option<u32>,
+    /// Offset of the `fmt ` chunk payload from the start of the file.
+    pub fmt_offset: usize,
+    /// Offset of the `data` chunk payload from the start of the file.
+    pub data_offset: usize,
+    /// `cbSize` of an 18- or 40-byte `fmt ` chunk, 0 for a 16-byte one.
+    pub extension_size: u16,
+    /// Significant bits per sample; equal to `bits_per_sample` unless extensible.
+    pub valid_bits_per_sample: u16,
+    /// Speaker positions of an extensible format, 0 otherwise.
+    pub channel_mask: u32,
+    /// Sub-format GUID of an extensible format.
+    pub sub_format: Option<Guid>,
+    /// Codec-specific bytes after `cbSize`, such as ADPCM coefficients.
+    pub extension: Vec<u8>,
+    /// Compression type of an AIFF-C file, such as `NONE` or `sowt`.
+    pub compression: Option<u32>,
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
 }
 
 impl WAVHeader {
-    pub fn new(
-        chunk_id: u32,
-        chunk_size: u32,
-        format: u32,
-        sub_chunk_1_id: u32,
-        sub_chunk_1_size: u32,
-        audio_format: u16,
-        num_channels: u16,
-        sample_rate: u32,
-        byte_rate: u32,
-        block_align: u16,
-        bits_per_sample: u16,
-        sub_chunk_2_id: u32,
-        sub_chunk_2_size: u32,
-    ) -> Self {
-        WAVHeader {
+    /// Parses the RIFF header and the `fmt ` and `data` chunks, wherever they
+    /// appear, or the `FORM` header and the `COMM` and `SSND` chunks of an AIFF file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, WavError> {
+        if aiff::is_aiff(bytes) {
+            let chunks = aiff::walk_chunks(bytes);
+            let comm_bytes = riff::find_chunk(&chunks, b"COMM").map_or(&[][..], |comm| comm.data(bytes));
+            let ssnd_bytes = riff::find_chunk(&chunks, b"SSND").map_or(&[][..], |ssnd| ssnd.data(bytes));
+            return aiff::header(bytes, &chunks, comm_bytes, ssnd_bytes, bytes.len() as u64);
+        }
+        let chunks = riff::walk_chunks(bytes);
+        let fmt_bytes = riff::find_chunk(&chunks, b"fmt ").map_or(&[][..], |fmt| fmt.data(bytes));
+        let fact_bytes = riff::find_chunk(&chunks, b"fact").map_or(&[][..], |fact| fact.data(bytes));
+        Self::from_chunks(bytes, &chunks, fmt_bytes, fact_bytes, bytes.len() as u64)
+    }
+
+    /// Builds the header from the start of the file, which must hold the RIFF
+    /// header and any `ds64` chunk, the chunk list, and the `fmt ` and `fact`
+    /// payloads; `fact_bytes` is empty when there is no `fact` chunk.
+    pub fn from_chunks(prefix: &[u8], chunks: &[riff::Chunk], fmt_bytes: &[u8], fact_bytes: &[u8], file_len: u64) -> Result<Self, WavError> {
+        if prefix.len() < 12 {
+            return Err(WavError::Truncated { expected: 12, got: prefix.len() });
+        }
+        let chunk_id = be_u32(prefix, 0);
+        if chunk_id != riff::fourcc(b"RIFF") && !riff::is_rf64(chunk_id) {
+            return Err(WavError::NotRiff);
+        }
+        if be_u32(prefix, 8) != riff::fourcc(b"WAVE") {
+            return Err(WavError::NotWave);
+        }
+
+        let ds64 = riff::read_ds64(prefix);
+        if riff::is_rf64(chunk_id) && ds64.is_none() {
+            return Err(WavError::MissingChunk(riff::fourcc(b"ds64")));
+        }
+        let fmt = riff::find_chunk(chunks, b"fmt ").ok_or(WavError::MissingChunk(riff::fourcc(b"fmt ")))?;
+        let data = riff::find_chunk(chunks, b"data").ok_or(WavError::MissingChunk(riff::fourcc(b"data")))?;
+
+        if fmt.size < 16 {
+            return Err(WavError::InconsistentSizes);
+        }
+        if fmt_bytes.len() < 16 {
+            return Err(WavError::Truncated { expected: fmt.data_offset() + 16, got: file_len as usize });
+        }
+
+        let mut header = WAVHeader {
             chunk_id,
-            chunk_size,
-            format,
-            sub_chunk_1_id,
-            sub_chunk_1_size,
-            audio_format,
-            num_channels,
-            sample_rate,
-            byte_rate,
-            block_align,
-            bits_per_sample,
-            sub_chunk_2_id,
-            sub_chunk_2_size,
-        }
-    }
-    
+            chunk_size: ds64.as_ref().map_or(le_u32(prefix, 4) as u64, |ds64| ds64.riff_size),
+            format: be_u32(prefix, 8),
+            sub_chunk_1_id: fmt.id,
+            sub_chunk_1_size: fmt.size as u32,
+            audio_format: le_u16(fmt_bytes, 0),
+            num_channels: le_u16(fmt_bytes, 2),
+            sample_rate: le_u32(fmt_bytes, 4),
+            byte_rate: le_u32(fmt_bytes, 8),
+            block_align: le_u16(fmt_bytes, 12),
+            bits_per_sample: le_u16(fmt_bytes, 14),
+            sub_chunk_2_id: data.id,
+            sub_chunk_2_size: data.size,
+            ds64_offset: ds64.as_ref().map(|ds64| ds64.offset),
+            sample_count: ds64.as_ref().map(|ds64| ds64.sample_count),
+            fact_samples: (fact_bytes.len() >= 4).then(|| le_u32(fact_bytes, 0)),
+            fmt_offset: fmt.data_offset(),
+            data_offset: data.data_offset(),
+            extension_size: 0,
+            valid_bits_per_sample: le_u16(fmt_bytes, 14),
+            channel_mask: 0,
+            sub_format: None,
+            extension: Vec::new(),
+            compression: None,
+        };
+
+        if fmt_bytes.len() >= 18 {
+            header.extension_size = le_u16(fmt_bytes, 16);
+            let end = 18 + header.extension_size as usize;
+            if end > fmt_bytes.len() {
+                return Err(WavError::InconsistentSizes);
+            }
+            header.extension = fmt_bytes[18..end].to_vec();
+        }
+
+        if header.format_tag() == AudioFormat::Extensible {
+            if header.extension.len() < 22 {
+                return Err(WavError::InconsistentSizes);
+            }
+            let ext = &header.extension;
+            header.valid_bits_per_sample = le_u16(ext, 0);
+            header.channel_mask = le_u32(ext, 2);
+            header.sub_format = Some(Guid(ext[6..22].try_into().unwrap()));
+        }
+
+        match header.codec() {
+            AudioFormat::Pcm
+            | AudioFormat::MsAdpcm
+            | AudioFormat::IeeeFloat
+            | AudioFormat::ALaw
+            | AudioFormat::MuLaw
+            | AudioFormat::ImaAdpcm => {}
+            codec => return Err(WavError::UnsupportedFormat(codec.code())),
+        }
+        if header.num_channels == 0 {
+            return Err(WavError::InconsistentSizes);
+        }
+        Ok(header)
+    }
+
+    /// Returns true for an AIFF or AIFF-C file, whose header fields are big-endian.
+    pub fn is_aiff(&self) -> bool {
+        self.chunk_id == riff::fourcc(b"FORM")
+    }
+
+    /// Returns true if samples are stored big-endian, with 8-bit PCM signed,
+    /// as in AIFF unless the AIFF-C compression type says otherwise.
+    pub fn is_big_endian(&self) -> bool {
+        self.is_aiff() && !matches!(self.compression.map(u32::to_be_bytes).as_ref(), Some(b"sowt" | b"raw "))
+    }
+
+    /// Bytes of sample data in a file of `file_len` bytes: the `data` size,
+    /// cut short where the file ends, as with a streaming placeholder size.
+    pub fn data_len(&self, file_len: u64) -> u64 {
+        file_len.saturating_sub(self.data_offset as u64).min(self.sub_chunk_2_size)
+    }
+
+    /// Bits per sample of one channel as stored, which can exceed `bits_per_sample`.
+    /// A compressed format, whose blocks hold many frames, gives `bits_per_sample`.
+    pub fn container_bits(&self) -> u16 {
+        if samples::is_compressed(self) {
+            return self.bits_per_sample;
+        }
+        (self.block_align as u32 / self.num_channels.max(1) as u32 * 8).min(u16::MAX as u32) as u16
+    }
+
+    /// The format code as written in the `fmt ` chunk.
+    pub fn format_tag(&self) -> AudioFormat {
+        AudioFormat::from_code(self.audio_format)
+    }
+
+    /// The codec of the sample data, looking through an extensible sub-format.
+    /// An extensible format with a non-standard GUID stays `Extensible`.
+    pub fn codec(&self) -> AudioFormat {
+        match self.sub_format.and_then(|guid| guid.format_code()) {
+            Some(code) => AudioFormat::from_code(code),
+            None => self.format_tag(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A RIFF/WAVE file with an empty `data` chunk and then a `fmt ` chunk
+    /// declared `fmt_size` bytes long, holding `fmt`.
+    fn wave(fmt_size: u32, fmt: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVEdata\0\0\0\0fmt ".to_vec();
+        bytes.extend_from_slice(&fmt_size.to_le_bytes());
+        bytes.extend_from_slice(fmt);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn short_fmt_chunk_is_inconsistent_and_short_file_truncated() {
+        let fmt = [1, 0, 1, 0, 0x40, 0x1F, 0, 0, 0x80, 0x3E, 0, 0, 2, 0, 16, 0];
+        assert!(WAVHeader::parse(&wave(16, &fmt)).is_ok());
+        assert!(matches!(WAVHeader::parse(&wave(14, &fmt[..14])), Err(WavError::InconsistentSizes)));
+        assert!(matches!(WAVHeader::parse(&wave(16, &fmt[..14])), Err(WavError::Truncated { .. })));
+    }
 }
@@ -0,0 +1,259 @@
+use std::fs::OpenOptions;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::error::WavError;
+use crate::format::AudioFormat;
+use crate::riff::{self, Chunk};
+use crate::utils::WAVHeader;
+
+/// A header field that disagrees with the file or with the other fields.
+pub struct Issue {
+    pub field: &'static str,
+    pub found: u64,
+    pub expected: u64,
+    pub reason: &'static str,
+    /// Location and width of the field when it can be rewritten with `expected`.
+    pub patch: Option<(usize, usize)>,
+}
+
+/// Returns the size the `data` chunk should have. A size of 0 or 0xFFFFFFFF
+/// left by an interrupted recording, or one that runs past the end of the
+/// file, means the samples run to the end of the file.
+fn expected_data_size(header: &WAVHeader, chunks: &[Chunk], file_len: u64) -> u64 {
+    let available = file_len.saturating_sub(header.data_offset as u64);
+    let size = header.sub_chunk_2_size;
+    let placeholder = header.ds64_offset.is_none() && size == u32::MAX as u64;
+    let interrupted = size == 0 || placeholder || size > available;
+    let next_is_chunk = chunks
+        .iter()
+        .find(|chunk| chunk.offset as u64 >= (header.data_offset as u64).saturating_add(size))
+        .is_some_and(|chunk| riff::is_valid_fourcc(chunk.id));
+    if interrupted && !(size == 0 && next_is_chunk) {
+        let block_align = header.block_align.max(1) as u64;
+        available - available % block_align
+    } else {
+        size
+    }
+}
+
+/// Checks every field of `header` against the chunks and length of the file
+/// and the format maths.
+pub fn validate(header: &WAVHeader, chunks: &[Chunk], file_len: u64) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut check = |field, found: u64, expected: u64, reason, patch| {
+        if found != expected {
+            issues.push(Issue { field, found, expected, reason, patch });
+        }
+    };
+    let fmt = header.fmt_offset;
+    // AIFF derives BlockAlign and ByteRate rather than storing them, so only
+    // a `fmt ` chunk has fields to patch.
+    let riff_fmt = (!header.is_aiff()).then_some(fmt);
+    let codec = header.codec();
+    let is_linear = matches!(codec, AudioFormat::Pcm | AudioFormat::IeeeFloat | AudioFormat::ALaw | AudioFormat::MuLaw);
+
+    let data_size = expected_data_size(header, chunks, file_len);
+    let data_end = header.data_offset as u64 + data_size + (data_size & 1);
+    let riff_size = file_len.max(data_end) - 8;
+    match header.ds64_offset {
+        None if header.is_aiff() => {
+            check("ChunkSize", header.chunk_size, riff_size.min(u32::MAX as u64), "does not match the file length", Some((4, 4)));
+            check("SubChunk2Size", header.sub_chunk_2_size, data_size, "does not match the sample data present", None);
+            if let Some(sample_count) = header.sample_count
+                && header.block_align > 0
+            {
+                let expected = data_size / header.block_align as u64;
+                check("NumSampleFrames", sample_count, expected, "is not SubChunk2Size / BlockAlign", Some((fmt + 2, 4)));
+            }
+        }
+        Some(ds64) => {
+            check("ChunkSize", header.chunk_size, riff_size, "does not match the file length", Some((ds64, 8)));
+            check("SubChunk2Size", header.sub_chunk_2_size, data_size, "does not match the sample data present", Some((ds64 + 8, 8)));
+            if let Some(sample_count) = header.sample_count
+                && is_linear
+                && header.block_align > 0
+            {
+                let expected = data_size / header.block_align as u64;
+                check("SampleCount", sample_count, expected, "is not SubChunk2Size / BlockAlign", Some((ds64 + 16, 8)));
+            }
+        }
+        None => {
+            check("ChunkSize", header.chunk_size, riff_size.min(u32::MAX as u64), "does not match the file length", Some((4, 4)));
+            check("SubChunk2Size", header.sub_chunk_2_size, data_size, "does not match the sample data present", Some((header.data_offset - 4, 4)));
+        }
+    }
+
+    let block_align = if is_linear {
+        let expected = header.num_channels as u64 * (header.bits_per_sample as u64).div_ceil(8);
+        check("BlockAlign", header.block_align as u64, expected, "is not NumChannels * BitsPerSample / 8", riff_fmt.map(|fmt| (fmt + 12, 2)));
+        expected
+    } else {
+        header.block_align as u64
+    };
+    if is_linear {
+        let expected = header.sample_rate as u64 * block_align;
+        check("ByteRate", header.byte_rate as u64, expected, "is not SampleRate * BlockAlign", riff_fmt.map(|fmt| (fmt + 8, 4)));
+        if block_align > 0 {
+            check("SubChunk2Size", data_size, data_size - data_size % block_align, "is not a whole number of frames", None);
+        }
+    }
+
+    let bits = header.bits_per_sample as u64;
+    match codec {
+        AudioFormat::IeeeFloat => check("BitsPerSample", bits, if bits == 64 { 64 } else { 32 }, "is not 32 or 64 for float", None),
+        AudioFormat::ALaw | AudioFormat::MuLaw => check("BitsPerSample", bits, 8, "is not 8 for G.711", None),
+        AudioFormat::Pcm => check("BitsPerSample", bits, bits.clamp(1, 32), "is not between 1 and 32", None),
+        _ => {}
+    }
+    if header.sub_format.is_some() {
+        let valid_bits = header.valid_bits_per_sample as u64;
+        check("ValidBitsPerSample", valid_bits, valid_bits.min(bits), "exceeds BitsPerSample", None);
+        let channels = header.channel_mask.count_ones() as u64;
+        if channels > 0 {
+            check("ChannelMask", channels, header.num_channels as u64, "names a different number of speakers than NumChannels", None);
+        }
+    }
+    issues
+}
+
+/// Rewrites every repairable field of `issues` in `file`, big-endian in an
+/// AIFF file.
+pub fn patch<W: Write + Seek>(file: &mut W, header: &WAVHeader, issues: &[Issue]) -> io::Result<()> {
+    for issue in issues {
+        if let Some((offset, width)) = issue.patch {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            if header.is_aiff() {
+                file.write_all(&issue.expected.to_be_bytes()[8 - width..])?;
+            } else {
+                file.write_all(&issue.expected.to_le_bytes()[..width])?;
+            }
+        }
+    }
+    file.flush()
+}
+
+/// Validates the file at `path` and rewrites every repairable field in
+/// place. Returns the issues found, repaired or not.
+pub fn repair(path: &str, header: &WAVHeader, chunks: &[Chunk], file_len: u64) -> Result<Vec<Issue>, WavError> {
+    let issues = validate(header, chunks, file_len);
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    patch(&mut file, header, &issues)?;
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::format::Container;
+    use crate::reader::WavReader;
+    use crate::writer::{WavSpec, WavWriter};
+
+    /// A stereo 16-bit file of `frames` frames, followed by `chunks`.
+    fn file(container: Container, frames: usize, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let spec = WavSpec { format: AudioFormat::Pcm, channels: 2, sample_rate: 8000, bits_per_sample: 16, container };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_bytes(&vec![1; frames * 4]).unwrap();
+        for (id, data) in chunks {
+            writer.add_chunk(riff::fourcc(id), data.to_vec());
+        }
+        writer.finalize().unwrap().into_inner()
+    }
+
+    fn check(bytes: &[u8]) -> (WAVHeader, Vec<Issue>) {
+        let (header, chunks) = WavReader::new(Cursor::new(bytes)).unwrap().into_parts();
+        let issues = validate(&header, &chunks, bytes.len() as u64);
+        (header, issues)
+    }
+
+    fn fields(issues: &[Issue]) -> Vec<(&'static str, u64, u64)> {
+        issues.iter().map(|issue| (issue.field, issue.found, issue.expected)).collect()
+    }
+
+    /// Applies the patches of every issue, checks that the repaired file is
+    /// valid and returns it.
+    fn repaired(bytes: &[u8]) -> Vec<u8> {
+        let (header, issues) = check(bytes);
+        let mut file = Cursor::new(bytes.to_vec());
+        patch(&mut file, &header, &issues).unwrap();
+        let bytes = file.into_inner();
+        assert!(fields(&check(&bytes).1).is_empty(), "{:?}", fields(&check(&bytes).1));
+        bytes
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn written_files_are_valid() {
+        assert!(check(&file(Container::Wave, 10, &[(b"LIST", b"INFO")])).1.is_empty());
+        assert!(check(&file(Container::Aiff, 10, &[])).1.is_empty());
+    }
+
+    #[test]
+    fn repairs_placeholder_sizes() {
+        // sizes left by an interrupted recording, with the samples running to the end
+        let good = file(Container::Wave, 10, &[]);
+        let data = check(&good).0.data_offset;
+        for placeholder in [0, u32::MAX] {
+            let mut bytes = good.clone();
+            set_u32(&mut bytes, 4, placeholder);
+            set_u32(&mut bytes, data - 4, placeholder);
+            let riff_size = good.len() as u64 - 8;
+            assert_eq!(fields(&check(&bytes).1), [("ChunkSize", placeholder as u64, riff_size), ("SubChunk2Size", placeholder as u64, 40)]);
+            assert_eq!(repaired(&bytes), good);
+        }
+    }
+
+    #[test]
+    fn placeholder_size_of_a_recording_cut_short_runs_to_the_end() {
+        // a size of 0 with the last frame half written
+        let mut bytes = file(Container::Wave, 10, &[]);
+        let data = check(&bytes).0.data_offset;
+        bytes.truncate(data + 38);
+        set_u32(&mut bytes, data - 4, 0);
+        let (_, issues) = check(&bytes);
+        assert_eq!(fields(&issues)[1], ("SubChunk2Size", 0, 36));
+        repaired(&bytes);
+    }
+
+    #[test]
+    fn size_of_0_before_a_chunk_is_an_empty_data_chunk() {
+        assert!(check(&file(Container::Wave, 0, &[(b"LIST", b"INFO")])).1.is_empty());
+    }
+
+    #[test]
+    fn repairs_block_align_and_byte_rate() {
+        let good = file(Container::Wave, 10, &[(b"LIST", b"INFO")]);
+        let fmt = check(&good).0.fmt_offset;
+        let mut bytes = good.clone();
+        set_u32(&mut bytes, fmt + 8, 1234);
+        bytes[fmt + 12] = 3;
+        assert_eq!(fields(&check(&bytes).1), [("BlockAlign", 3, 4), ("ByteRate", 1234, 32000)]);
+        assert_eq!(repaired(&bytes), good);
+    }
+
+    #[test]
+    fn reports_without_patches_what_cannot_be_rewritten() {
+        let mut bytes = file(Container::Wave, 10, &[]);
+        let fmt = check(&bytes).0.fmt_offset;
+        bytes[fmt + 14] = 40;
+        let (_, issues) = check(&bytes);
+        let bits = issues.iter().find(|issue| issue.field == "BitsPerSample").unwrap();
+        assert_eq!((bits.found, bits.expected, bits.patch), (40, 32, None));
+    }
+
+    #[test]
+    fn repairs_aiff_sizes_big_endian() {
+        let good = file(Container::Aiff, 10, &[]);
+        let mut bytes = good.clone();
+        bytes[4..8].copy_from_slice(&1u32.to_be_bytes());
+        let (header, issues) = check(&bytes);
+        assert_eq!(fields(&issues), [("ChunkSize", 1, good.len() as u64 - 8)]);
+        assert_eq!(issues[0].patch, Some((4, 4)));
+        assert!(header.is_aiff());
+        assert_eq!(repaired(&bytes), good);
+    }
+}
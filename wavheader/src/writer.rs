@@ -0,0 +1,474 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use crate::aiff;
+use crate::error::WavError;
+use crate::format::{AudioFormat, Container};
+use crate::riff::DS64_SIZE;
+use crate::samples::{self, Samples};
+use crate::utils::WAVHeader;
+
+/// Format parameters of a file to write. `block_align` and `byte_rate`
+/// follow from them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavSpec {
+    /// `Pcm`, `IeeeFloat`, `ALaw` or `MuLaw`.
+    pub format: AudioFormat,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub container: Container,
+}
+
+impl WavSpec {
+    /// Takes the format of an existing file, resolving an extensible sub-format.
+    pub fn from_header(header: &WAVHeader) -> Self {
+        WavSpec {
+            format: header.codec(),
+            channels: header.num_channels,
+            sample_rate: header.sample_rate,
+            bits_per_sample: header.bits_per_sample,
+            container: if header.is_aiff() { Container::Aiff } else { Container::Wave },
+        }
+    }
+
+    /// Bytes per sample of one channel.
+    pub fn sample_width(&self) -> usize {
+        (self.bits_per_sample as usize).div_ceil(8)
+    }
+
+    pub fn block_align(&self) -> u16 {
+        (self.sample_width() * self.channels as usize) as u16
+    }
+
+    pub fn byte_rate(&self) -> u32 {
+        self.sample_rate * self.block_align() as u32
+    }
+
+    /// Files with more than two channels carry a channel mask, which needs
+    /// `WAVE_FORMAT_EXTENSIBLE`.
+    fn is_extensible(&self) -> bool {
+        self.channels > 2
+    }
+
+    fn validate(&self) -> Result<(), WavError> {
+        let width = self.sample_width();
+        let supported = match self.format {
+            AudioFormat::Pcm => (1..=4).contains(&width),
+            AudioFormat::IeeeFloat => width == 4 || width == 8,
+            AudioFormat::ALaw | AudioFormat::MuLaw => width == 1,
+            _ => false,
+        };
+        if !supported {
+            return Err(WavError::UnsupportedFormat(self.format.code()));
+        }
+        if self.channels == 0 || self.sample_rate == 0 {
+            return Err(WavError::InconsistentSizes);
+        }
+        Ok(())
+    }
+
+    /// The `fmt ` chunk payload: 16 bytes for PCM, 18 for other codecs and
+    /// 40 for extensible.
+    fn fmt_chunk(&self) -> Vec<u8> {
+        let format_tag = if self.is_extensible() { AudioFormat::Extensible } else { self.format };
+        let mut fmt = Vec::with_capacity(40);
+        fmt.extend_from_slice(&format_tag.code().to_le_bytes());
+        fmt.extend_from_slice(&self.channels.to_le_bytes());
+        fmt.extend_from_slice(&self.sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&self.byte_rate().to_le_bytes());
+        fmt.extend_from_slice(&self.block_align().to_le_bytes());
+        fmt.extend_from_slice(&((self.sample_width() * 8) as u16).to_le_bytes());
+        if self.is_extensible() {
+            let channel_mask = if self.channels < 18 { (1u32 << self.channels) - 1 } else { 0 };
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+            fmt.extend_from_slice(&channel_mask.to_le_bytes());
+            fmt.extend_from_slice(&self.format.code().to_le_bytes());
+            fmt.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+        } else if self.format != AudioFormat::Pcm {
+            fmt.extend_from_slice(&0u16.to_le_bytes());
+        }
+        fmt
+    }
+
+    /// The `COMM` chunk payload with a zero frame count, and the form type:
+    /// `AIFF` for PCM, `AIFC` with a compression type for other codecs.
+    fn comm_chunk(&self) -> (&'static [u8; 4], Vec<u8>) {
+        let compression = aiff::compression_type(self.format, self.bits_per_sample);
+        // G.711 files give the size of the decoded samples.
+        let sample_size = if self.sample_width() == 1 && self.format != AudioFormat::Pcm { 16 } else { self.bits_per_sample };
+        let mut comm = Vec::with_capacity(40);
+        comm.extend_from_slice(&self.channels.to_be_bytes());
+        comm.extend_from_slice(&0u32.to_be_bytes());
+        comm.extend_from_slice(&sample_size.to_be_bytes());
+        comm.extend_from_slice(&aiff::write_extended(self.sample_rate));
+        let Some((id, name)) = compression else {
+            return (b"AIFF", comm);
+        };
+        comm.extend_from_slice(id);
+        comm.push(name.len() as u8);
+        comm.extend_from_slice(name.as_bytes());
+        if name.len() % 2 == 0 {
+            comm.push(0);
+        }
+        (b"AIFC", comm)
+    }
+
+    /// Encodes samples whose type matches this format: `I16` for 8- and
+    /// 16-bit PCM, A-law and µ-law, `I32` for 24- and 32-bit PCM, `F32` and
+    /// `F64` for float. Bytes come in the order of the container.
+    pub fn encode(&self, samples: &Samples) -> Result<Vec<u8>, WavError> {
+        let mismatch = Err(WavError::UnsupportedFormat(self.format.code()));
+        let bytes = match (self.format, self.sample_width(), samples) {
+            (AudioFormat::Pcm, 1, Samples::I16(buffer)) => {
+                buffer.data.iter().map(|&s| ((s >> 8) + 128) as u8).collect()
+            }
+            (AudioFormat::Pcm, 2, Samples::I16(buffer)) => {
+                buffer.data.iter().flat_map(|s| s.to_le_bytes()).collect()
+            }
+            (AudioFormat::Pcm, 3, Samples::I32(buffer)) => {
+                buffer.data.iter().flat_map(|s| {
+                    let b = s.to_le_bytes();
+                    [b[0], b[1], b[2]]
+                }).collect()
+            }
+            (AudioFormat::Pcm, 4, Samples::I32(buffer)) => {
+                buffer.data.iter().flat_map(|s| s.to_le_bytes()).collect()
+            }
+            (AudioFormat::IeeeFloat, 4, Samples::F32(buffer)) => {
+                buffer.data.iter().flat_map(|s| s.to_le_bytes()).collect()
+            }
+            (AudioFormat::IeeeFloat, 8, Samples::F64(buffer)) => {
+                buffer.data.iter().flat_map(|s| s.to_le_bytes()).collect()
+            }
+            (AudioFormat::ALaw, 1, Samples::I16(buffer)) => {
+                buffer.data.iter().map(|&s| samples::linear_to_alaw(s)).collect()
+            }
+            (AudioFormat::MuLaw, 1, Samples::I16(buffer)) => {
+                buffer.data.iter().map(|&s| samples::linear_to_ulaw(s)).collect()
+            }
+            _ => return mismatch,
+        };
+        match self.container {
+            Container::Wave => Ok(bytes),
+            Container::Aiff => Ok(aiff::swap_sample_order(&bytes, self.sample_width(), self.format)),
+        }
+    }
+
+    /// Quantizes floating-point samples in [-1.0, 1.0] to this format,
+    /// clipping values outside that range.
+    pub fn quantize(&self, data: &[f64]) -> Samples {
+        let channels = self.channels as usize;
+        let scale = |bits: u16| (1u64 << (bits - 1)) as f64;
+        let to_int = |s: f64, bits: u16| {
+            let max = scale(bits) - 1.0;
+            (s * scale(bits)).round().clamp(-max - 1.0, max) as i64
+        };
+        match (self.format, self.sample_width()) {
+            (AudioFormat::IeeeFloat, 4) => {
+                Samples::F32(samples::Buffer::new(channels, data.iter().map(|&s| s as f32).collect()))
+            }
+            (AudioFormat::IeeeFloat, _) => Samples::F64(samples::Buffer::new(channels, data.to_vec())),
+            (AudioFormat::Pcm, width @ (3 | 4)) => Samples::I32(samples::Buffer::new(
+                channels,
+                data.iter().map(|&s| to_int(s, width as u16 * 8) as i32).collect(),
+            )),
+            (AudioFormat::Pcm, 1) => Samples::I16(samples::Buffer::new(
+                channels,
+                data.iter().map(|&s| (to_int(s, 8) << 8) as i16).collect(),
+            )),
+            _ => Samples::I16(samples::Buffer::new(channels, data.iter().map(|&s| to_int(s, 16) as i16).collect())),
+        }
+    }
+}
+
+/// Writes a RIFF/WAVE or AIFF file. The header is written up front with
+/// empty sizes, samples are streamed after it, and `finalize` patches the
+/// sizes in place. In a WAV file a `JUNK` chunk after the RIFF header
+/// reserves room for a `ds64` chunk, so a file that grows past 4 GiB is
+/// promoted to RF64 on finalize; an AIFF file cannot grow past 4 GiB.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    spec: WavSpec,
+    /// Offset of the `data` or `SSND` chunk size field.
+    data_size_offset: u64,
+    /// Offset of the frame count field of an AIFF `COMM` chunk.
+    frames_offset: u64,
+    data_size: u64,
+    /// Chunks written after the `data` chunk on finalize, as (id, payload).
+    trailing: Vec<(u32, Vec<u8>)>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &str, spec: WavSpec) -> Result<Self, WavError> {
+        WavWriter::new(BufWriter::new(File::create(path)?), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, spec: WavSpec) -> Result<Self, WavError> {
+        spec.validate()?;
+        if spec.container == Container::Aiff {
+            return Self::new_aiff(inner, spec);
+        }
+        let fmt = spec.fmt_chunk();
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(b"WAVE")?;
+        inner.write_all(b"JUNK")?;
+        inner.write_all(&(DS64_SIZE as u32).to_le_bytes())?;
+        inner.write_all(&[0; DS64_SIZE])?;
+        inner.write_all(b"fmt ")?;
+        inner.write_all(&(fmt.len() as u32).to_le_bytes())?;
+        inner.write_all(&fmt)?;
+        inner.write_all(b"data")?;
+        let data_size_offset = inner.stream_position()?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { inner, spec, data_size_offset, frames_offset: 0, data_size: 0, trailing: Vec::new() })
+    }
+
+    /// Writes the `FORM` header, an `FVER` chunk for AIFF-C, the `COMM`
+    /// chunk, and the `SSND` chunk header with zero offset and block size.
+    fn new_aiff(mut inner: W, spec: WavSpec) -> Result<Self, WavError> {
+        let (form_type, comm) = spec.comm_chunk();
+        inner.write_all(b"FORM")?;
+        inner.write_all(&0u32.to_be_bytes())?;
+        inner.write_all(form_type)?;
+        if form_type == b"AIFC" {
+            inner.write_all(b"FVER")?;
+            inner.write_all(&4u32.to_be_bytes())?;
+            inner.write_all(&aiff::AIFC_VERSION.to_be_bytes())?;
+        }
+        inner.write_all(b"COMM")?;
+        inner.write_all(&(comm.len() as u32).to_be_bytes())?;
+        let frames_offset = inner.stream_position()? + 2;
+        inner.write_all(&comm)?;
+        inner.write_all(b"SSND")?;
+        let data_size_offset = inner.stream_position()?;
+        inner.write_all(&[0; 12])?;
+        Ok(WavWriter { inner, spec, data_size_offset, frames_offset, data_size: 0, trailing: Vec::new() })
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// Adds a chunk, such as `LIST` or `bext`, to be written after the `data`
+    /// chunk when the file is finalized.
+    pub fn add_chunk(&mut self, id: u32, data: Vec<u8>) {
+        self.trailing.push((id, data));
+    }
+
+    /// Appends raw interleaved sample bytes, which must hold whole frames.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), WavError> {
+        if !bytes.len().is_multiple_of(self.spec.block_align() as usize) {
+            return Err(WavError::InconsistentSizes);
+        }
+        self.inner.write_all(bytes)?;
+        self.data_size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Appends samples of the type that matches the format; see `WavSpec::encode`.
+    pub fn write_samples(&mut self, samples: &Samples) -> Result<(), WavError> {
+        if samples.channels() != self.spec.channels as usize {
+            return Err(WavError::InconsistentSizes);
+        }
+        let bytes = self.spec.encode(samples)?;
+        self.write_bytes(&bytes)
+    }
+
+    /// Appends interleaved floating-point samples, quantized to the format.
+    pub fn write_f64(&mut self, data: &[f64]) -> Result<(), WavError> {
+        let samples = self.spec.quantize(data);
+        self.write_samples(&samples)
+    }
+
+    /// Pads the `data` chunk to an even length, writes the chunks added with
+    /// `add_chunk`, patches the RIFF and `data` sizes, and returns the
+    /// underlying writer. Sizes that do not fit in 32
+    /// bits turn the file into RF64, with the reserved `JUNK` chunk becoming `ds64`.
+    /// An AIFF file gets its `FORM`, `SSND` and frame count fields patched instead.
+    pub fn finalize(mut self) -> Result<W, WavError> {
+        if self.data_size % 2 == 1 {
+            self.inner.write_all(&[0])?;
+        }
+        let aiff = self.spec.container == Container::Aiff;
+        for (id, data) in &self.trailing {
+            let size = data.len() as u32;
+            self.inner.write_all(&id.to_be_bytes())?;
+            self.inner.write_all(&if aiff { size.to_be_bytes() } else { size.to_le_bytes() })?;
+            self.inner.write_all(data)?;
+            if data.len() % 2 == 1 {
+                self.inner.write_all(&[0])?;
+            }
+        }
+        let end = self.inner.stream_position()?;
+        let riff_size = end - 8;
+        if aiff {
+            if riff_size > u32::MAX as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "AIFF file larger than 4 GiB").into());
+            }
+            let frames = self.data_size / self.spec.block_align() as u64;
+            self.inner.seek(SeekFrom::Start(4))?;
+            self.inner.write_all(&(riff_size as u32).to_be_bytes())?;
+            self.inner.seek(SeekFrom::Start(self.frames_offset))?;
+            self.inner.write_all(&(frames as u32).to_be_bytes())?;
+            self.inner.seek(SeekFrom::Start(self.data_size_offset))?;
+            self.inner.write_all(&(self.data_size as u32 + 8).to_be_bytes())?;
+        } else if riff_size > u32::MAX as u64 {
+            let sample_count = self.data_size / self.spec.block_align() as u64;
+            self.inner.seek(SeekFrom::Start(0))?;
+            self.inner.write_all(b"RF64")?;
+            self.inner.write_all(&u32::MAX.to_le_bytes())?;
+            self.inner.seek(SeekFrom::Start(12))?;
+            self.inner.write_all(b"ds64")?;
+            self.inner.write_all(&(DS64_SIZE as u32).to_le_bytes())?;
+            self.inner.write_all(&riff_size.to_le_bytes())?;
+            self.inner.write_all(&self.data_size.to_le_bytes())?;
+            self.inner.write_all(&sample_count.to_le_bytes())?;
+            self.inner.write_all(&0u32.to_le_bytes())?;
+            self.inner.seek(SeekFrom::Start(self.data_size_offset))?;
+            self.inner.write_all(&u32::MAX.to_le_bytes())?;
+        } else {
+            self.inner.seek(SeekFrom::Start(4))?;
+            self.inner.write_all(&(riff_size as u32).to_le_bytes())?;
+            self.inner.seek(SeekFrom::Start(self.data_size_offset))?;
+            self.inner.write_all(&(self.data_size as u32).to_le_bytes())?;
+        }
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Writes a whole file in one call.
+pub fn write(path: &str, spec: WavSpec, samples: &Samples) -> Result<(), WavError> {
+    let mut writer = WavWriter::create(path, spec)?;
+    writer.write_samples(samples)?;
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::riff;
+    use crate::samples::Buffer;
+
+    fn spec(format: AudioFormat, channels: u16, bits_per_sample: u16, container: Container) -> WavSpec {
+        WavSpec { format, channels, sample_rate: 44100, bits_per_sample, container }
+    }
+
+    /// Writes `samples` and `chunks` to memory and returns the file.
+    fn write_file(spec: WavSpec, samples: &Samples, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_samples(samples).unwrap();
+        for (id, data) in chunks {
+            writer.add_chunk(riff::fourcc(id), data.to_vec());
+        }
+        writer.finalize().unwrap().into_inner()
+    }
+
+    /// Keeps the first bytes written and only counts the rest, to write
+    /// files larger than 4 GiB without holding them.
+    struct Sparse {
+        head: Vec<u8>,
+        position: u64,
+        len: u64,
+    }
+
+    impl Write for Sparse {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let start = self.position as usize;
+            if start < self.head.len() {
+                let end = (start + buf.len()).min(self.head.len());
+                self.head[start..end].copy_from_slice(&buf[..end - start]);
+            }
+            self.position += buf.len() as u64;
+            self.len = self.len.max(self.position);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Sparse {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.position = match pos {
+                SeekFrom::Start(offset) => offset,
+                SeekFrom::End(offset) => self.len.saturating_add_signed(offset),
+                SeekFrom::Current(offset) => self.position.saturating_add_signed(offset),
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn wave_sizes_are_patched_on_finalize() {
+        let samples = Samples::I16(Buffer::new(2, vec![0, 1, -1, 1000, i16::MIN, i16::MAX]));
+        let bytes = write_file(spec(AudioFormat::Pcm, 2, 16, Container::Wave), &samples, &[(b"LIST", b"INFOtest")]);
+        let header = WAVHeader::parse(&bytes).unwrap();
+        assert_eq!(header.chunk_size, bytes.len() as u64 - 8);
+        assert_eq!(header.sub_chunk_2_size, 12);
+        assert_eq!((header.block_align, header.byte_rate), (4, 176400));
+        assert_eq!(samples::decode(&header, &bytes).unwrap(), samples);
+
+        let chunks = riff::walk_chunks(&bytes);
+        let ids: Vec<String> = chunks.iter().map(|chunk| riff::fourcc_str(chunk.id)).collect();
+        assert_eq!(ids, ["JUNK", "fmt ", "data", "LIST"]);
+        assert_eq!(chunks[0].size, DS64_SIZE as u64);
+    }
+
+    #[test]
+    fn odd_data_is_padded_before_trailing_chunks() {
+        let samples = Samples::I16(Buffer::new(1, vec![0, 256, -256]));
+        let bytes = write_file(spec(AudioFormat::Pcm, 1, 8, Container::Wave), &samples, &[(b"bext", b"x")]);
+        let header = WAVHeader::parse(&bytes).unwrap();
+        assert_eq!(header.sub_chunk_2_size, 3);
+        assert_eq!(samples::decode(&header, &bytes).unwrap(), samples);
+        let bext = riff::walk_chunks(&bytes).into_iter().last().unwrap();
+        assert_eq!((riff::fourcc_str(bext.id), bext.offset as u64, bext.size), ("bext".to_string(), header.data_offset as u64 + 4, 1));
+        assert_eq!(bytes.len() % 2, 0);
+    }
+
+    #[test]
+    fn wave_past_4_gib_becomes_rf64() {
+        let sink = Sparse { head: vec![0; 128], position: 0, len: 0 };
+        let mut writer = WavWriter::new(sink, spec(AudioFormat::Pcm, 2, 16, Container::Wave)).unwrap();
+        let block = vec![0; 1 << 20];
+        for _ in 0..4097 {
+            writer.write_bytes(&block).unwrap();
+        }
+        let sink = writer.finalize().unwrap();
+        let data_size = 4097u64 << 20;
+
+        let ds64 = riff::read_ds64(&sink.head).unwrap();
+        assert_eq!(&sink.head[0..8], b"RF64\xFF\xFF\xFF\xFF");
+        assert_eq!((ds64.riff_size, ds64.data_size, ds64.sample_count), (sink.len - 8, data_size, data_size / 4));
+        let header = WAVHeader::parse(&sink.head).unwrap();
+        assert_eq!(header.sub_chunk_2_size, data_size);
+        assert_eq!(&sink.head[header.data_offset - 4..header.data_offset], b"\xFF\xFF\xFF\xFF");
+    }
+
+    #[test]
+    fn aiff_sizes_and_sample_order() {
+        let samples = Samples::I16(Buffer::new(2, vec![0x0102, -2, 300, -300]));
+        let bytes = write_file(spec(AudioFormat::Pcm, 2, 16, Container::Aiff), &samples, &[]);
+        assert_eq!(&bytes[0..4], b"FORM");
+        assert_eq!(&bytes[8..12], b"AIFF");
+        let header = WAVHeader::parse(&bytes).unwrap();
+        assert!(header.is_aiff());
+        assert_eq!(header.chunk_size, bytes.len() as u64 - 8);
+        assert_eq!((header.sub_chunk_2_size, header.sample_count), (8, Some(2)));
+        assert_eq!(header.sample_rate, 44100);
+        // big-endian samples after the 8-byte SSND offset and block size
+        assert_eq!(&bytes[header.data_offset..header.data_offset + 2], [0x01, 0x02]);
+        assert_eq!(samples::decode(&header, &bytes).unwrap(), samples);
+    }
+}